@@ -0,0 +1,100 @@
+use anyhow::Result;
+
+use crate::data::{Streamers, VideoData, VideoMetadata};
+use crate::error::DownloaderError;
+use crate::get_not_downloaded_videos_from_db;
+use crate::prelude::*;
+use crate::store::Store;
+
+/// All videos the next run of the pipeline would back up
+pub async fn pending_videos(store: &dyn Store) -> Result<Vec<VideoData>> {
+    let mut videos = vec![];
+    for video in get_not_downloaded_videos_from_db(store).await? {
+        if let Some(video) = video.await? {
+            videos.push(video);
+        }
+    }
+    Ok(videos)
+}
+
+/// Remove the error of a failed video so the next run tries it again,
+/// with all of its retries.
+pub async fn reset_video_error(store: &dyn Store, video_id: i64) -> Result<VideoMetadata> {
+    let mut metadata = store
+        .get_video_metadata(video_id)
+        .await?
+        .ok_or_else(|| DownloaderError::NotFound(format!("video {}", video_id)))?;
+    info!(
+        "Resetting the error of video {}: {:?}",
+        video_id, metadata.error
+    );
+    metadata.error = None;
+    metadata.error_code = None;
+    metadata.error_transient = None;
+    metadata.next_attempt_at = None;
+    metadata.retry_count = None;
+    store.save_video_metadata(&metadata).await?;
+    Ok(metadata)
+}
+
+/// Add a streamer and watch it, an existing streamer is watched again and
+/// keeps the settings that are not given.
+pub async fn add_streamer(
+    store: &dyn Store,
+    login: &str,
+    youtube_user: Option<String>,
+    public_videos: Option<bool>,
+) -> Result<Streamers> {
+    let login = login.to_lowercase();
+    let mut streamer = store
+        .get_streamer(&login)
+        .await?
+        .unwrap_or_else(|| Streamers {
+            login: login.clone(),
+            display_name: Some(login.clone()),
+            ..Default::default()
+        });
+    streamer.watched = Some(true);
+    if youtube_user.is_some() {
+        streamer.youtube_user = youtube_user;
+    }
+    if public_videos.is_some() {
+        streamer.public_videos_default = public_videos;
+    }
+    info!("Adding streamer: {}", login);
+    store.upsert_streamer(&streamer).await?;
+    Ok(streamer)
+}
+
+/// Start or stop looking for new videos of the streamer, the videos that are
+/// already in the store are backed up either way.
+pub async fn set_streamer_watched(store: &dyn Store, login: &str, watched: bool) -> Result<()> {
+    let login = login.to_lowercase();
+    let mut streamer = store
+        .get_streamer(&login)
+        .await?
+        .ok_or_else(|| DownloaderError::NotFound(format!("streamer {}", login)))?;
+    info!("Setting watched of streamer {} to {}", login, watched);
+    streamer.watched = Some(watched);
+    store.upsert_streamer(&streamer).await
+}
+
+/// Delete the streamer and its destinations, its videos are kept.
+///
+/// Not every store can delete rows (bigquery), there the streamer can only be
+/// unwatched.
+pub async fn remove_streamer(store: &dyn Store, login: &str) -> Result<()> {
+    let login = login.to_lowercase();
+    if !store.can_delete_streamers() {
+        return Err(DownloaderError::Config(format!(
+            "this store can not delete streamers, unwatch {} instead (`streamer unwatch {}`)",
+            login, login
+        ))
+        .into());
+    }
+    info!("Removing streamer: {}", login);
+    if !store.delete_streamer(&login).await? {
+        return Err(DownloaderError::NotFound(format!("streamer {}", login)).into());
+    }
+    Ok(())
+}
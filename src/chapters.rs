@@ -0,0 +1,72 @@
+use std::path::{Path, PathBuf};
+
+use anyhow::{Context, Result};
+use serde::{Deserialize, Serialize};
+
+use crate::prelude::*;
+
+/// A chapter of a video, like a game of a variety stream
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Chapter {
+    /// when the chapter starts, in seconds from the start of the video
+    pub start: f64,
+    pub title: String,
+}
+
+/// The file next to a video that holds its chapters.
+///
+/// Sources that know the chapters of a video write it when downloading, it can
+/// also be put there by hand. It stays after the split removed the video, so
+/// the chapters of the parts can still be looked up when they are uploaded.
+pub fn sidecar_path(video_file_path: &Path) -> PathBuf {
+    let file_stem = video_file_path
+        .file_stem()
+        .map(|s| s.to_string_lossy().to_string())
+        .unwrap_or_default();
+    video_file_path.with_file_name(format!("{}.chapters.json", file_stem))
+}
+
+/// the chapters in the sidecar of the video, `None` if there is no sidecar
+pub async fn read_sidecar(video_file_path: &Path) -> Result<Option<Vec<Chapter>>> {
+    let path = sidecar_path(video_file_path);
+    if !path.exists() {
+        return Ok(None);
+    }
+    let json = tokio::fs::read_to_string(&path)
+        .await
+        .with_context(|| format!("could not read the chapters from {}", path.display()))?;
+    let mut chapters: Vec<Chapter> = serde_json::from_str(&json)
+        .with_context(|| format!("invalid chapters in {}", path.display()))?;
+    chapters.sort_by(|a, b| a.start.total_cmp(&b.start));
+    Ok(Some(chapters))
+}
+
+pub async fn write_sidecar(video_file_path: &Path, chapters: &[Chapter]) -> Result<()> {
+    let path = sidecar_path(video_file_path);
+    debug!("Writing {} chapters to {}", chapters.len(), path.display());
+    tokio::fs::write(&path, serde_json::to_string_pretty(chapters)?)
+        .await
+        .with_context(|| format!("could not write the chapters to {}", path.display()))
+}
+
+pub async fn remove_sidecar(video_file_path: &Path) -> Result<()> {
+    let path = sidecar_path(video_file_path);
+    if path.exists() {
+        tokio::fs::remove_file(&path).await?;
+    }
+    Ok(())
+}
+
+/// the chapter that is running at that time
+pub fn chapter_at(chapters: &[Chapter], seconds: f64) -> Option<&Chapter> {
+    chapters.iter().rev().find(|c| c.start <= seconds)
+}
+
+/// the times at which one chapter ends and the next starts
+pub fn boundaries(chapters: &[Chapter]) -> Vec<f64> {
+    chapters
+        .iter()
+        .map(|c| c.start)
+        .filter(|start| *start > 0.0)
+        .collect()
+}
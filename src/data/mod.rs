@@ -43,6 +43,20 @@ pub struct Videos {
     pub video_type: Option<String>,
     pub duration: Option<i64>,
     pub thumbnail_url: Option<String>,
+    /// name of the [VideoSource](crate::source::VideoSource) the video came from
+    ///
+    /// `None` for videos that were added before there were multiple sources,
+    /// those are all from twitch
+    pub source: Option<String>,
+}
+
+impl Videos {
+    /// the name of the source this video came from
+    pub fn source_name(&self) -> &str {
+        self.source
+            .as_deref()
+            .unwrap_or(crate::source::twitch::TWITCH_SOURCE_NAME)
+    }
 }
 
 #[derive(BigDataTableDerive, Debug, Default, Clone)]
@@ -56,14 +70,285 @@ pub struct VideoMetadata {
 
     pub backed_up: Option<bool>,
     pub total_clips_amount: Option<i64>,
+    /// not used anymore, the state of every part is in [VideoParts]
     pub parts_backed_up_id: Option<i64>,
     pub parts_size: Option<i64>,
     pub error: Option<String>,
     pub download_playlist_url: Option<String>,
     pub youtube_playlist_url: Option<String>,
+    /// one of [VideoStage], see [VideoMetadata::stage]
+    pub stage: Option<String>,
+    pub stage_updated_at: Option<DateTime<Utc>>,
+    /// the file the video was downloaded to, the parts are split next to it
+    pub download_path: Option<String>,
+    /// how often the backup of this video failed
+    pub retry_count: Option<i64>,
+    /// when a video with a transient error is tried again
+    pub next_attempt_at: Option<DateTime<Utc>>,
+    /// if the [error](VideoMetadata::error) is expected to go away by itself,
+    /// see [classify_error](crate::retry::classify_error)
+    pub error_transient: Option<bool>,
+    /// the [code](crate::error::DownloaderError::code) of the error, the
+    /// message is in [error](VideoMetadata::error)
+    pub error_code: Option<String>,
+}
+
+impl VideoMetadata {
+    /// the stage the backup of this video is in
+    ///
+    /// videos without a stage are from before the stages existed, their
+    /// stage is guessed from `backed_up` and `error`
+    pub fn stage(&self) -> VideoStage {
+        if let Some(stage) = self.stage.as_deref().and_then(VideoStage::parse) {
+            return stage;
+        }
+        if self.backed_up == Some(true) {
+            VideoStage::CleanedUp
+        } else if self.error.is_some() {
+            VideoStage::Failed
+        } else {
+            VideoStage::Discovered
+        }
+    }
+
+    /// if the video should be backed up now: it is not backed up yet and
+    /// either did not fail or failed with a transient error whose wait is over
+    pub fn is_pending(&self, now: DateTime<Utc>) -> bool {
+        if self.backed_up != Some(false) {
+            return false;
+        }
+        if self.error.is_none() {
+            return true;
+        }
+        self.error_transient == Some(true)
+            && match self.next_attempt_at {
+                Some(next_attempt_at) => next_attempt_at <= now,
+                None => true,
+            }
+    }
+
+    pub fn set_stage(&mut self, stage: VideoStage) {
+        self.stage = Some(stage.as_str().to_string());
+        self.stage_updated_at = Some(Utc::now());
+    }
+}
+
+/// The stages a video goes through while it is backed up
+///
+/// Every stage is saved once it is reached, so the backup can continue from
+/// the last one after a restart.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum VideoStage {
+    /// the video is known but nothing was done with it yet
+    Discovered,
+    /// the download was started but did not finish
+    Downloading,
+    /// the whole video is at [VideoMetadata::download_path]
+    Downloaded,
+    /// the video is split into parts next to the downloaded file
+    Split,
+    /// the upload of the parts was started but did not finish
+    Uploading,
+    /// all parts are at all required destinations
+    Uploaded,
+    /// the local files are removed, nothing left to do
+    CleanedUp,
+    /// the last attempt failed, see [VideoMetadata::error]
+    Failed,
+}
+
+impl VideoStage {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            VideoStage::Discovered => "discovered",
+            VideoStage::Downloading => "downloading",
+            VideoStage::Downloaded => "downloaded",
+            VideoStage::Split => "split",
+            VideoStage::Uploading => "uploading",
+            VideoStage::Uploaded => "uploaded",
+            VideoStage::CleanedUp => "cleaned_up",
+            VideoStage::Failed => "failed",
+        }
+    }
+
+    pub fn parse(stage: &str) -> Option<Self> {
+        match stage {
+            "discovered" => Some(VideoStage::Discovered),
+            "downloading" => Some(VideoStage::Downloading),
+            "downloaded" => Some(VideoStage::Downloaded),
+            "split" => Some(VideoStage::Split),
+            "uploading" => Some(VideoStage::Uploading),
+            "uploaded" => Some(VideoStage::Uploaded),
+            "cleaned_up" => Some(VideoStage::CleanedUp),
+            "failed" => Some(VideoStage::Failed),
+            _ => None,
+        }
+    }
+}
+
+/// A part of a video and its upload state at one destination
+#[derive(BigDataTableDerive, Debug, Default, Clone)]
+#[db_name("video_parts")]
+pub struct VideoParts {
+    /// `{video_id}_{destination}_{part}`, see [VideoParts::create_id]
+    #[primary_key]
+    #[required]
+    pub id: String,
+    #[client]
+    pub client: BigqueryClient,
+
+    pub video_id: Option<i64>,
+    /// name of the [UploadDestination](crate::destination::UploadDestination)
+    pub destination: Option<String>,
+    /// the number of the part, starting at 1
+    pub part: Option<i64>,
+    /// the id of the part at the destination (youtube video id, object key, ...)
+    pub remote_id: Option<String>,
+    pub url: Option<String>,
+    pub uploaded_at: Option<DateTime<Utc>>,
+    /// duration of the part in seconds
+    pub duration: Option<i64>,
+    /// sha256 of the part file (hex)
+    pub file_hash: Option<String>,
+    /// one of [PartStatus], see [VideoParts::status]
+    pub status: Option<String>,
+}
+
+impl VideoParts {
+    pub fn create_id(video_id: i64, destination: &str, part: i64) -> String {
+        format!("{}_{}_{}", video_id, destination, part)
+    }
+
+    pub fn new(video_id: i64, destination: &str, part: i64) -> Self {
+        Self {
+            id: Self::create_id(video_id, destination, part),
+            video_id: Some(video_id),
+            destination: Some(destination.to_string()),
+            part: Some(part),
+            status: Some(PartStatus::Pending.as_str().to_string()),
+            ..Default::default()
+        }
+    }
+
+    /// the parsed status, unknown or missing values count as [PartStatus::Pending]
+    pub fn status(&self) -> PartStatus {
+        self.status
+            .as_deref()
+            .and_then(PartStatus::parse)
+            .unwrap_or(PartStatus::Pending)
+    }
+
+    pub fn set_status(&mut self, status: PartStatus) {
+        self.status = Some(status.as_str().to_string());
+    }
+}
+
+/// How far the upload of a [VideoParts] got
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum PartStatus {
+    /// the upload was not started or did not finish
+    Pending,
+    /// the part is at the destination but not in the collection yet
+    Uploaded,
+    /// the part is at the destination and in the collection, nothing left to do
+    InCollection,
+    /// the last upload attempt failed
+    Failed,
+}
+
+impl PartStatus {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            PartStatus::Pending => "pending",
+            PartStatus::Uploaded => "uploaded",
+            PartStatus::InCollection => "in_collection",
+            PartStatus::Failed => "failed",
+        }
+    }
+
+    pub fn parse(status: &str) -> Option<Self> {
+        match status {
+            "pending" => Some(PartStatus::Pending),
+            "uploaded" => Some(PartStatus::Uploaded),
+            "in_collection" => Some(PartStatus::InCollection),
+            "failed" => Some(PartStatus::Failed),
+            _ => None,
+        }
+    }
+}
+
+/// A destination the videos of a streamer are uploaded to
+#[derive(BigDataTableDerive, Debug, Default, Clone)]
+#[db_name("streamer_destinations")]
+pub struct StreamerDestinations {
+    /// `{streamer_login}_{destination}`, see [StreamerDestinations::create_id]
+    #[primary_key]
+    #[required]
+    pub id: String,
+    #[client]
+    pub client: BigqueryClient,
+
+    pub streamer_login: Option<String>,
+    /// name of the [UploadDestination](crate::destination::UploadDestination)
+    pub destination: Option<String>,
+    /// if a video is only backed up once it was uploaded to this destination
+    pub required: Option<bool>,
+}
+
+impl StreamerDestinations {
+    pub fn create_id(streamer_login: &str, destination: &str) -> String {
+        format!("{}_{}", streamer_login, destination)
+    }
+
+    pub fn new(streamer_login: &str, destination: &str, required: bool) -> Self {
+        Self {
+            id: Self::create_id(streamer_login, destination),
+            streamer_login: Some(streamer_login.to_string()),
+            destination: Some(destination.to_string()),
+            required: Some(required),
+            ..Default::default()
+        }
+    }
+}
+
+/// The state of a video at one of its destinations
+#[derive(BigDataTableDerive, Debug, Default, Clone)]
+#[db_name("video_uploads")]
+pub struct VideoUploads {
+    /// `{video_id}_{destination}`, see [VideoUploads::create_id]
+    #[primary_key]
+    #[required]
+    pub id: String,
+    #[client]
+    pub client: BigqueryClient,
+
+    pub video_id: Option<i64>,
+    /// name of the [UploadDestination](crate::destination::UploadDestination)
+    pub destination: Option<String>,
+    /// if all parts are uploaded to the destination
+    pub backed_up: Option<bool>,
+    pub error: Option<String>,
+    pub collection_url: Option<String>,
+    pub updated_at: Option<DateTime<Utc>>,
+}
+
+impl VideoUploads {
+    pub fn create_id(video_id: i64, destination: &str) -> String {
+        format!("{}_{}", video_id, destination)
+    }
+
+    pub fn new(video_id: i64, destination: &str) -> Self {
+        Self {
+            id: Self::create_id(video_id, destination),
+            video_id: Some(video_id),
+            destination: Some(destination.to_string()),
+            backed_up: Some(false),
+            ..Default::default()
+        }
+    }
 }
 
-#[derive(Debug, Default)]
+#[derive(Debug, Default, Clone)]
 pub struct VideoData {
     pub video: Videos,
     pub metadata: VideoMetadata,
@@ -71,11 +356,11 @@ pub struct VideoData {
 }
 
 impl VideoData {
-    pub fn from_twitch_video(video: &twitch_data::Video, client: BigqueryClient) -> Result<Self> {
+    pub fn from_twitch_video(video: &twitch_data::Video) -> Result<Self> {
         Ok(Self {
             video: Videos {
                 video_id: video.id.parse::<i64>()?,
-                client: client.clone(),
+                client: Default::default(),
                 title: Some(video.title.clone()),
                 description: Some(video.description.clone()),
                 bool_test: Some(true),
@@ -88,11 +373,13 @@ impl VideoData {
                 video_type: Some("archive".to_string()),
                 duration: Some(video.duration),
                 thumbnail_url: Some(video.thumbnail_url.clone()),
+                source: Some(crate::source::twitch::TWITCH_SOURCE_NAME.to_string()),
             },
             metadata: VideoMetadata {
                 video_id: video.id.parse::<i64>()?,
-                client: client.clone(),
+                client: Default::default(),
                 backed_up: Some(false),
+                stage: Some(VideoStage::Discovered.as_str().to_string()),
                 ..Default::default()
             },
             streamer: Streamers {
@@ -0,0 +1,219 @@
+use std::path::{Component, Path, PathBuf};
+use std::sync::Mutex;
+
+use anyhow::{anyhow, Context, Result};
+use async_trait::async_trait;
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+
+use crate::destination::{Collection, PartInfo, UploadDestination, UploadedPart};
+use crate::prelude::*;
+
+pub const LOCAL_ARCHIVE_DESTINATION_NAME: &str = "local_archive";
+
+/// The layout that is used if none is configured
+pub const DEFAULT_ARCHIVE_LAYOUT: &str = "{streamer_login}/{yyyy}/{mm}/{video_id}/{part}.mp4";
+
+/// The metadata that is written next to every archived part (`01.mp4` => `01.json`)
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct ArchivedPartMetadata {
+    pub video_id: i64,
+    pub streamer_login: String,
+    pub title: String,
+    pub description: String,
+    pub tags: Vec<String>,
+    pub created_at: Option<DateTime<Utc>>,
+    pub part: usize,
+    pub total_parts: usize,
+    /// the title of the collection the part belongs to, set once it was added to one
+    #[serde(default)]
+    pub collection: Option<String>,
+}
+
+/// [UploadDestination] that copies the parts into a directory (a NAS for example).
+///
+/// The path of every part is built from a layout relative to the archive root.
+/// These placeholders are replaced:
+/// - `{streamer_login}`
+/// - `{yyyy}`, `{mm}`, `{dd}`: the date the stream happened
+/// - `{video_id}`
+/// - `{part}`: the number of the part with two digits, added as `_{part}` to
+///   the end of the file name if the layout does not contain it
+pub struct LocalArchiveDestination {
+    root: PathBuf,
+    layout: String,
+    /// the temporary files of the copies that are running
+    partial_files: Mutex<Vec<PathBuf>>,
+}
+
+impl LocalArchiveDestination {
+    pub fn new<P: Into<PathBuf>>(root: P) -> Self {
+        Self {
+            root: root.into(),
+            layout: DEFAULT_ARCHIVE_LAYOUT.to_string(),
+            partial_files: Mutex::new(vec![]),
+        }
+    }
+
+    pub fn with_layout<S: Into<String>>(mut self, layout: S) -> Self {
+        let layout = layout.into();
+        // without the part all parts of a video would overwrite each other
+        self.layout = if layout.contains("{part}") {
+            layout
+        } else {
+            let file_name_start = layout.rfind('/').map_or(0, |i| i + 1);
+            let with_part = match layout[file_name_start..].rfind('.') {
+                Some(i) if i > 0 => {
+                    let extension_start = file_name_start + i;
+                    format!(
+                        "{}_{{part}}{}",
+                        &layout[..extension_start],
+                        &layout[extension_start..]
+                    )
+                }
+                _ => format!("{}_{{part}}", layout),
+            };
+            warn!(
+                "the archive layout has no {{part}}, using this one instead: {}",
+                with_part
+            );
+            with_part
+        };
+        self
+    }
+
+    /// get the path the part is archived at
+    pub fn part_path(&self, part: &PartInfo) -> Result<PathBuf> {
+        let created_at = part
+            .created_at
+            .ok_or_else(|| anyhow!("video {} has no created_at time", part.video_id))?;
+        let relative = self
+            .layout
+            .replace("{streamer_login}", &part.streamer_login.to_lowercase())
+            .replace("{yyyy}", &created_at.format("%Y").to_string())
+            .replace("{mm}", &created_at.format("%m").to_string())
+            .replace("{dd}", &created_at.format("%d").to_string())
+            .replace("{video_id}", &part.video_id.to_string())
+            .replace("{part}", &format!("{:0>2}", part.part));
+        let relative = PathBuf::from(relative);
+        // the values come from outside, so they must not be able to leave the archive
+        if relative
+            .components()
+            .any(|c| !matches!(c, Component::Normal(_)))
+        {
+            return Err(anyhow!(
+                "archive path must be relative and stay inside the archive: {}",
+                relative.display()
+            ));
+        }
+        Ok(self.root.join(relative))
+    }
+
+    fn metadata_path(part_path: &Path) -> PathBuf {
+        part_path.with_extension("json")
+    }
+
+    async fn write_metadata(path: &Path, metadata: &ArchivedPartMetadata) -> Result<()> {
+        let content = serde_json::to_string_pretty(metadata)?;
+        tokio::fs::write(path, content)
+            .await
+            .with_context(|| format!("could not write archive metadata {}", path.display()))
+    }
+}
+
+#[async_trait(?Send)]
+impl UploadDestination for LocalArchiveDestination {
+    fn name(&self) -> &str {
+        LOCAL_ARCHIVE_DESTINATION_NAME
+    }
+
+    async fn upload_part(&self, path: &Path, part: &PartInfo) -> Result<UploadedPart> {
+        let target = self.part_path(part)?;
+        let folder = target
+            .parent()
+            .ok_or_else(|| anyhow!("archive path has no parent: {}", target.display()))?;
+        tokio::fs::create_dir_all(folder)
+            .await
+            .with_context(|| format!("could not create archive folder {}", folder.display()))?;
+
+        // copy to a temporary name first, so a part is either complete or not there
+        let partial = target.with_extension("partial");
+        trace!("copying {} to {}", path.display(), partial.display());
+        self.partial_files
+            .lock()
+            .map_err(|_| anyhow!("the partial files are poisoned"))?
+            .push(partial.clone());
+        let result = tokio::fs::copy(path, &partial)
+            .await
+            .with_context(|| format!("could not copy {} into the archive", path.display()));
+        let result = match result {
+            Ok(_) => tokio::fs::rename(&partial, &target)
+                .await
+                .map_err(Into::into),
+            Err(e) => Err(e),
+        };
+        if let Ok(mut partial_files) = self.partial_files.lock() {
+            partial_files.retain(|p| p != &partial);
+        }
+        result?;
+
+        let metadata = ArchivedPartMetadata {
+            video_id: part.video_id,
+            streamer_login: part.streamer_login.clone(),
+            title: part.title.clone(),
+            description: part.description.clone(),
+            tags: part.tags.clone(),
+            created_at: part.created_at,
+            part: part.part,
+            total_parts: part.total_parts,
+            collection: None,
+        };
+        Self::write_metadata(&Self::metadata_path(&target), &metadata).await?;
+
+        let absolute = std::fs::canonicalize(&target)?;
+        Ok(UploadedPart {
+            id: target.to_string_lossy().to_string(),
+            url: format!("file://{}", absolute.display()),
+        })
+    }
+
+    async fn find_or_create_collection(&self, title: &str, _public: bool) -> Result<Collection> {
+        // the folders of the layout already group the parts, so there is nothing to create
+        let absolute = std::fs::canonicalize(&self.root)
+            .with_context(|| format!("archive root does not exist: {}", self.root.display()))?;
+        Ok(Collection {
+            id: title.to_string(),
+            url: format!("file://{}", absolute.display()),
+        })
+    }
+
+    async fn add_part_to_collection(
+        &self,
+        part: &UploadedPart,
+        collection: &Collection,
+    ) -> Result<()> {
+        let path = Self::metadata_path(Path::new(&part.id));
+        let content = tokio::fs::read_to_string(&path)
+            .await
+            .with_context(|| format!("could not read archive metadata {}", path.display()))?;
+        let mut metadata: ArchivedPartMetadata = serde_json::from_str(&content)?;
+        metadata.collection = Some(collection.id.clone());
+        Self::write_metadata(&path, &metadata).await
+    }
+
+    async fn cancel_uploads(&self) -> Result<()> {
+        let partial_files: Vec<PathBuf> = self
+            .partial_files
+            .lock()
+            .map_err(|_| anyhow!("the partial files are poisoned"))?
+            .drain(..)
+            .collect();
+        for partial in partial_files {
+            info!("Removing the interrupted copy {}", partial.display());
+            if let Err(e) = tokio::fs::remove_file(&partial).await {
+                warn!("could not remove {}: {}", partial.display(), e);
+            }
+        }
+        Ok(())
+    }
+}
@@ -0,0 +1,173 @@
+use std::collections::HashMap;
+use std::path::Path;
+use std::rc::Rc;
+
+use anyhow::{anyhow, Result};
+use async_trait::async_trait;
+use chrono::{DateTime, Utc};
+
+pub use local::LocalArchiveDestination;
+pub use s3::{S3Config, S3Destination};
+pub use youtube::YoutubeDestination;
+
+use crate::data::{StreamerDestinations, Streamers};
+use crate::destination::youtube::YOUTUBE_DESTINATION_NAME;
+
+pub mod local;
+pub mod s3;
+pub mod youtube;
+
+/// Everything a destination needs to know about a single part of a video
+#[derive(Debug, Clone, PartialEq, Eq, Default)]
+pub struct PartInfo {
+    pub title: String,
+    pub description: String,
+    pub tags: Vec<String>,
+    /// if the part should be publicly visible
+    pub public: bool,
+    pub video_id: i64,
+    pub streamer_login: String,
+    /// when the stream of the video happened
+    pub created_at: Option<DateTime<Utc>>,
+    /// the number of this part, starting at 1
+    pub part: usize,
+    pub total_parts: usize,
+    /// duration of the part in seconds, if known
+    pub duration: Option<i64>,
+    /// the name of the [chapter](crate::chapters::Chapter) the part starts in
+    pub chapter: Option<String>,
+}
+
+/// A part that was uploaded to a destination
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct UploadedPart {
+    /// the id of the part at the destination
+    pub id: String,
+    /// a url that points to the part for as long as it exists at the destination
+    pub url: String,
+}
+
+/// A collection of parts at a destination (a playlist on youtube, ...)
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Collection {
+    /// the id of the collection at the destination
+    pub id: String,
+    /// a url that points to the collection for as long as it exists at the destination
+    pub url: String,
+}
+
+/// Somewhere backups are uploaded to (youtube, ...).
+///
+/// Every part of a video is uploaded on its own and then added to a collection
+/// that groups all parts of the video.
+#[async_trait(?Send)]
+pub trait UploadDestination {
+    /// The name of this destination, used in logs
+    fn name(&self) -> &str;
+    /// upload a single part of a video
+    async fn upload_part(&self, path: &Path, part: &PartInfo) -> Result<UploadedPart>;
+    /// get the collection with the title or create it if it does not exist yet
+    async fn find_or_create_collection(&self, title: &str, public: bool) -> Result<Collection>;
+    /// add an uploaded part to a collection
+    async fn add_part_to_collection(
+        &self,
+        part: &UploadedPart,
+        collection: &Collection,
+    ) -> Result<()>;
+    /// Clean up the uploads that were started but not finished.
+    ///
+    /// Called when the shutdown deadline passed and the running uploads were
+    /// dropped, so nothing half uploaded is left at the destination.
+    async fn cancel_uploads(&self) -> Result<()> {
+        Ok(())
+    }
+}
+
+/// A destination a video has to be uploaded to
+#[derive(Clone)]
+pub struct VideoDestination {
+    /// the name of the destination, as it is stored in [StreamerDestinations]
+    pub name: String,
+    /// if a video is only backed up once it was uploaded to this destination
+    pub required: bool,
+    pub destination: Rc<dyn UploadDestination>,
+}
+
+/// All destinations the pipeline can upload to.
+///
+/// Every destination is registered by its name, except for youtube where every
+/// account is its own destination (see [UploadDestinations::key_for]).
+#[derive(Default, Clone)]
+pub struct UploadDestinations {
+    destinations: HashMap<String, Rc<dyn UploadDestination>>,
+}
+
+impl UploadDestinations {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn insert<S: Into<String>>(&mut self, key: S, destination: Rc<dyn UploadDestination>) {
+        self.destinations.insert(key.into(), destination);
+    }
+
+    pub fn get(&self, key: &str) -> Option<Rc<dyn UploadDestination>> {
+        self.destinations.get(key).cloned()
+    }
+
+    pub fn contains(&self, key: &str) -> bool {
+        self.destinations.contains_key(key)
+    }
+
+    pub fn keys(&self) -> impl Iterator<Item = &String> {
+        self.destinations.keys()
+    }
+
+    /// the key the destination with the name is registered under for the streamer
+    pub fn key_for(name: &str, streamer: &Streamers) -> String {
+        match name {
+            YOUTUBE_DESTINATION_NAME => format!("{}:{}", name, youtube_user(streamer)),
+            _ => name.to_string(),
+        }
+    }
+
+    /// Get the destinations of a streamer.
+    ///
+    /// Fails if one of them is not available, so a video is never marked as
+    /// backed up without reaching all of its destinations.
+    pub fn for_streamer(
+        &self,
+        streamer: &Streamers,
+        streamer_destinations: &[StreamerDestinations],
+    ) -> Result<Vec<VideoDestination>> {
+        streamer_destinations
+            .iter()
+            .map(|streamer_destination| {
+                let name = streamer_destination.destination.clone().ok_or_else(|| {
+                    anyhow!("destination {} has no name", streamer_destination.id)
+                })?;
+                let key = Self::key_for(&name, streamer);
+                let destination = self.get(&key).ok_or_else(|| {
+                    anyhow!(
+                        "destination {} of streamer {} is not available",
+                        key,
+                        streamer.login
+                    )
+                })?;
+                Ok(VideoDestination {
+                    name,
+                    required: streamer_destination.required.unwrap_or(true),
+                    destination,
+                })
+            })
+            .collect()
+    }
+}
+
+/// the youtube account the videos of the streamer are uploaded to
+pub fn youtube_user(streamer: &Streamers) -> String {
+    streamer
+        .youtube_user
+        .clone()
+        .unwrap_or_else(|| "NopixelVODs".to_string())
+}
@@ -0,0 +1,442 @@
+use std::path::Path;
+use std::sync::Mutex;
+
+use anyhow::{anyhow, Context, Result};
+use async_trait::async_trait;
+use chrono::Utc;
+use reqwest::{Method, Url};
+use ring::{digest, hmac};
+use tokio::io::AsyncReadExt;
+
+use crate::destination::{Collection, PartInfo, UploadDestination, UploadedPart};
+use crate::prelude::*;
+
+pub const S3_DESTINATION_NAME: &str = "s3";
+
+/// S3 does not accept multipart chunks smaller than this (except for the last one)
+pub const MIN_CHUNK_SIZE: usize = 5 * 1024 * 1024;
+
+/// The sha256 of an empty body
+const EMPTY_PAYLOAD_HASH: &str = "e3b0c44298fc1c149afbf4c8996fb92427ae41e4649b934ca495991b7852b855";
+
+/// Everything that is needed to talk to a S3 compatible storage
+#[derive(Debug, Clone)]
+pub struct S3Config {
+    /// for example `https://s3.eu-central-1.amazonaws.com` or `http://localhost:9000` for MinIO
+    pub endpoint: String,
+    pub region: String,
+    pub bucket: String,
+    pub access_key: String,
+    pub secret_key: String,
+    /// prepended to every object key
+    pub key_prefix: String,
+    /// size of the chunks of the multipart upload in bytes
+    pub chunk_size: usize,
+}
+
+/// [UploadDestination] that uploads the parts to a S3 compatible object storage.
+///
+/// The objects are addressed path style (`{endpoint}/{bucket}/{key}`), which
+/// works with AWS as well as MinIO and most other compatible stores. Every part
+/// is uploaded with a multipart upload and gets the info about the video as
+/// object metadata (`x-amz-meta-*`).
+pub struct S3Destination {
+    config: S3Config,
+    endpoint: Url,
+    client: reqwest::Client,
+    /// the key and upload id of every multipart upload that is running
+    running_uploads: Mutex<Vec<(String, String)>>,
+}
+
+impl S3Destination {
+    pub fn new(config: S3Config) -> Result<Self> {
+        let mut endpoint = Url::parse(&config.endpoint)
+            .with_context(|| format!("invalid s3 endpoint: {}", config.endpoint))?;
+        if !endpoint.path().ends_with('/') {
+            endpoint.set_path(&format!("{}/", endpoint.path()));
+        }
+        if config.chunk_size < MIN_CHUNK_SIZE {
+            return Err(anyhow!(
+                "s3 chunk size must be at least {} bytes, got {}",
+                MIN_CHUNK_SIZE,
+                config.chunk_size
+            ));
+        }
+        Ok(Self {
+            config,
+            endpoint,
+            client: reqwest::Client::new(),
+            running_uploads: Mutex::new(vec![]),
+        })
+    }
+
+    /// get the key the part is stored under
+    pub fn object_key(&self, part: &PartInfo) -> String {
+        format!(
+            "{}{}/{}/{:0>2}.mp4",
+            self.config.key_prefix,
+            part.streamer_login.to_lowercase(),
+            part.video_id,
+            part.part
+        )
+    }
+
+    fn object_url(&self, key: &str) -> Result<Url> {
+        let path = format!(
+            "{}/{}",
+            uri_encode(&self.config.bucket, false),
+            uri_encode(key, false)
+        );
+        Ok(self.endpoint.join(&path)?)
+    }
+
+    /// send a signed request and return the response if it was successful
+    async fn send(
+        &self,
+        method: Method,
+        url: Url,
+        headers: Vec<(String, String)>,
+        body: Vec<u8>,
+    ) -> Result<reqwest::Response> {
+        let payload_hash = match body.is_empty() {
+            true => EMPTY_PAYLOAD_HASH.to_string(),
+            false => hex(digest::digest(&digest::SHA256, &body).as_ref()),
+        };
+        let amz_date = Utc::now().format("%Y%m%dT%H%M%SZ").to_string();
+        let mut headers = headers;
+        headers.push(("host".to_string(), host_header(&url)?));
+        headers.push(("x-amz-content-sha256".to_string(), payload_hash.clone()));
+        headers.push(("x-amz-date".to_string(), amz_date.clone()));
+        let authorization = sign_v4(
+            &self.config.access_key,
+            &self.config.secret_key,
+            &self.config.region,
+            method.as_str(),
+            &url,
+            &headers,
+            &payload_hash,
+            &amz_date,
+        );
+
+        let mut request = self.client.request(method.clone(), url.clone());
+        for (name, value) in headers.iter().filter(|(name, _)| name != "host") {
+            request = request.header(name, value);
+        }
+        let response = request
+            .header("authorization", authorization)
+            .body(body)
+            .send()
+            .await?;
+        let status = response.status();
+        if !status.is_success() {
+            let text = response.text().await.unwrap_or_default();
+            return Err(anyhow!(
+                "s3 request {} {} failed with {}: {}",
+                method,
+                url,
+                status,
+                text
+            ));
+        }
+        Ok(response)
+    }
+
+    async fn initiate_multipart_upload(&self, key: &str, part: &PartInfo) -> Result<String> {
+        let mut url = self.object_url(key)?;
+        url.set_query(Some("uploads"));
+        let mut headers = object_metadata(part);
+        headers.push(("content-type".to_string(), "video/mp4".to_string()));
+        let response = self.send(Method::POST, url, headers, vec![]).await?;
+        let body = response.text().await?;
+        xml_value(&body, "UploadId")
+            .ok_or_else(|| anyhow!("s3 did not return an upload id: {}", body))
+    }
+
+    async fn upload_chunk(
+        &self,
+        key: &str,
+        upload_id: &str,
+        chunk_number: usize,
+        chunk: Vec<u8>,
+    ) -> Result<String> {
+        let mut url = self.object_url(key)?;
+        url.set_query(Some(&format!(
+            "partNumber={}&uploadId={}",
+            chunk_number,
+            uri_encode(upload_id, true)
+        )));
+        let response = self.send(Method::PUT, url, vec![], chunk).await?;
+        let etag = response
+            .headers()
+            .get("etag")
+            .and_then(|e| e.to_str().ok())
+            .ok_or_else(|| anyhow!("s3 did not return an etag for chunk {}", chunk_number))?;
+        Ok(etag.to_string())
+    }
+
+    async fn complete_multipart_upload(
+        &self,
+        key: &str,
+        upload_id: &str,
+        etags: &[String],
+    ) -> Result<()> {
+        let mut url = self.object_url(key)?;
+        url.set_query(Some(&format!("uploadId={}", uri_encode(upload_id, true))));
+        let mut body = "<CompleteMultipartUpload>".to_string();
+        for (i, etag) in etags.iter().enumerate() {
+            body.push_str(&format!(
+                "<Part><PartNumber>{}</PartNumber><ETag>{}</ETag></Part>",
+                i + 1,
+                etag
+            ));
+        }
+        body.push_str("</CompleteMultipartUpload>");
+        let response = self
+            .send(Method::POST, url, vec![], body.into_bytes())
+            .await?;
+        // the request can fail even though the status is 200
+        let text = response.text().await?;
+        if text.contains("<Error>") {
+            return Err(anyhow!("s3 could not complete the upload: {}", text));
+        }
+        Ok(())
+    }
+
+    async fn abort_multipart_upload(&self, key: &str, upload_id: &str) -> Result<()> {
+        let mut url = self.object_url(key)?;
+        url.set_query(Some(&format!("uploadId={}", uri_encode(upload_id, true))));
+        self.send(Method::DELETE, url, vec![], vec![]).await?;
+        Ok(())
+    }
+
+    async fn upload_chunks(&self, path: &Path, key: &str, upload_id: &str) -> Result<()> {
+        let mut file = tokio::fs::File::open(path)
+            .await
+            .with_context(|| format!("could not open {}", path.display()))?;
+        let mut etags = vec![];
+        loop {
+            let mut chunk = Vec::with_capacity(self.config.chunk_size);
+            let read = (&mut file)
+                .take(self.config.chunk_size as u64)
+                .read_to_end(&mut chunk)
+                .await?;
+            // an empty file still needs one (empty) chunk
+            if read == 0 && !etags.is_empty() {
+                break;
+            }
+            trace!("uploading chunk {} of {}", etags.len() + 1, key);
+            let etag = self
+                .upload_chunk(key, upload_id, etags.len() + 1, chunk)
+                .await?;
+            etags.push(etag);
+            if read < self.config.chunk_size {
+                break;
+            }
+        }
+        self.complete_multipart_upload(key, upload_id, &etags).await
+    }
+}
+
+#[async_trait(?Send)]
+impl UploadDestination for S3Destination {
+    fn name(&self) -> &str {
+        S3_DESTINATION_NAME
+    }
+
+    async fn upload_part(&self, path: &Path, part: &PartInfo) -> Result<UploadedPart> {
+        let key = self.object_key(part);
+        info!("Uploading {} to s3: {}", path.display(), key);
+        let upload_id = self.initiate_multipart_upload(&key, part).await?;
+        self.running_uploads
+            .lock()
+            .map_err(|_| anyhow!("the running uploads are poisoned"))?
+            .push((key.clone(), upload_id.clone()));
+        let result = self.upload_chunks(path, &key, &upload_id).await;
+        if let Ok(mut running) = self.running_uploads.lock() {
+            running.retain(|(_, id)| id != &upload_id);
+        }
+        if let Err(e) = result {
+            // otherwise the chunks stay in the bucket (and cost money) until they expire
+            if let Err(abort_error) = self.abort_multipart_upload(&key, &upload_id).await {
+                warn!("could not abort the upload of {}: {}", key, abort_error);
+            }
+            return Err(e);
+        }
+        Ok(UploadedPart {
+            url: self.object_url(&key)?.to_string(),
+            id: key,
+        })
+    }
+
+    async fn find_or_create_collection(&self, title: &str, _public: bool) -> Result<Collection> {
+        // buckets have no collections, the keys already group the parts of a video
+        Ok(Collection {
+            id: title.to_string(),
+            url: self.object_url("")?.to_string(),
+        })
+    }
+
+    async fn add_part_to_collection(
+        &self,
+        _part: &UploadedPart,
+        _collection: &Collection,
+    ) -> Result<()> {
+        Ok(())
+    }
+
+    async fn cancel_uploads(&self) -> Result<()> {
+        let running: Vec<(String, String)> = self
+            .running_uploads
+            .lock()
+            .map_err(|_| anyhow!("the running uploads are poisoned"))?
+            .drain(..)
+            .collect();
+        for (key, upload_id) in running {
+            info!("Aborting the interrupted upload of {}", key);
+            if let Err(e) = self.abort_multipart_upload(&key, &upload_id).await {
+                warn!("could not abort the upload of {}: {}", key, e);
+            }
+        }
+        Ok(())
+    }
+}
+
+/// the `x-amz-meta-*` headers for a part.
+///
+/// Header values can only be ascii, so they are uri encoded.
+fn object_metadata(part: &PartInfo) -> Vec<(String, String)> {
+    let mut metadata = vec![
+        ("video-id", part.video_id.to_string()),
+        ("streamer-login", part.streamer_login.clone()),
+        ("title", part.title.clone()),
+        ("part", part.part.to_string()),
+        ("total-parts", part.total_parts.to_string()),
+    ];
+    if let Some(created_at) = part.created_at {
+        metadata.push(("created-at", created_at.to_rfc3339()));
+    }
+    metadata
+        .into_iter()
+        .map(|(name, value)| (format!("x-amz-meta-{}", name), uri_encode(&value, true)))
+        .collect()
+}
+
+fn host_header(url: &Url) -> Result<String> {
+    let host = url
+        .host_str()
+        .ok_or_else(|| anyhow!("s3 url has no host: {}", url))?;
+    Ok(match url.port() {
+        Some(port) => format!("{}:{}", host, port),
+        None => host.to_string(),
+    })
+}
+
+/// Create the `Authorization` header for a request with AWS signature version 4.
+///
+/// `headers` must contain every header that is sent (including `host` and
+/// `x-amz-date`), all of them are signed.
+#[allow(clippy::too_many_arguments)]
+pub fn sign_v4(
+    access_key: &str,
+    secret_key: &str,
+    region: &str,
+    method: &str,
+    url: &Url,
+    headers: &[(String, String)],
+    payload_hash: &str,
+    amz_date: &str,
+) -> String {
+    let mut headers: Vec<(String, String)> = headers
+        .iter()
+        .map(|(name, value)| (name.to_lowercase(), value.trim().to_string()))
+        .collect();
+    headers.sort();
+    let canonical_headers: String = headers
+        .iter()
+        .map(|(name, value)| format!("{}:{}\n", name, value))
+        .collect();
+    let signed_headers = headers
+        .iter()
+        .map(|(name, _)| name.as_str())
+        .collect::<Vec<_>>()
+        .join(";");
+
+    let mut query: Vec<(String, String)> = url
+        .query()
+        .unwrap_or_default()
+        .split('&')
+        .filter(|pair| !pair.is_empty())
+        .map(|pair| match pair.split_once('=') {
+            Some((key, value)) => (key.to_string(), value.to_string()),
+            None => (pair.to_string(), String::new()),
+        })
+        .collect();
+    query.sort();
+    let canonical_query = query
+        .iter()
+        .map(|(key, value)| format!("{}={}", key, value))
+        .collect::<Vec<_>>()
+        .join("&");
+
+    // the path of the url is already encoded
+    let canonical_request = format!(
+        "{}\n{}\n{}\n{}\n{}\n{}",
+        method,
+        url.path(),
+        canonical_query,
+        canonical_headers,
+        signed_headers,
+        payload_hash
+    );
+    let date = &amz_date[..8];
+    let scope = format!("{}/{}/s3/aws4_request", date, region);
+    let string_to_sign = format!(
+        "AWS4-HMAC-SHA256\n{}\n{}\n{}",
+        amz_date,
+        scope,
+        hex(digest::digest(&digest::SHA256, canonical_request.as_bytes()).as_ref())
+    );
+
+    let mut key = format!("AWS4{}", secret_key).into_bytes();
+    for value in [date, region, "s3", "aws4_request"] {
+        key = hmac_sha256(&key, value.as_bytes());
+    }
+    let signature = hex(&hmac_sha256(&key, string_to_sign.as_bytes()));
+    format!(
+        "AWS4-HMAC-SHA256 Credential={}/{}, SignedHeaders={}, Signature={}",
+        access_key, scope, signed_headers, signature
+    )
+}
+
+fn hmac_sha256(key: &[u8], data: &[u8]) -> Vec<u8> {
+    let key = hmac::Key::new(hmac::HMAC_SHA256, key);
+    hmac::sign(&key, data).as_ref().to_vec()
+}
+
+fn hex(bytes: &[u8]) -> String {
+    bytes.iter().map(|b| format!("{:02x}", b)).collect()
+}
+
+/// encode everything except the unreserved characters, like S3 expects it
+fn uri_encode(value: &str, encode_slash: bool) -> String {
+    let mut encoded = String::with_capacity(value.len());
+    for byte in value.bytes() {
+        match byte {
+            b'A'..=b'Z' | b'a'..=b'z' | b'0'..=b'9' | b'-' | b'_' | b'.' | b'~' => {
+                encoded.push(byte as char)
+            }
+            b'/' if !encode_slash => encoded.push('/'),
+            _ => encoded.push_str(&format!("%{:02X}", byte)),
+        }
+    }
+    encoded
+}
+
+/// get the text of the first element with the name, good enough for the simple S3 responses
+fn xml_value(xml: &str, name: &str) -> Option<String> {
+    let start_tag = format!("<{}>", name);
+    let end_tag = format!("</{}>", name);
+    let start = xml.find(&start_tag)? + start_tag.len();
+    let end = xml[start..].find(&end_tag)? + start;
+    Some(xml[start..end].to_string())
+}
@@ -0,0 +1,200 @@
+use std::collections::HashMap;
+use std::path::Path;
+use std::sync::Mutex;
+
+use anyhow::{anyhow, Result};
+use async_trait::async_trait;
+use google_youtube::{scopes, PrivacyStatus, YoutubeClient};
+
+use crate::destination::{Collection, PartInfo, UploadDestination, UploadedPart};
+use crate::error::DownloaderError;
+use crate::prelude::*;
+
+pub const YOUTUBE_DESTINATION_NAME: &str = "youtube";
+
+/// [UploadDestination] that uploads the parts as youtube videos and groups
+/// them in a playlist.
+pub struct YoutubeDestination {
+    client: YoutubeClient,
+    /// the videos that were uploaded, by their id.
+    ///
+    /// The client needs the full objects to add a video to a playlist.
+    videos: Mutex<HashMap<String, google_youtube3::api::Video>>,
+    /// the playlists that were found or created, by their id
+    playlists: Mutex<HashMap<String, google_youtube3::api::Playlist>>,
+    /// held for every api call, youtube fails parallel uploads and playlist
+    /// changes of the same account
+    api_lock: tokio::sync::Mutex<()>,
+}
+
+impl YoutubeDestination {
+    pub fn new(client: YoutubeClient) -> Self {
+        Self {
+            client,
+            videos: Mutex::new(HashMap::new()),
+            playlists: Mutex::new(HashMap::new()),
+            api_lock: tokio::sync::Mutex::new(()),
+        }
+    }
+
+    /// create a client for the youtube user, this might ask for a login the first time
+    pub async fn connect(client_secret_path: &str, user: &str) -> Result<Self> {
+        let client = YoutubeClient::new(
+            Some(client_secret_path),
+            vec![
+                scopes::YOUTUBE_UPLOAD,
+                scopes::YOUTUBE_READONLY,
+                scopes::YOUTUBE,
+            ],
+            Some(user),
+        )
+        .await
+        .map_err(|e| anyhow!("error creating the youtube client: {}", e))?;
+        Ok(Self::new(client))
+    }
+}
+
+/// youtube reports these reasons when the quota of the day is used up
+const QUOTA_REASONS: &[&str] = &["quotaExceeded", "uploadLimitExceeded", "rateLimitExceeded"];
+
+/// the client only gives us a message, quota errors are turned into
+/// [DownloaderError::Quota] so the upload is retried once the quota resets
+fn youtube_error(error: impl std::fmt::Display) -> anyhow::Error {
+    let message = error.to_string();
+    let source = anyhow!("{}", message);
+    if QUOTA_REASONS.iter().any(|reason| message.contains(reason)) {
+        DownloaderError::Quota {
+            destination: YOUTUBE_DESTINATION_NAME.to_string(),
+            source,
+        }
+        .into()
+    } else {
+        source
+    }
+}
+
+fn privacy_status(public: bool) -> PrivacyStatus {
+    match public {
+        true => PrivacyStatus::Public,
+        false => PrivacyStatus::Private,
+    }
+}
+
+#[async_trait(?Send)]
+impl UploadDestination for YoutubeDestination {
+    fn name(&self) -> &str {
+        YOUTUBE_DESTINATION_NAME
+    }
+
+    async fn upload_part(&self, path: &Path, part: &PartInfo) -> Result<UploadedPart> {
+        let _api_lock = self.api_lock.lock().await;
+        let youtube_video = self
+            .client
+            .upload_video(
+                path,
+                &part.title,
+                &part.description,
+                part.tags.clone(),
+                privacy_status(part.public),
+            )
+            .await
+            .map_err(youtube_error)?;
+        let id = youtube_video
+            .id
+            .clone()
+            .ok_or_else(|| anyhow!("youtube did not return an id for the video"))?;
+        self.videos
+            .lock()
+            .map_err(|e| anyhow!("{}", e))?
+            .insert(id.clone(), youtube_video);
+        Ok(UploadedPart {
+            url: format!("https://www.youtube.com/watch?v={}", id),
+            id,
+        })
+    }
+
+    async fn find_or_create_collection(&self, title: &str, public: bool) -> Result<Collection> {
+        let _api_lock = self.api_lock.lock().await;
+        let playlist = self
+            .client
+            .find_playlist_or_create_by_name(title, privacy_status(public))
+            .await
+            .map_err(youtube_error)?;
+        let id = playlist
+            .id
+            .clone()
+            .ok_or_else(|| anyhow!("youtube did not return an id for the playlist"))?;
+        self.playlists
+            .lock()
+            .map_err(|e| anyhow!("{}", e))?
+            .insert(id.clone(), playlist);
+        Ok(Collection {
+            url: format!("https://www.youtube.com/playlist?list={}", id),
+            id,
+        })
+    }
+
+    async fn add_part_to_collection(
+        &self,
+        part: &UploadedPart,
+        collection: &Collection,
+    ) -> Result<()> {
+        // after a restart only the ids from the store are known, the client
+        // only needs the ids to create the playlist item
+        let video = cached_or_from_id(&self.videos, &part.id, |id| google_youtube3::api::Video {
+            id: Some(id),
+            ..Default::default()
+        })?;
+        let playlist = cached_or_from_id(&self.playlists, &collection.id, |id| {
+            google_youtube3::api::Playlist {
+                id: Some(id),
+                ..Default::default()
+            }
+        })?;
+        trace!("adding video {} to playlist {}", part.id, collection.id);
+        let _api_lock = self.api_lock.lock().await;
+        self.client
+            .add_video_to_playlist(&video, &playlist)
+            .await
+            .map_err(youtube_error)?;
+        Ok(())
+    }
+}
+
+/// the object this client got from youtube, or one that only has the id if it
+/// was created by an earlier run
+fn cached_or_from_id<T: Clone>(
+    cache: &Mutex<HashMap<String, T>>,
+    id: &str,
+    from_id: impl FnOnce(String) -> T,
+) -> Result<T> {
+    let cached = cache.lock().map_err(|e| anyhow!("{}", e))?.get(id).cloned();
+    Ok(cached.unwrap_or_else(|| from_id(id.to_string())))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parts_of_an_earlier_run_are_found_by_their_id() {
+        let videos: Mutex<HashMap<String, google_youtube3::api::Video>> =
+            Mutex::new(HashMap::new());
+        let from_id = |id| google_youtube3::api::Video {
+            id: Some(id),
+            ..Default::default()
+        };
+        let video = cached_or_from_id(&videos, "abc", from_id).unwrap();
+        assert_eq!(Some("abc".to_string()), video.id);
+
+        videos.lock().unwrap().insert(
+            "cached".to_string(),
+            google_youtube3::api::Video {
+                id: Some("cached-object".to_string()),
+                ..Default::default()
+            },
+        );
+        let video = cached_or_from_id(&videos, "cached", from_id).unwrap();
+        assert_eq!(Some("cached-object".to_string()), video.id);
+    }
+}
@@ -0,0 +1,159 @@
+use std::fmt;
+use std::path::Path;
+
+use anyhow::Result;
+use downloader_config::Config;
+
+use crate::admin::pending_videos;
+use crate::chapters;
+use crate::data::{VideoData, VideoStage};
+use crate::destination::PartInfo;
+use crate::prelude::*;
+use crate::settings::Settings;
+use crate::source::VideoSources;
+use crate::store::Store;
+use crate::{
+    chapters_of_parts, estimate_part_durations, get_part_infos,
+    get_playlist_title_from_twitch_video, get_streamer_destinations,
+    get_videos_of_watched_streamers,
+};
+
+/// The bit rate the size of the parts is estimated with before the video is
+/// downloaded. It is the highest twitch allows, so the dry run rather shows
+/// too many parts than too few.
+const ASSUMED_BIT_RATE: u64 = 6_000_000;
+
+/// What the next run of the pipeline would do.
+///
+/// Created by [dry_run] without writing to the store, downloading anything
+/// or calling an upload api.
+#[derive(Debug, Default)]
+pub struct DryRunReport {
+    /// the videos that would be added to the store
+    pub new_videos: Vec<PlannedVideo>,
+    /// the videos that would be backed up, in the order they are in the store
+    pub pending_videos: Vec<PlannedVideo>,
+}
+
+/// How a single video would be backed up
+#[derive(Debug)]
+pub struct PlannedVideo {
+    pub video_id: i64,
+    pub streamer_login: String,
+    pub source: String,
+    pub stage: VideoStage,
+    /// the length of the video in seconds, if the source knows it
+    pub duration: Option<i64>,
+    /// the names of the destinations the video would be uploaded to
+    pub destinations: Vec<String>,
+    pub playlist_title: String,
+    /// the parts the video would be split into, estimated from the duration,
+    /// the split limits and the chapters of the video if they are known
+    pub parts: Vec<PartInfo>,
+}
+
+/// Find out what the next run would do, without doing it.
+pub async fn dry_run<'a>(
+    store: &dyn Store,
+    sources: &VideoSources<'a>,
+    config: &Config,
+    settings: &Settings,
+) -> Result<DryRunReport> {
+    info!("Dry run: nothing is written or uploaded");
+    let mut report = DryRunReport::default();
+    for video in get_videos_of_watched_streamers(store, sources).await? {
+        if store.get_video(video.video.video_id).await?.is_none() {
+            report
+                .new_videos
+                .push(plan_video(store, config, settings, &video).await?);
+        }
+    }
+    for video in pending_videos(store).await? {
+        report
+            .pending_videos
+            .push(plan_video(store, config, settings, &video).await?);
+    }
+    Ok(report)
+}
+
+async fn plan_video(
+    store: &dyn Store,
+    config: &Config,
+    settings: &Settings,
+    video: &VideoData,
+) -> Result<PlannedVideo> {
+    let video_chapters = match &video.metadata.download_path {
+        Some(download_path) => chapters::read_sidecar(Path::new(download_path))
+            .await?
+            .unwrap_or_default(),
+        None => vec![],
+    };
+    let part_durations: Vec<Option<f64>> = estimate_part_durations(
+        video.video.duration.unwrap_or_default().max(0) as f64,
+        &settings.split_limits(config),
+        Some(ASSUMED_BIT_RATE),
+        &video_chapters,
+    )?
+    .into_iter()
+    .map(Some)
+    .collect();
+    let part_chapters = chapters_of_parts(&video_chapters, &part_durations);
+    let parts = get_part_infos(video, &part_durations, &part_chapters, config, settings)?;
+    let destinations = get_streamer_destinations(store, settings, &video.streamer)
+        .await?
+        .into_iter()
+        .filter_map(|d| d.destination)
+        .collect();
+    Ok(PlannedVideo {
+        video_id: video.video.video_id,
+        streamer_login: video.streamer.login.clone(),
+        source: video.video.source_name().to_string(),
+        stage: video.metadata.stage(),
+        duration: video.video.duration,
+        destinations,
+        playlist_title: get_playlist_title_from_twitch_video(video)?,
+        parts,
+    })
+}
+
+impl fmt::Display for DryRunReport {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        writeln!(f, "New videos ({}):", self.new_videos.len())?;
+        for video in &self.new_videos {
+            write!(f, "{}", video)?;
+        }
+        writeln!(f, "Pending videos ({}):", self.pending_videos.len())?;
+        for video in &self.pending_videos {
+            write!(f, "{}", video)?;
+        }
+        Ok(())
+    }
+}
+
+impl fmt::Display for PlannedVideo {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let duration = match self.duration {
+            Some(duration) => format!("{} seconds", duration),
+            None => "unknown length".to_string(),
+        };
+        writeln!(
+            f,
+            "- video {} of {} from {} ({}, stage: {})",
+            self.video_id,
+            self.streamer_login,
+            self.source,
+            duration,
+            self.stage.as_str()
+        )?;
+        writeln!(f, "  destinations: {}", self.destinations.join(", "))?;
+        writeln!(f, "  playlist: {}", self.playlist_title)?;
+        writeln!(f, "  parts: {}", self.parts.len())?;
+        for part in &self.parts {
+            writeln!(f, "  - title: {}", part.title)?;
+            for line in part.description.lines() {
+                writeln!(f, "    {}", line)?;
+            }
+        }
+        Ok(())
+    }
+}
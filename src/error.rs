@@ -0,0 +1,106 @@
+use thiserror::Error;
+
+use crate::retry::{classify_error, ErrorKind};
+
+pub type DownloaderResult<T> = std::result::Result<T, DownloaderError>;
+
+/// The ways the backup of a video can fail
+///
+/// The [code](DownloaderError::code) and [message](DownloaderError::message)
+/// are saved with the video, so failures can be grouped and retried by kind.
+#[derive(Debug, Error)]
+pub enum DownloaderError {
+    #[error("could not download video {video_id}")]
+    Download {
+        video_id: i64,
+        #[source]
+        source: anyhow::Error,
+    },
+    /// the downloaded file is not the whole video, it was removed so the
+    /// next attempt downloads it again
+    #[error("the download of video {video_id} is broken")]
+    BrokenDownload {
+        video_id: i64,
+        #[source]
+        source: anyhow::Error,
+    },
+    #[error("could not split video {video_id} with ffmpeg")]
+    Split {
+        video_id: i64,
+        #[source]
+        source: anyhow::Error,
+    },
+    #[error("upload to {destination} failed")]
+    Upload {
+        destination: String,
+        #[source]
+        source: anyhow::Error,
+    },
+    #[error("the quota of {destination} is exceeded")]
+    Quota {
+        destination: String,
+        #[source]
+        source: anyhow::Error,
+    },
+    /// the store or the files on the local disk
+    #[error("could not access the storage")]
+    Storage(#[source] anyhow::Error),
+    #[error("invalid config: {0}")]
+    Config(String),
+    #[error("{0} was not found")]
+    NotFound(String),
+    /// a shutdown was requested, the work continues on the next start
+    #[error("interrupted by a shutdown")]
+    Interrupted,
+}
+
+impl DownloaderError {
+    /// keep errors that already are a [DownloaderError], wrap everything else
+    pub fn or_wrap(error: anyhow::Error, wrap: impl FnOnce(anyhow::Error) -> Self) -> Self {
+        match error.downcast::<DownloaderError>() {
+            Ok(error) => error,
+            Err(error) => wrap(error),
+        }
+    }
+
+    /// a short, stable name of the kind of error, saved as the error code
+    pub fn code(&self) -> &'static str {
+        match self {
+            DownloaderError::Download { .. } => "download",
+            DownloaderError::BrokenDownload { .. } => "broken_download",
+            DownloaderError::Split { .. } => "split",
+            DownloaderError::Upload { .. } => "upload",
+            DownloaderError::Quota { .. } => "quota",
+            DownloaderError::Storage(_) => "storage",
+            DownloaderError::Config(_) => "config",
+            DownloaderError::NotFound(_) => "not_found",
+            DownloaderError::Interrupted => "interrupted",
+        }
+    }
+
+    /// the error with all of its causes
+    pub fn message(&self) -> String {
+        let mut message = self.to_string();
+        let mut source = std::error::Error::source(self);
+        while let Some(cause) = source {
+            message.push_str(": ");
+            message.push_str(&cause.to_string());
+            source = cause.source();
+        }
+        message
+    }
+
+    /// if trying again can fix this error
+    pub fn kind(&self) -> ErrorKind {
+        match self {
+            DownloaderError::Quota { .. }
+            | DownloaderError::BrokenDownload { .. }
+            | DownloaderError::Interrupted => ErrorKind::Transient,
+            DownloaderError::Config(_) | DownloaderError::NotFound(_) => ErrorKind::Permanent,
+            DownloaderError::Download { source, .. }
+            | DownloaderError::Split { source, .. }
+            | DownloaderError::Upload { source, .. }
+            | DownloaderError::Storage(source) => classify_error(source),
+        }
+    }
+}
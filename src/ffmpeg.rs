@@ -0,0 +1,154 @@
+use std::ffi::OsStr;
+use std::process::Stdio;
+
+use thiserror::Error;
+use tokio::process::Command;
+
+use crate::prelude::*;
+use crate::retry::ErrorKind;
+
+/// how many lines at the end of stderr are kept in the error, the rest is
+/// only logged
+const STDERR_TAIL_LINES: usize = 10;
+
+/// The ways a run of ffmpeg (or ffprobe) can fail
+#[derive(Debug, Error)]
+pub enum FfmpegError {
+    #[error("could not start {program}, is it installed?")]
+    NotInstalled {
+        program: String,
+        #[source]
+        source: std::io::Error,
+    },
+    #[error("a file ffmpeg needs was not found: {0}")]
+    FileNotFound(String),
+    #[error("the input is not a valid media file: {0}")]
+    InvalidInput(String),
+    #[error("no space left on the device: {0}")]
+    NoSpace(String),
+    #[error("permission denied: {0}")]
+    PermissionDenied(String),
+    /// an option, codec or format this build of ffmpeg does not know
+    #[error("ffmpeg does not support the arguments: {0}")]
+    Unsupported(String),
+    #[error("{program} failed with {status}: {stderr}")]
+    Failed {
+        program: String,
+        status: String,
+        /// the end of stderr
+        stderr: String,
+    },
+}
+
+impl FfmpegError {
+    /// if running ffmpeg again can succeed
+    pub fn kind(&self) -> ErrorKind {
+        match self {
+            // fixed by whoever runs the downloader
+            FfmpegError::NotInstalled { .. }
+            | FfmpegError::NoSpace(_)
+            | FfmpegError::PermissionDenied(_) => ErrorKind::Transient,
+            FfmpegError::FileNotFound(_)
+            | FfmpegError::InvalidInput(_)
+            | FfmpegError::Unsupported(_) => ErrorKind::Permanent,
+            FfmpegError::Failed { .. } => ErrorKind::Transient,
+        }
+    }
+}
+
+/// What a successful run wrote
+#[derive(Debug, Clone, Default)]
+pub struct FfmpegOutput {
+    pub stdout: String,
+    pub stderr: String,
+}
+
+/// run ffmpeg with the arguments and fail if it does not exit successfully
+pub async fn run_ffmpeg<I, S>(args: I) -> Result<FfmpegOutput, FfmpegError>
+where
+    I: IntoIterator<Item = S>,
+    S: AsRef<OsStr>,
+{
+    let args = ["-hide_banner".as_ref(), "-nostdin".as_ref()]
+        .into_iter()
+        .map(OsStr::to_os_string)
+        .chain(args.into_iter().map(|a| a.as_ref().to_os_string()));
+    run_program("ffmpeg", args).await
+}
+
+/// run a program of the ffmpeg suite (ffmpeg, ffprobe, ...)
+///
+/// The exit status is checked, stderr is logged and well known failures are
+/// turned into their own [FfmpegError].
+pub async fn run_program<I, S>(program: &str, args: I) -> Result<FfmpegOutput, FfmpegError>
+where
+    I: IntoIterator<Item = S>,
+    S: AsRef<OsStr>,
+{
+    let mut command = Command::new(program);
+    command
+        .args(args)
+        .stdin(Stdio::null())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped());
+    debug!("Running command: {:?}", command.as_std());
+    let output = command
+        .output()
+        .await
+        .map_err(|source| FfmpegError::NotInstalled {
+            program: program.to_string(),
+            source,
+        })?;
+    let stdout = String::from_utf8_lossy(&output.stdout).to_string();
+    let stderr = String::from_utf8_lossy(&output.stderr).to_string();
+    if output.status.success() {
+        trace!("{} finished, stderr:\n{}", program, stderr);
+        return Ok(FfmpegOutput { stdout, stderr });
+    }
+    warn!(
+        "{} failed with {}, stderr:\n{}",
+        program, output.status, stderr
+    );
+    Err(
+        parse_stderr(&stderr).unwrap_or_else(|| FfmpegError::Failed {
+            program: program.to_string(),
+            status: output.status.to_string(),
+            stderr: stderr_tail(&stderr),
+        }),
+    )
+}
+
+/// find the well known failures in the stderr of ffmpeg
+pub fn parse_stderr(stderr: &str) -> Option<FfmpegError> {
+    for line in stderr.lines() {
+        let message = line.trim().to_string();
+        if line.contains("No space left on device") {
+            return Some(FfmpegError::NoSpace(message));
+        }
+        if line.contains("No such file or directory") {
+            return Some(FfmpegError::FileNotFound(message));
+        }
+        if line.contains("Permission denied") {
+            return Some(FfmpegError::PermissionDenied(message));
+        }
+        if line.contains("Invalid data found when processing input")
+            || line.contains("moov atom not found")
+        {
+            return Some(FfmpegError::InvalidInput(message));
+        }
+        if line.contains("Unrecognized option")
+            || line.contains("Unknown encoder")
+            || line.contains("Unknown decoder")
+            || line.contains("Requested output format")
+        {
+            return Some(FfmpegError::Unsupported(message));
+        }
+    }
+    None
+}
+
+fn stderr_tail(stderr: &str) -> String {
+    let lines: Vec<&str> = stderr.lines().collect();
+    let start = lines.len().saturating_sub(STDERR_TAIL_LINES);
+    lines[start..].join("\n")
+}
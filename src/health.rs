@@ -0,0 +1,87 @@
+use std::path::Path;
+
+use anyhow::{Context, Result};
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+
+use crate::error::DownloaderError;
+use crate::prelude::*;
+
+/// How the main loop is doing, written to the health file after every
+/// iteration so monitoring can alert on repeated failures.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct LoopHealth {
+    /// how many iterations ran since the start
+    pub iterations: u64,
+    /// how many iterations in a row failed, reset by a successful one
+    pub consecutive_failures: u32,
+    pub total_failures: u64,
+    pub last_success_at: Option<DateTime<Utc>>,
+    pub last_failure_at: Option<DateTime<Utc>>,
+    pub last_error: Option<String>,
+    /// the [code](DownloaderError::code) of the last error, if it had one
+    pub last_error_code: Option<String>,
+}
+
+impl LoopHealth {
+    pub fn record_success(&mut self, now: DateTime<Utc>) {
+        self.iterations += 1;
+        self.consecutive_failures = 0;
+        self.last_success_at = Some(now);
+    }
+
+    pub fn record_failure(&mut self, error: &anyhow::Error, now: DateTime<Utc>) {
+        self.iterations += 1;
+        self.consecutive_failures += 1;
+        self.total_failures += 1;
+        self.last_failure_at = Some(now);
+        self.last_error = Some(format!("{:#}", error));
+        self.last_error_code = error_code(error).map(str::to_string);
+    }
+
+    /// if the loop should stop instead of trying again on the next cycle
+    ///
+    /// that is the case for errors with one of the `fatal_error_codes` and
+    /// once `max_consecutive_failures` iterations failed in a row
+    pub fn is_fatal(
+        &self,
+        error: &anyhow::Error,
+        fatal_error_codes: &[String],
+        max_consecutive_failures: Option<u32>,
+    ) -> bool {
+        if let Some(code) = error_code(error) {
+            if fatal_error_codes.iter().any(|c| c == code) {
+                return true;
+            }
+        }
+        match max_consecutive_failures {
+            Some(max) => self.consecutive_failures >= max,
+            None => false,
+        }
+    }
+
+    /// write the health as json, through a temporary file so readers never
+    /// see a half written file
+    pub fn write(&self, path: &Path) -> Result<()> {
+        if let Some(parent) = path.parent() {
+            std::fs::create_dir_all(parent)
+                .with_context(|| format!("could not create folder for {}", path.display()))?;
+        }
+        let json = serde_json::to_string_pretty(self)?;
+        let partial = path.with_extension("partial");
+        std::fs::write(&partial, json)
+            .with_context(|| format!("could not write {}", partial.display()))?;
+        std::fs::rename(&partial, path)
+            .with_context(|| format!("could not write {}", path.display()))?;
+        trace!("wrote health to {}", path.display());
+        Ok(())
+    }
+}
+
+/// the code of the first [DownloaderError] in the chain
+fn error_code(error: &anyhow::Error) -> Option<&'static str> {
+    error
+        .chain()
+        .find_map(|cause| cause.downcast_ref::<DownloaderError>())
+        .map(DownloaderError::code)
+}
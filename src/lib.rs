@@ -6,199 +6,395 @@ use std::future::Future;
 use std::io::stdin;
 use std::path::{Path, PathBuf};
 use std::process::Stdio;
+use std::rc::Rc;
 use std::str::Chars;
 
 use anyhow::{anyhow, Context, Result};
 use chrono::{Datelike, Duration};
 use downloader_config;
-use downloader_config::{load_config, Config};
-use google_bigquery_v2::prelude::*;
-use google_youtube::{scopes, PrivacyStatus, YoutubeClient};
-use nameof::name_of;
+use downloader_config::Config;
+use futures_util::TryStreamExt;
 use path_clean::clean;
-use tokio::io::BufReader;
-use tokio::process::Command;
-use twitch_data::{TwitchClient, Video};
-
-use crate::data::{Streamers, VideoData};
+use tokio::io::{AsyncReadExt, BufReader};
+
+use crate::chapters::Chapter;
+use crate::data::{
+    PartStatus, StreamerDestinations, Streamers, VideoData, VideoMetadata, VideoParts, VideoStage,
+    VideoUploads,
+};
+use crate::destination::local::LOCAL_ARCHIVE_DESTINATION_NAME;
+use crate::destination::s3::S3_DESTINATION_NAME;
+use crate::destination::youtube::YOUTUBE_DESTINATION_NAME;
+use crate::destination::{
+    youtube_user, Collection, LocalArchiveDestination, PartInfo, S3Destination, UploadDestination,
+    UploadDestinations, UploadedPart, VideoDestination, YoutubeDestination,
+};
+use crate::error::{DownloaderError, DownloaderResult};
+use crate::health::LoopHealth;
 use crate::prelude::*;
-
+use crate::probe::MediaInfo;
+use crate::schedule::Trigger;
+use crate::settings::Settings;
+use crate::shutdown::Shutdown;
+use crate::source::{HlsSource, LocalFolderSource, TwitchSource, VideoSource, VideoSources};
+use crate::split_points::{find_split_points, SplitPointMode, SplitPoints};
+use crate::store::{create_store, Store};
+use crate::workers::StageLimits;
+
+pub mod admin;
+pub mod chapters;
 pub mod data;
+pub mod destination;
+pub mod dry_run;
+pub mod error;
+pub mod ffmpeg;
+pub mod health;
 pub mod prelude;
-
-async fn check_for_new_videos<'a>(
-    db_client: &BigqueryClient,
-    twitch_client: &TwitchClient<'a>,
-) -> Result<()> {
+pub mod probe;
+pub mod retry;
+pub mod schedule;
+pub mod settings;
+pub mod shutdown;
+pub mod source;
+pub mod split_points;
+pub mod store;
+pub mod workers;
+
+pub async fn check_for_new_videos<'a>(store: &dyn Store, sources: &VideoSources<'a>) -> Result<()> {
     trace!("Checking for new videos");
+    let videos = get_videos_of_watched_streamers(store, sources).await?;
+    //put those videos in the database if they are not already there
+    add_new_videos_to_store(store, videos).await?;
+    Ok(())
+}
+
+/// Get the videos of all watched streamers from all sources, including the
+/// ones that are already in the store.
+pub async fn get_videos_of_watched_streamers<'a>(
+    store: &dyn Store,
+    sources: &VideoSources<'a>,
+) -> Result<Vec<VideoData>> {
     //check for new videos from the channels in the database that are watched
-    let watched = get_watched_streamers(db_client).await?;
+    let watched = store.get_watched_streamers().await?;
 
     info!("Got {} watched streamers", watched.len());
-    //put those videos in the database if they are not already there
+    let mut result = vec![];
     for streamer in watched {
-        let videos = get_twitch_videos_from_streamer(&streamer, &twitch_client).await?;
-        info!("Got {} videos for {}", videos.len(), streamer.login);
-        for video in videos {
-            let video_id: &i64 = &video.id.parse()?;
-            let loaded_video = data::Videos::get_by_pk(db_client.clone(), video_id).await;
-            debug!("get_by_pk result: {:?}", loaded_video);
-            if loaded_video.is_err() {
-                let mut video = data::VideoData::from_twitch_video(&video, db_client.clone())
-                    .map_err(|e| anyhow::anyhow!("{}", e))?;
-                info!(
-                    "Video {} is not in the database, adding it: {}",
-                    video_id,
-                    video
-                        .video
-                        .title
-                        .as_ref()
-                        .unwrap_or(&"TITLE NOT FOUND".to_string())
-                );
+        for source in sources.iter() {
+            // one source that is down should not stop the others
+            let videos = match source.get_videos_for_streamer(&streamer).await {
+                Ok(videos) => videos,
+                Err(e) => {
+                    warn!(
+                        "Could not get the videos for {} from {}: {:?}",
+                        streamer.login,
+                        source.name(),
+                        e
+                    );
+                    continue;
+                }
+            };
+            info!(
+                "Got {} videos for {} from {}",
+                videos.len(),
+                streamer.login,
+                source.name()
+            );
+            // the sources only know the login, the settings of the streamer are in the store
+            result.extend(videos.into_iter().map(|mut video| {
+                video.streamer = streamer.clone();
                 video
-                    .video
-                    .upsert()
-                    .await
-                    .map_err(|e| anyhow!("error saving video data: {}", e))?;
+            }));
+        }
+    }
+    Ok(result)
+}
+
+/// Adds the videos that are not in the store yet.
+///
+/// Returns the number of videos that were added.
+pub async fn add_new_videos_to_store(store: &dyn Store, videos: Vec<VideoData>) -> Result<usize> {
+    let mut added = 0;
+    for video in videos {
+        let video_id = video.video.video_id;
+        let loaded_video = store.get_video(video_id).await?;
+        if loaded_video.is_none() {
+            info!(
+                "Video {} is not in the database, adding it: {}",
+                video_id,
                 video
-                    .metadata
-                    .upsert()
-                    .await
-                    .map_err(|e| anyhow!("error saving video metadata: {}", e))?;
-            }
+                    .video
+                    .title
+                    .as_ref()
+                    .unwrap_or(&"TITLE NOT FOUND".to_string())
+            );
+            store.upsert_video(&video.video).await?;
+            store.upsert_video_metadata(&video.metadata).await?;
+            added += 1;
         }
     }
-    Ok(())
+    Ok(added)
 }
 
-async fn get_twitch_videos_from_streamer<'a>(
-    streamer: &Streamers,
-    twitch_client: &TwitchClient<'a>,
-) -> Result<Vec<Video>> {
-    trace!("Getting videos from streamer {}", streamer.login);
-    let videos = twitch_client
-        .get_videos_from_login(&streamer.login, None)
-        .await
-        .map_err(|e| anyhow!("{}", e))?;
+/// What became of the backup of a video
+#[derive(Debug)]
+pub enum BackupOutcome {
+    /// the video is backed up to all its destinations
+    BackedUp,
+    /// the video was left for a later run, with the reason why
+    Skipped(String),
+    /// the backup failed, the error is recorded in the metadata of the video
+    Failed(DownloaderError),
+}
 
-    Ok(videos)
+/// Everything the backup needs, created from the config and the settings
+pub struct Pipeline<'a> {
+    pub config: Config,
+    pub settings: Settings,
+    pub store: Box<dyn Store>,
+    pub sources: VideoSources<'a>,
+    pub destinations: UploadDestinations,
+    /// requested on SIGTERM/SIGINT
+    pub shutdown: Shutdown,
 }
 
-async fn get_watched_streamers(client: &BigqueryClient) -> Result<Vec<Streamers>> {
-    trace!("Getting watched streamers");
-    let watched = Streamers::select()
-        .with_client(client.clone())
-        .add_where_eq(name_of!(watched in Streamers), Some(&true))
-        .map_err(|e| anyhow!("{}", e))?
-        .set_limit(1000)
-        .build_query()
-        .map_err(|e| anyhow!("{}", e))?
-        .run()
+impl<'a> Pipeline<'a> {
+    /// load the config and connect to the store, the sources and all destinations
+    pub async fn create() -> Result<Pipeline<'a>> {
+        let config = downloader_config::load_config();
+        let settings = Settings::load()?;
+        info!("loaded config");
+        let shutdown = Shutdown::new();
+        shutdown
+            .listen_for_signals()
+            .context("could not listen for signals")?;
+
+        let store = create_store(&config, &settings).await?;
+        let sources = create_video_sources(&settings).await?;
+        info!("getting upload destinations");
+        let destinations = get_upload_destinations(store.as_ref(), &config, &settings)
+            .await
+            .context("could not create upload destinations")?;
+        info!("got upload destinations");
+        Ok(Self {
+            config,
+            settings,
+            store,
+            sources,
+            destinations,
+            shutdown,
+        })
+    }
+
+    /// one cycle of the main loop: look for new videos and back up everything
+    /// that is not backed up yet
+    pub async fn run_iteration(&self) -> Result<()> {
+        let store = self.store.as_ref();
+        trace!("Checking for new videos");
+        check_for_new_videos(store, &self.sources).await?;
+        trace!("backing up not downloaded videos");
+        backup_not_downloaded_videos(
+            store,
+            &self.sources,
+            &self.config,
+            &self.settings,
+            &self.destinations,
+            &self.shutdown,
+        )
         .await
-        .map_err(|e| anyhow!("{}", e))?;
-    let watched = watched
-        .map_err_with_data("Error getting watched streamers")
-        .map_err(|e| anyhow!("{}", e))?;
-    Ok(watched)
-}
+    }
 
-pub async fn start_backup() -> Result<()> {
-    info!("Starting backup");
-    let config = downloader_config::load_config();
-    info!("loaded config");
-    let project_id = &config.bigquery_project_id;
-    let service_account_path = &config.bigquery_service_account_path;
-    let dataset_id = &config.bigquery_dataset_id;
-    let youtube_client_secret = &config.youtube_client_secret_path.as_str();
-
-    info!("creating BigqueryClient");
-    let client = BigqueryClient::new(project_id, dataset_id, Some(service_account_path))
+    /// back up a single video right away, even if it failed before
+    pub async fn backup_video(&self, video_id: i64) -> Result<BackupOutcome> {
+        let store = self.store.as_ref();
+        let metadata = store
+            .get_video_metadata(video_id)
+            .await?
+            .ok_or_else(|| DownloaderError::NotFound(format!("video {}", video_id)))?;
+        if metadata.backed_up == Some(true) {
+            info!("Video {} is already backed up", video_id);
+            return Ok(BackupOutcome::BackedUp);
+        }
+        let video = get_video_data(store, metadata).await?.ok_or_else(|| {
+            DownloaderError::NotFound(format!("the video or streamer of video {}", video_id))
+        })?;
+        backup_and_record_video(
+            store,
+            &self.sources,
+            &self.config,
+            &self.settings,
+            &self.destinations,
+            &self.settings.stage_limits(),
+            &self.shutdown,
+            video,
+        )
         .await
-        .map_err(|e| anyhow!("{}", e))?;
+    }
+}
+
+/// all sources that are enabled in the settings
+pub async fn create_video_sources<'a>(settings: &Settings) -> Result<VideoSources<'a>> {
     info!("creating twitch client");
     let twitch_client = twitch_data::get_client()
         .await
         .map_err(|e| anyhow!("{}", e))?;
-    info!("getting youtube clients");
-    let youtube_clients: HashMap<String, YoutubeClient> = get_youtube_clients(&client)
-        .await
-        .context("could not create youtube clients")?;
-    info!("got youtube clients");
+    let mut sources = VideoSources::new();
+    sources.add(TwitchSource::new(twitch_client));
+    if let Some(inbox) = &settings.local_inbox_path {
+        info!("watching local inbox: {}", inbox);
+        sources.add(LocalFolderSource::new(inbox));
+    }
+    if let Some(playlists_file) = &settings.hls_playlists_file {
+        info!("archiving hls playlists from: {}", playlists_file);
+        sources.add(
+            HlsSource::new(playlists_file)
+                .with_max_bandwidth(settings.hls_max_bandwidth)
+                .with_retries(settings.hls_segment_retries),
+        );
+    }
+    Ok(sources)
+}
+
+pub async fn start_backup() -> Result<()> {
+    info!("Starting backup");
+    let pipeline = Pipeline::create().await?;
+    let settings = &pipeline.settings;
+    let shutdown = &pipeline.shutdown;
+    let schedule = settings.schedule()?;
+    let trigger = Trigger::new();
+    trigger
+        .listen_for_signals()
+        .context("could not listen for signals")?;
     info!("Starting main loop");
+    let mut health = LoopHealth::default();
     'main_loop: loop {
         trace!("Beginning of main loop");
 
-        trace!("Checking for new videos");
-        check_for_new_videos(&client, &twitch_client).await?;
-        trace!("backing up not downloaded videos");
-        backup_not_downloaded_videos(&client, &twitch_client, &config, &youtube_clients)
-            .await
-            .map_err(|e| anyhow!("{}", e))?;
+        let result = pipeline.run_iteration().await;
+        let now = chrono::Utc::now();
+        match &result {
+            Ok(()) => health.record_success(now),
+            Err(e) => {
+                health.record_failure(e, now);
+                error!(
+                    consecutive_failures = health.consecutive_failures,
+                    "Iteration of the main loop failed: {:#}", e
+                );
+            }
+        }
+        if let Some(health_file) = &settings.health_file {
+            if let Err(e) = health.write(Path::new(health_file)) {
+                warn!("could not write the health file: {:#}", e);
+            }
+        }
+        if let Err(e) = result {
+            if health.is_fatal(
+                &e,
+                &settings.fatal_error_codes,
+                settings.max_consecutive_failures,
+            ) {
+                error!("Stopping the main loop because of a fatal error");
+                return Err(e);
+            }
+        }
 
-        //sleep for an hour
-        info!("Sleeping for a while");
-        tokio::time::sleep(std::time::Duration::from_secs(3 * 60 * 60)).await;
+        if shutdown.is_requested() {
+            break 'main_loop;
+        }
+        let now = chrono::Utc::now();
+        let next_run = schedule.next_run(now) + schedule.random_jitter();
+        info!("Sleeping until the next run at {}", next_run);
+        let wait = (next_run - now).to_std().unwrap_or_default();
+        tokio::select! {
+            _ = tokio::time::sleep(wait) => {}
+            _ = trigger.triggered() => {}
+            _ = shutdown.requested() => break 'main_loop,
+        }
         //repeat
     }
+    info!("Stopped the main loop");
+    Ok(())
+}
+
+/// Get the destinations of the streamer.
+///
+/// Streamers without any destinations in the store use the ones from the
+/// settings: the local archive and/or s3 if they are listed there, youtube otherwise.
+pub async fn get_streamer_destinations(
+    store: &dyn Store,
+    settings: &Settings,
+    streamer: &Streamers,
+) -> Result<Vec<StreamerDestinations>> {
+    let destinations = store.get_streamer_destinations(&streamer.login).await?;
+    if !destinations.is_empty() {
+        return Ok(destinations);
+    }
+    let mut names = vec![];
+    if settings.uses_local_archive(&streamer.login) {
+        names.push(LOCAL_ARCHIVE_DESTINATION_NAME);
+    }
+    if settings.uses_s3(&streamer.login) {
+        names.push(S3_DESTINATION_NAME);
+    }
+    if names.is_empty() {
+        names.push(YOUTUBE_DESTINATION_NAME);
+    }
+    Ok(names
+        .into_iter()
+        .map(|name| StreamerDestinations::new(&streamer.login, name, true))
+        .collect())
 }
 
-async fn get_youtube_clients(
-    db_client: &BigqueryClient,
-) -> anyhow::Result<HashMap<String, YoutubeClient>> {
-    let mut result = HashMap::new();
-    let config = load_config();
-    let streamers = get_watched_streamers(db_client).await?;
-    info!("Getting youtube clients for {:?}", streamers);
+/// create all destinations the watched streamers need
+async fn get_upload_destinations(
+    store: &dyn Store,
+    config: &Config,
+    settings: &Settings,
+) -> Result<UploadDestinations> {
+    let mut result = UploadDestinations::new();
+    if let Some(path) = &settings.local_archive_path {
+        info!("backing up to local archive at: {}", path);
+        let local_archive =
+            LocalArchiveDestination::new(path).with_layout(&settings.local_archive_layout);
+        result.insert(LOCAL_ARCHIVE_DESTINATION_NAME, Rc::new(local_archive));
+    }
+    if let Some(s3_config) = settings.s3_config() {
+        info!(
+            "backing up to s3 bucket {} at: {}",
+            s3_config.bucket, s3_config.endpoint
+        );
+        result.insert(S3_DESTINATION_NAME, Rc::new(S3Destination::new(s3_config)?));
+    }
+    let streamers = store.get_watched_streamers().await?;
+    info!("Getting upload destinations for {:?}", streamers);
     for streamer in streamers {
+        let streamer_destinations = get_streamer_destinations(store, settings, &streamer).await?;
+        let uses_youtube = streamer_destinations
+            .iter()
+            .any(|d| d.destination.as_deref() == Some(YOUTUBE_DESTINATION_NAME));
+        let key = UploadDestinations::key_for(YOUTUBE_DESTINATION_NAME, &streamer);
+        // multiple streamers can share a youtube account, so the clients are shared too
+        if !uses_youtube || result.contains(&key) {
+            continue;
+        }
         trace!("Creating youtube client");
-
-        let user = streamer
-            .youtube_user
-            .as_ref()
-            .unwrap_or(&"NopixelVODs".to_string())
-            .to_string();
+        let user = youtube_user(&streamer);
         info!("creating youtube client for user: {}", user);
-        let youtube_client = YoutubeClient::new(
-            Some(config.youtube_client_secret_path.as_str()),
-            vec![
-                scopes::YOUTUBE_UPLOAD,
-                scopes::YOUTUBE_READONLY,
-                scopes::YOUTUBE,
-            ],
-            Some(&user),
-        )
-        .await
-        .map_err(|e| anyhow!("error creating the youtube client: {}", e))?;
+        let destination =
+            YoutubeDestination::connect(&config.youtube_client_secret_path, &user).await?;
         info!("Got client for user: {}", user);
-        result.insert(user, youtube_client);
+        result.insert(key, Rc::new(destination));
     }
-    info!("Got youtube clients");
+    info!("Got upload destinations");
     Ok(result)
 }
 
-async fn get_not_downloaded_videos_from_db(
-    client: &BigqueryClient,
+pub async fn get_not_downloaded_videos_from_db(
+    store: &dyn Store,
 ) -> Result<impl Iterator<Item = impl Future<Output = Result<Option<data::VideoData>>> + '_> + '_> {
-    //TODO: make sure that this is sorted by date (oldest first)
     info!("getting not downloaded videos from db (metadata)");
 
-    let mut video_metadata_list = data::VideoMetadata::select()
-        .with_client(client.clone())
-        .add_where_eq(name_of!(backed_up in data::VideoMetadata), Some(&false))
-        .context("could not add backed_up where")?
-        .add_where_eq::<String>(name_of!(error in data::VideoMetadata), None)
-        .context("could not add error where")?
-        .add_order_by(
-            name_of!(video_id in data::VideoMetadata),
-            OrderDirection::Ascending,
-        )
-        //TODO: check if ordering by video_id is correct (should be oldest first)
-        //TODO: sort this by streamer (join is needed)
-        .set_limit(1000)
-        .build_query()?
-        .run()
-        .await?
-        .map_err_with_data("Error getting not downloaded videos from db")?;
+    let video_metadata_list = store.get_not_downloaded_video_metadata().await?;
     info!("getting not downloaded videos from db (videos)");
     let amount = video_metadata_list.len();
     info!("got about {} videos", amount);
@@ -211,159 +407,530 @@ async fn get_not_downloaded_videos_from_db(
                 i + 1,
                 amount
             );
-            let video_id = metadata.video_id.clone();
-            let v = data::Videos::get_by_pk(client.clone(), &video_id.clone()).await;
-
-            if let Ok(video) = v {
-                info!(
-                    "getting not downloaded videos from db (streamer): {}/{}",
-                    i + 1,
-                    amount
-                );
-                let user_login = video.user_login.clone().unwrap().to_lowercase();
-                let streamer = data::Streamers::get_by_pk(client.clone(), &user_login).await;
-                if streamer.is_err() {
-                    // .expect(format!("Streamer with login not found: {}", user_login).as_str());
-                    warn!("Streamer with login not found: {}", user_login);
-                    return Ok(None);
-                }
-                let streamer = streamer.unwrap();
-
-                return Ok(Some(VideoData {
-                    video,
-                    metadata,
-                    streamer,
-                }));
-            }
-            Ok(None)
+            get_video_data(store, metadata).await
         }); //TODO: maybe figure out how to use the filter method on this async iterator (filter out None values)
     return Ok(res);
 }
 
-async fn backup_not_downloaded_videos<'a>(
-    client: &BigqueryClient,
-    twitch_client: &TwitchClient<'a>,
+/// load the video and the streamer that belong to the metadata, `None` if
+/// one of them is missing
+pub async fn get_video_data(
+    store: &dyn Store,
+    metadata: VideoMetadata,
+) -> Result<Option<VideoData>> {
+    let video_id = metadata.video_id;
+    let video = match store.get_video(video_id).await? {
+        Some(video) => video,
+        None => {
+            warn!("Video not found: {}", video_id);
+            return Ok(None);
+        }
+    };
+    let user_login = video.user_login.clone().unwrap_or_default().to_lowercase();
+    let streamer = match store.get_streamer(&user_login).await? {
+        Some(streamer) => streamer,
+        None => {
+            warn!("Streamer with login not found: {}", user_login);
+            return Ok(None);
+        }
+    };
+    Ok(Some(VideoData {
+        video,
+        metadata,
+        streamer,
+    }))
+}
+
+pub async fn backup_not_downloaded_videos<'a>(
+    store: &dyn Store,
+    sources: &VideoSources<'a>,
     config: &Config,
-    youtube_clients: &HashMap<String, YoutubeClient>,
+    settings: &Settings,
+    destinations: &UploadDestinations,
+    shutdown: &Shutdown,
 ) -> Result<()> {
     trace!("backup not downloaded videos");
-    let path = Path::new(&config.download_folder_path);
     info!("Getting not downloaded videos from db");
-    let videos = get_not_downloaded_videos_from_db(client).await?;
-    for mut video in videos.into_iter() {
-        let video = video.await?;
-
-        if video.is_none() {
-            continue;
+    let mut videos = vec![];
+    for video in get_not_downloaded_videos_from_db(store).await? {
+        if let Some(video) = video.await? {
+            videos.push(video);
         }
-        let mut video = video.unwrap();
+    }
+    // one streamer with a lot of videos should not block everyone else
+    let videos = workers::fair_order(videos, |video| video.streamer.login.clone());
+    let limits = settings.stage_limits();
+    let limits = &limits;
+    info!(
+        "Backing up {} videos, {} at a time",
+        videos.len(),
+        settings.max_concurrent_videos.max(1)
+    );
+    // every backup runs to the end, so none is left without its stage saved
+    workers::run_to_completion(videos, settings.max_concurrent_videos, |video| async move {
+        let video_id = video.video.video_id;
+        backup_and_record_video(
+            store,
+            sources,
+            config,
+            settings,
+            destinations,
+            limits,
+            shutdown,
+            video,
+        )
+        .await
+        .map(|_| ())
+        .with_context(|| format!("could not back up video {}", video_id))
+    })
+    .await?;
 
-        trace!("Getting youtube client");
-        let youtube_client = youtube_clients.get(
-            video
-                .streamer
-                .youtube_user
-                .as_ref()
-                .unwrap_or(&"unknown".to_string()),
+    info!("Backing up not downloaded videos finished");
+    Ok(())
+}
+
+/// Back up a video and save how it went.
+///
+/// Failed backups are recorded in the metadata of the video and reported in the
+/// outcome, only errors of the store itself are returned.
+#[allow(clippy::too_many_arguments)]
+async fn backup_and_record_video<'a>(
+    store: &dyn Store,
+    sources: &VideoSources<'a>,
+    config: &Config,
+    settings: &Settings,
+    destinations: &UploadDestinations,
+    limits: &StageLimits,
+    shutdown: &Shutdown,
+    mut video: VideoData,
+) -> Result<BackupOutcome> {
+    if shutdown.is_requested() {
+        info!(
+            "Not starting the backup of video {} because of the shutdown",
+            video.video.video_id
         );
-        if youtube_client.is_none() {
-            warn!("could not find youtube client for video: {:?}", video);
-            let clients: Vec<String> = youtube_clients.keys().map(|k| k.clone()).collect();
-            let clients: String = clients.join(";");
+        return Ok(BackupOutcome::Skipped(
+            "the shutdown was requested".to_string(),
+        ));
+    }
+
+    trace!("Getting upload destinations");
+    let streamer_destinations = get_streamer_destinations(store, settings, &video.streamer).await?;
+    let video_destinations =
+        match destinations.for_streamer(&video.streamer, &streamer_destinations) {
+            Ok(video_destinations) => video_destinations,
+            Err(e) => {
+                let available: Vec<String> = destinations.keys().cloned().collect();
+                let available: String = available.join(";");
+                warn!(
+                    ?video,
+                    warning = "could not find upload destinations for video",
+                    error = %e,
+                    available
+                );
+                return Ok(BackupOutcome::Skipped(format!(
+                    "no upload destinations: {}",
+                    e
+                )));
+            }
+        };
+    let source = match sources.for_video(&video) {
+        Some(source) => source,
+        None => {
             warn!(
-                ?video,
-                warning = "could not find youtube client for video",
-                clients
+                "could not find source '{}' for video: {}",
+                video.video.source_name(),
+                video.video.video_id
             );
-            continue;
+            return Ok(BackupOutcome::Skipped(format!(
+                "the source '{}' is not available",
+                video.video.source_name()
+            )));
         }
-        let youtube_client = youtube_client.expect("we just checked it");
-        let result = backup_video(twitch_client, config, path, &mut video, &youtube_client).await;
-        if let Err(e) = result {
-            let error_message = format!("Error while backing up video: {}", e.to_string());
+    };
+    let result = {
+        let backup = backup_video(
+            store,
+            source,
+            config,
+            settings,
+            &mut video,
+            &video_destinations,
+            limits,
+            shutdown,
+        );
+        tokio::select! {
+            result = backup => result,
+            _ = shutdown.deadline_passed(settings.shutdown_deadline()) => {
+                // the running uploads were dropped, so they never clean up after themselves
+                for video_destination in &video_destinations {
+                    if let Err(e) = video_destination.destination.cancel_uploads().await {
+                        warn!(
+                            "could not clean up the uploads to {}: {:?}",
+                            video_destination.name, e
+                        );
+                    }
+                }
+                Err(DownloaderError::Interrupted)
+            }
+        }
+    };
+    if let Err(DownloaderError::Interrupted) = result {
+        // the stage and the uploaded parts are saved as soon as they are done,
+        // the files of the last stage stay on disk for the next start and the
+        // parts that were not completely uploaded are uploaded again
+        info!(
+            "Backup of video {} was interrupted in stage: {}",
+            video.video.video_id,
+            video.metadata.stage().as_str()
+        );
+        store
+            .save_video_metadata(&video.metadata)
+            .await
+            .context("could not save the progress of the interrupted video")?;
+        return Ok(BackupOutcome::Skipped(format!(
+            "the backup was interrupted in stage {}",
+            video.metadata.stage().as_str()
+        )));
+    }
+    match result {
+        Ok(outcome) => Ok(outcome),
+        Err(e) => {
+            let error_message = format!("Error while backing up video: {}", e.message());
             warn!(error_message, error=?e);
             video.metadata.error = Some(error_message);
+            video.metadata.error_code = Some(e.code().to_string());
             video.metadata.backed_up = Some(false);
-            video.metadata.save().await.map_err(|e| anyhow!("{}", e))?;
-            continue;
+            video.metadata.set_stage(VideoStage::Failed);
+            settings.retry_policy().record_failure(
+                &mut video.metadata,
+                e.kind(),
+                chrono::Utc::now(),
+            );
+            store.save_video_metadata(&video.metadata).await?;
+            Ok(BackupOutcome::Failed(e))
         }
-        info!("Video backed up");
     }
-
-    info!("Backing up not downloaded videos finished");
-    Ok(())
 }
 
-async fn backup_video<'a>(
-    twitch_client: &TwitchClient<'a>,
+#[allow(clippy::too_many_arguments)]
+async fn backup_video(
+    store: &dyn Store,
+    source: &dyn VideoSource,
     config: &Config,
-    path: &Path,
+    settings: &Settings,
     video: &mut VideoData,
-    youtube_client: &YoutubeClient,
-) -> Result<()> {
+    destinations: &[VideoDestination],
+    limits: &StageLimits,
+    shutdown: &Shutdown,
+) -> DownloaderResult<BackupOutcome> {
+    let video_id = video.video.video_id;
     info!(
         "Backing up video {}: {}\nLength: {}",
-        video.video.video_id,
+        video_id,
         video.video.title.as_ref().unwrap(),
-        video.video.duration.as_ref().unwrap()
+        video.video.duration.unwrap_or_default()
     );
-    let video_file_path = twitch_client
-        .download_video(video.video.video_id.to_string(), "", path)
-        .await;
-    if video_file_path.is_err() {
-        warn!(
-            "Failed to download video: {}: {:?}",
-            video.video.video_id, video.video.title
-        );
-        return Err(anyhow!(
-            "Failed to download video: {}: {:?}",
-            video.video.video_id,
-            video.video.title
-        ));
+    let mut stage = resume_stage(&video.metadata)
+        .await
+        .map_err(DownloaderError::Storage)?;
+    info!(
+        "Continuing backup of video {} from stage: {}",
+        video_id,
+        stage.as_str()
+    );
+
+    if matches!(stage, VideoStage::Discovered | VideoStage::Downloading) {
+        save_stage(store, video, VideoStage::Downloading).await?;
+        let path = Path::new(&config.download_folder_path);
+        let download_permit = limits.download().await;
+        let video_file_path = source.download_video(video, path).await;
+        drop(download_permit);
+        let video_file_path = match video_file_path {
+            Ok(video_file_path) => video_file_path,
+            Err(e) => {
+                warn!(
+                    "Failed to download video: {}: {:?}: {:?}",
+                    video_id, video.video.title, e
+                );
+                return Err(DownloaderError::or_wrap(e, |source| {
+                    DownloaderError::Download { video_id, source }
+                }));
+            }
+        };
+        video.metadata.download_path = Some(video_file_path.to_string_lossy().to_string());
+        stage = VideoStage::Downloaded;
+        save_stage(store, video, stage).await?;
     }
-    let video_file_path = video_file_path.unwrap();
-    info!("Splitting video into parts");
-    //TODO: optimization: if the video is shorter than the soft cap, then skip this step
-    let mut video_parts = split_video_into_parts(
-        video_file_path.to_path_buf(),
-        Duration::minutes(config.youtube_video_length_minutes_soft_cap),
-        Duration::minutes(config.youtube_video_length_minutes_hard_cap),
-    )
-    .await
-    .map_err(|e| anyhow!("error while splitting video into parts: {}", e))?;
-    video_parts.sort();
-    info!("Uploading video to youtube");
-    debug!("Video parts: {:?}", video_parts);
-    debug!("Video: {:?}", video);
-    debug!("Config: {:?}", config);
-    let res = upload_video_to_youtube(&video_parts, video, &youtube_client, config).await;
-    if let Err(e) = res {
-        info!("Error uploading video: {}", e);
-        video.metadata.error = Some(e.to_string());
-        video
-            .metadata
-            .save()
+    let video_file_path = PathBuf::from(video.metadata.download_path.clone().ok_or_else(|| {
+        DownloaderError::NotFound(format!("the download path of video {}", video_id))
+    })?);
+
+    if shutdown.is_requested() {
+        return Err(DownloaderError::Interrupted);
+    }
+    if stage == VideoStage::Downloaded {
+        // ffmpeg does not overwrite the parts of a split that was interrupted
+        let leftover_parts = find_video_parts(&video_file_path)
             .await
-            .map_err(|e| anyhow!("could not save metadata error code to db: {}", e))?;
-    } else {
+            .map_err(DownloaderError::Storage)?;
+        cleanup_video_parts(leftover_parts)
+            .await
+            .map_err(DownloaderError::Storage)?;
+        let _split_permit = limits.split().await;
+        let media_info = probe::probe(&video_file_path)
+            .await
+            .map_err(|source| DownloaderError::Split { video_id, source })?;
+        if let Err(e) = check_download(video, &media_info, settings) {
+            // a new download is the only way to fix a broken file
+            warn!(
+                "The download of video {} is broken, removing it: {:?}",
+                video_id, e
+            );
+            tokio::fs::remove_file(&video_file_path)
+                .await
+                .context("could not remove the broken download")
+                .map_err(DownloaderError::Storage)?;
+            return Err(DownloaderError::BrokenDownload {
+                video_id,
+                source: e,
+            });
+        }
+        // the chapters are needed for the parts after the video is gone
+        if !media_info.chapters.is_empty() && !chapters::sidecar_path(&video_file_path).exists() {
+            chapters::write_sidecar(&video_file_path, &media_info.chapters)
+                .await
+                .map_err(DownloaderError::Storage)?;
+        }
+        let split_limits = settings.split_limits(config);
+        let size = tokio::fs::metadata(&video_file_path)
+            .await
+            .context("could not get the size of the download")
+            .map_err(DownloaderError::Storage)?
+            .len();
+        if split_limits.fits_in_one_part(media_info.duration.unwrap_or(f64::MAX), size) {
+            info!("Video is short enough to be uploaded in one part, not splitting it");
+            keep_as_single_part(&video_file_path)
+                .await
+                .map_err(|source| DownloaderError::Split { video_id, source })?;
+        } else {
+            info!("Splitting video into parts");
+            split_video_into_parts_with_limits(video_file_path.to_path_buf(), &split_limits)
+                .await
+                .map_err(|source| DownloaderError::Split { video_id, source })?;
+        }
+        stage = VideoStage::Split;
+        save_stage(store, video, stage).await?;
+    }
+    let video_parts = find_video_parts(&video_file_path)
+        .await
+        .map_err(DownloaderError::Storage)?;
+
+    if shutdown.is_requested() {
+        return Err(DownloaderError::Interrupted);
+    }
+    if let Some(quiet_hours) = &settings.quiet_hours {
+        if matches!(stage, VideoStage::Split | VideoStage::Uploading)
+            && quiet_hours.contains(chrono::Utc::now())
+        {
+            info!(
+                "Quiet hours until {}, uploading video {} later",
+                quiet_hours.end, video_id
+            );
+            return Ok(BackupOutcome::Skipped(format!(
+                "quiet hours until {}",
+                quiet_hours.end
+            )));
+        }
+    }
+    if matches!(stage, VideoStage::Split | VideoStage::Uploading) {
+        let _upload_permit = limits.upload().await;
+        save_stage(store, video, VideoStage::Uploading).await?;
+        let destination_names: Vec<&str> = destinations.iter().map(|d| d.name.as_str()).collect();
+        let destination_names = destination_names.join(", ");
+        info!("Uploading video to {}", destination_names);
+        debug!("Video parts: {:?}", video_parts);
+        debug!("Video: {:?}", video);
+        debug!("Config: {:?}", config);
+        // the parts stay on disk if this fails, so the next attempt can upload them
+        upload_video_parts(
+            store,
+            &video_file_path,
+            &video_parts,
+            video,
+            destinations,
+            config,
+            settings,
+            shutdown,
+        )
+        .await
+        .map_err(|e| {
+            DownloaderError::or_wrap(e, |source| DownloaderError::Upload {
+                destination: destination_names,
+                source,
+            })
+        })?;
         info!(
             "Video uploaded successfully: {}: {}",
-            video.video.video_id,
+            video_id,
             video.video.title.as_ref().unwrap()
         );
-        video.metadata.backed_up = Some(true);
-        video
-            .metadata
-            .save()
+        stage = VideoStage::Uploaded;
+        save_stage(store, video, stage).await?;
+    }
+
+    if stage == VideoStage::Uploaded {
+        info!("Cleaning up video parts");
+        cleanup_video_parts(video_parts)
+            .await
+            .map_err(DownloaderError::Storage)?;
+        chapters::remove_sidecar(&video_file_path)
             .await
-            .map_err(|e| anyhow!("error saving backed up flag to metadata db: {}", e))?;
+            .map_err(DownloaderError::Storage)?;
+        video.metadata.backed_up = Some(true);
+        video.metadata.error = None;
+        video.metadata.error_code = None;
+        video.metadata.error_transient = None;
+        video.metadata.next_attempt_at = None;
+        save_stage(store, video, VideoStage::CleanedUp).await?;
     }
-    info!("Cleaning up video parts");
-    cleanup_video_parts(video_parts).await?;
     info!("Video backed up");
+    Ok(BackupOutcome::BackedUp)
+}
+
+/// check that the downloaded file is a complete video
+fn check_download(video: &VideoData, media_info: &MediaInfo, settings: &Settings) -> Result<()> {
+    if media_info.video_stream().is_none() {
+        return Err(anyhow!("the download has no video stream"));
+    }
+    if let (Some(duration), Some(tolerance)) = (
+        video.video.duration,
+        settings.download_duration_tolerance_seconds,
+    ) {
+        probe::check_duration(
+            media_info,
+            Duration::seconds(duration),
+            Duration::seconds(tolerance),
+        )?;
+    }
     Ok(())
 }
 
+/// Turn the video into the first part of a split, so it is found by
+/// [find_video_parts] like any other part.
+///
+/// mp4 files are only renamed, other containers (`.ts` from hls, `.mkv` from
+/// the inbox, ...) are copied into an mp4 like the split would do.
+pub async fn keep_as_single_part(video_file_path: &Path) -> Result<PathBuf> {
+    let file_stem = video_file_path
+        .file_stem()
+        .ok_or_else(|| anyhow!("the video has no file name: {:?}", video_file_path))?;
+    let part_path =
+        video_file_path.with_file_name(format!("{}_000.mp4", file_stem.to_string_lossy()));
+    let is_mp4 = video_file_path
+        .extension()
+        .map(|e| e.eq_ignore_ascii_case("mp4"))
+        .unwrap_or(false);
+    if is_mp4 {
+        debug!("Renaming {:?} to {:?}", video_file_path, part_path);
+        tokio::fs::rename(video_file_path, &part_path).await?;
+    } else {
+        debug!("Remuxing {:?} into {:?}", video_file_path, part_path);
+        ffmpeg::run_ffmpeg([
+            "-i".as_ref(),
+            video_file_path.as_os_str(),
+            "-c".as_ref(),
+            "copy".as_ref(),
+            "-map".as_ref(),
+            "0".as_ref(),
+            "-avoid_negative_ts".as_ref(),
+            "1".as_ref(),
+            part_path.as_os_str(),
+        ])
+        .await
+        .context("ffmpeg could not copy the video into an mp4")?;
+        tokio::fs::remove_file(video_file_path).await?;
+    }
+    Ok(part_path)
+}
+
+/// save the stage of the video so the backup can continue from there
+async fn save_stage(
+    store: &dyn Store,
+    video: &mut VideoData,
+    stage: VideoStage,
+) -> DownloaderResult<()> {
+    debug!(
+        "Video {} is now in stage: {}",
+        video.video.video_id,
+        stage.as_str()
+    );
+    video.metadata.set_stage(stage);
+    store
+        .save_video_metadata(&video.metadata)
+        .await
+        .context("could not save the stage of the video")
+        .map_err(DownloaderError::Storage)
+}
+
+/// get the stage the backup of a video should continue from
+///
+/// stages that need files on disk are only kept if those files are still
+/// there, failed videos continue after the last stage that left its files.
+pub async fn resume_stage(metadata: &VideoMetadata) -> Result<VideoStage> {
+    let stage = metadata.stage();
+    if !matches!(
+        stage,
+        VideoStage::Downloaded | VideoStage::Split | VideoStage::Uploading | VideoStage::Failed
+    ) {
+        return Ok(stage);
+    }
+    let download_path = match &metadata.download_path {
+        Some(download_path) => PathBuf::from(download_path),
+        None => return Ok(VideoStage::Discovered),
+    };
+    let downloaded = download_path.exists();
+    let split = !find_video_parts(&download_path).await?.is_empty();
+    let resumed = match stage {
+        VideoStage::Downloaded if downloaded => VideoStage::Downloaded,
+        VideoStage::Split | VideoStage::Uploading if split => stage,
+        // the split removes the downloaded file once it is done
+        VideoStage::Failed if downloaded => VideoStage::Downloaded,
+        VideoStage::Failed if split => VideoStage::Split,
+        _ => VideoStage::Discovered,
+    };
+    if resumed == VideoStage::Discovered && stage != VideoStage::Failed {
+        warn!(
+            "The files of video {} in stage {} are gone, starting over",
+            metadata.video_id,
+            stage.as_str()
+        );
+    }
+    Ok(resumed)
+}
+
+/// find the parts [split_video_into_parts] created for the video file, sorted by name
+pub async fn find_video_parts(video_file_path: &Path) -> Result<Vec<PathBuf>> {
+    let (parent_dir, file_stem) = match (video_file_path.parent(), video_file_path.file_stem()) {
+        (Some(parent_dir), Some(file_stem)) => (parent_dir, file_stem.to_string_lossy()),
+        _ => return Ok(vec![]),
+    };
+    if !parent_dir.exists() {
+        return Ok(vec![]);
+    }
+    let prefix = format!("{}_", file_stem);
+    let mut parts = vec![];
+    let mut entries = tokio::fs::read_dir(parent_dir).await?;
+    while let Some(entry) = entries.next_entry().await? {
+        let file_name = entry.file_name().to_string_lossy().to_string();
+        if file_name.starts_with(&prefix) && file_name.ends_with(".mp4") {
+            parts.push(entry.path());
+        }
+    }
+    parts.sort();
+    Ok(parts)
+}
+
 async fn cleanup_video_parts(video_parts: Vec<PathBuf>) -> Result<()> {
     trace!("cleanup video parts");
     for part in video_parts {
@@ -373,67 +940,606 @@ async fn cleanup_video_parts(video_parts: Vec<PathBuf>) -> Result<()> {
     Ok(())
 }
 
-async fn upload_video_to_youtube<'a>(
-    video_path: &Vec<PathBuf>,
-    mut video: &mut VideoData,
-    youtube_client: &YoutubeClient,
+#[allow(clippy::too_many_arguments)]
+async fn upload_video_parts(
+    store: &dyn Store,
+    video_file_path: &Path,
+    video_parts: &[PathBuf],
+    video: &mut VideoData,
+    destinations: &[VideoDestination],
     config: &Config,
+    settings: &Settings,
+    shutdown: &Shutdown,
 ) -> Result<()> {
-    trace!("upload video to youtube");
-    let part_count = video_path.len();
-    info!("Video has {} parts", part_count);
-    for (i, path) in video_path.iter().enumerate() {
-        info!("Uploading part {} of {}", i + 1, part_count);
-        let title = get_video_title_from_twitch_video(&video, i + 1, part_count)?;
-        info!("youtube part Title: {}", title);
-        let description =
-            get_video_description_from_twitch_video(&video, i + 1, part_count, &config)?;
-
-        let privacy = match video.streamer.public_videos_default {
-            Some(true) => PrivacyStatus::Public,
-            _ => PrivacyStatus::Private,
+    trace!("upload video parts");
+    let part_durations = get_part_durations(video_parts).await;
+    let part_chapters = match chapters::read_sidecar(video_file_path).await {
+        Ok(Some(video_chapters)) => chapters_of_parts(&video_chapters, &part_durations),
+        Ok(None) => vec![],
+        Err(e) => {
+            warn!("Could not read the chapters of the video: {:?}", e);
+            vec![]
+        }
+    };
+    let parts = video_parts
+        .iter()
+        .cloned()
+        .zip(get_part_infos(
+            video,
+            &part_durations,
+            &part_chapters,
+            config,
+            settings,
+        )?)
+        .collect::<Vec<_>>();
+    let collection_title = get_playlist_title_from_twitch_video(video)?;
+    upload_to_destinations(
+        store,
+        video,
+        &parts,
+        &collection_title,
+        destinations,
+        shutdown,
+    )
+    .await
+}
+
+/// the [PartInfo] of every part, with its length and the chapter it starts in
+pub fn get_part_infos(
+    video: &VideoData,
+    part_durations: &[Option<f64>],
+    part_chapters: &[Option<String>],
+    config: &Config,
+    settings: &Settings,
+) -> Result<Vec<PartInfo>> {
+    let part_count = part_durations.len();
+    let mut parts = Vec::with_capacity(part_count);
+    for (i, duration) in part_durations.iter().enumerate() {
+        let chapter = part_chapters.get(i).cloned().flatten();
+        let duration = duration.map(|d| d.round() as i64);
+        let mut part = get_part_info_from_twitch_video(video, i + 1, part_count, duration, config)?;
+        if settings.chapter_in_title {
+            part.title =
+                get_video_title_with_chapter(video, i + 1, part_count, chapter.as_deref())?;
+        }
+        part.chapter = chapter;
+        parts.push(part);
+    }
+    Ok(parts)
+}
+
+/// the length of every part in seconds, `None` for parts ffprobe can not read
+async fn get_part_durations(video_parts: &[PathBuf]) -> Vec<Option<f64>> {
+    let mut durations = Vec::with_capacity(video_parts.len());
+    for part in video_parts {
+        let duration = match probe::probe(part).await {
+            Ok(media_info) => media_info.duration,
+            Err(e) => {
+                warn!(
+                    "Could not get the length of part {}: {:?}",
+                    part.display(),
+                    e
+                );
+                None
+            }
         };
-        info!("Uploading video: {}", title);
-        info!("Description: {}", description);
-        info!(
-            "Privacy: {:?}",
-            match privacy {
-                PrivacyStatus::Public => "Public",
-                PrivacyStatus::Private => "Private",
-                PrivacyStatus::Unlisted => "Unlisted",
+        durations.push(duration);
+    }
+    durations
+}
+
+/// The chapter every part starts in.
+///
+/// The start of a part is the length of all parts before it, a part without
+/// a known length leaves the chapters of all parts after it unknown.
+pub fn chapters_of_parts(
+    video_chapters: &[Chapter],
+    part_durations: &[Option<f64>],
+) -> Vec<Option<String>> {
+    let mut part_chapters = Vec::with_capacity(part_durations.len());
+    let mut start = Some(0.0);
+    for duration in part_durations {
+        // a cut lands on the first keyframe after the chapter starts
+        let chapter = start.and_then(|start| chapters::chapter_at(video_chapters, start + 1.0));
+        part_chapters.push(chapter.map(|c| c.title.clone()));
+        start = match (start, duration) {
+            (Some(start), Some(duration)) => Some(start + duration),
+            _ => None,
+        };
+    }
+    part_chapters
+}
+
+/// Upload the parts to every destination that does not have them yet.
+///
+/// The state of every destination is saved in the store, so destinations that
+/// failed can be retried later without uploading everything again. Fails if
+/// one of the required destinations failed, optional ones are only logged.
+pub async fn upload_to_destinations(
+    store: &dyn Store,
+    video: &mut VideoData,
+    parts: &[(PathBuf, PartInfo)],
+    collection_title: &str,
+    destinations: &[VideoDestination],
+    shutdown: &Shutdown,
+) -> Result<()> {
+    let video_id = video.video.video_id;
+    let public = video.streamer.public_videos_default == Some(true);
+    let video_uploads = store.get_video_uploads(video_id).await?;
+    let mut failed = vec![];
+    for destination in destinations {
+        let mut video_upload = video_uploads
+            .iter()
+            .find(|u| u.destination.as_deref() == Some(destination.name.as_str()))
+            .cloned()
+            .unwrap_or_else(|| VideoUploads::new(video_id, &destination.name));
+        if video_upload.backed_up == Some(true) {
+            info!(
+                "Video {} is already uploaded to {}",
+                video_id, destination.name
+            );
+            continue;
+        }
+        if shutdown.is_requested() {
+            return Err(DownloaderError::Interrupted.into());
+        }
+        info!("Uploading video {} to {}", video_id, destination.name);
+        let res = upload_parts(
+            store,
+            destination.destination.as_ref(),
+            parts,
+            collection_title,
+            public,
+            shutdown,
+        )
+        .await;
+        match res {
+            Ok(collection) => {
+                video_upload.backed_up = Some(true);
+                video_upload.error = None;
+                video_upload.collection_url = collection.map(|c| c.url);
+                if destination.name == YOUTUBE_DESTINATION_NAME {
+                    video.metadata.youtube_playlist_url = video_upload.collection_url.clone();
+                }
+            }
+            Err(e) if matches!(e.downcast_ref(), Some(DownloaderError::Interrupted)) => {
+                return Err(e);
+            }
+            Err(e) => {
+                warn!(
+                    "Error uploading video {} to {}: {}",
+                    video_id, destination.name, e
+                );
+                video_upload.error = Some(format!("{:#}", e));
+                if destination.required {
+                    failed.push((destination.name.clone(), e));
+                }
+            }
+        }
+        video_upload.updated_at = Some(chrono::Utc::now());
+        store
+            .upsert_video_upload(&video_upload)
+            .await
+            .context("could not save the upload state")?;
+    }
+    if !failed.is_empty() {
+        return Err(failed_destinations_error(failed).into());
+    }
+    Ok(())
+}
+
+/// combine the errors of the required destinations that failed
+///
+/// a quota error is kept as it is, it decides when the next attempt makes sense
+fn failed_destinations_error(mut failed: Vec<(String, anyhow::Error)>) -> DownloaderError {
+    let quota = failed.iter().position(|(_, e)| {
+        matches!(
+            e.downcast_ref::<DownloaderError>(),
+            Some(DownloaderError::Quota { .. })
+        )
+    });
+    if let Some(quota) = quota {
+        let (_, e) = failed.swap_remove(quota);
+        return e
+            .downcast()
+            .expect("we just checked that it is a quota error");
+    }
+    let names: Vec<&str> = failed.iter().map(|(name, _)| name.as_str()).collect();
+    let destination = names.join(", ");
+    let source = match failed.len() {
+        1 => failed.remove(0).1,
+        _ => {
+            let errors: Vec<String> = failed
+                .iter()
+                .map(|(name, e)| format!("{}: {:#}", name, e))
+                .collect();
+            anyhow!("{}", errors.join("; "))
+        }
+    };
+    DownloaderError::Upload {
+        destination,
+        source,
+    }
+}
+
+/// Upload the parts to the destination and add all of them to one collection.
+///
+/// The state of every part is recorded in the store, so a failed upload
+/// resumes from the first part that is not at the destination yet instead of
+/// uploading the earlier parts again.
+/// The collection is only looked up once a part needs to be added to it, so
+/// nothing is created at the destination if the upload fails right away.
+/// After a shutdown was requested no new part is started.
+/// Returns `None` if there were no parts.
+pub async fn upload_parts(
+    store: &dyn Store,
+    destination: &dyn UploadDestination,
+    parts: &[(PathBuf, PartInfo)],
+    collection_title: &str,
+    public: bool,
+    shutdown: &Shutdown,
+) -> Result<Option<Collection>> {
+    let part_count = parts.len();
+    info!("Video has {} parts", part_count);
+    let mut video_parts = match parts.first() {
+        Some((_, part)) => store.get_video_parts(part.video_id).await?,
+        None => vec![],
+    };
+    video_parts.retain(|p| p.destination.as_deref() == Some(destination.name()));
+    let mut collection = None;
+    for (i, (path, part)) in parts.iter().enumerate() {
+        let part_number = part.part as i64;
+        let mut video_part = video_parts
+            .iter()
+            .find(|p| p.part == Some(part_number))
+            .cloned()
+            .unwrap_or_else(|| VideoParts::new(part.video_id, destination.name(), part_number));
+        let file_hash = hash_file(path).await?;
+        let already_uploaded = matches!(
+            video_part.status(),
+            PartStatus::Uploaded | PartStatus::InCollection
+        );
+        if already_uploaded
+            && video_part.file_hash.is_some()
+            && video_part.file_hash.as_deref() != Some(file_hash.as_str())
+        {
+            // uploading it again would only create a duplicate at the destination
+            warn!(
+                "Part {} of video {} changed since it was uploaded to {}, keeping the uploaded one",
+                part_number,
+                part.video_id,
+                destination.name()
+            );
+        }
+        video_part.file_hash = Some(file_hash);
+        video_part.duration = part.duration;
+
+        let uploaded = match video_part.status() {
+            PartStatus::InCollection => {
+                info!("Part {} of {} is already uploaded", i + 1, part_count);
+                continue;
+            }
+            PartStatus::Uploaded => {
+                info!(
+                    "Part {} of {} is already uploaded, adding it to the collection",
+                    i + 1,
+                    part_count
+                );
+                uploaded_part_from_video_part(&video_part)?
+            }
+            PartStatus::Pending | PartStatus::Failed => {
+                if shutdown.is_requested() {
+                    return Err(DownloaderError::Interrupted.into());
+                }
+                info!("Uploading part {} of {}", i + 1, part_count);
+                info!("Uploading video: {}", part.title);
+                info!("Description: {}", part.description);
+                info!("Public: {}", part.public);
+                video_part.set_status(PartStatus::Pending);
+                save_video_part(store, &video_part).await?;
+                let uploaded = match destination.upload_part(path, part).await {
+                    Ok(uploaded) => uploaded,
+                    Err(e) => {
+                        video_part.set_status(PartStatus::Failed);
+                        save_video_part(store, &video_part).await?;
+                        return Err(e);
+                    }
+                };
+                info!("Uploaded part {}: {}", i + 1, uploaded.url);
+                video_part.remote_id = Some(uploaded.id.clone());
+                video_part.url = Some(uploaded.url.clone());
+                video_part.uploaded_at = Some(chrono::Utc::now());
+                video_part.set_status(PartStatus::Uploaded);
+                save_video_part(store, &video_part).await?;
+                uploaded
             }
+        };
+
+        if collection.is_none() {
+            collection = Some(
+                destination
+                    .find_or_create_collection(collection_title, public)
+                    .await?,
+            );
+        }
+        let collection = collection.as_ref().expect("we just set it");
+        destination
+            .add_part_to_collection(&uploaded, collection)
+            .await?;
+        video_part.set_status(PartStatus::InCollection);
+        save_video_part(store, &video_part).await?;
+    }
+
+    if collection.is_none() && part_count > 0 {
+        // every part was already in the collection on an earlier run
+        collection = Some(
+            destination
+                .find_or_create_collection(collection_title, public)
+                .await?,
         );
+    }
+    Ok(collection)
+}
+
+async fn save_video_part(store: &dyn Store, video_part: &VideoParts) -> Result<()> {
+    store
+        .upsert_video_part(video_part)
+        .await
+        .context("could not save the state of the part")
+}
 
-        let youtube_video = youtube_client
-            .upload_video(
-                path,
-                &title,
-                &description,
-                config.youtube_tags.clone(),
-                privacy,
+fn uploaded_part_from_video_part(video_part: &VideoParts) -> Result<UploadedPart> {
+    match (&video_part.remote_id, &video_part.url) {
+        (Some(id), Some(url)) => Ok(UploadedPart {
+            id: id.clone(),
+            url: url.clone(),
+        }),
+        _ => Err(anyhow!(
+            "part {} is marked as uploaded but has no remote id or url",
+            video_part.id
+        )),
+    }
+}
+
+/// get the sha256 of a file as a hex string
+pub async fn hash_file(path: &Path) -> Result<String> {
+    let mut file = tokio::fs::File::open(path)
+        .await
+        .with_context(|| format!("could not open {} to hash it", path.display()))?;
+    let mut context = ring::digest::Context::new(&ring::digest::SHA256);
+    let mut buffer = vec![0; 1024 * 1024];
+    loop {
+        let read = file.read(&mut buffer).await?;
+        if read == 0 {
+            break;
+        }
+        context.update(&buffer[..read]);
+    }
+    Ok(context
+        .finish()
+        .as_ref()
+        .iter()
+        .map(|b| format!("{:02x}", b))
+        .collect())
+}
+
+/// get the title, description, tags and visibility of a single part
+///
+/// `duration` is the length of the part in seconds, it is only known once the
+/// video is split
+pub fn get_part_info_from_twitch_video(
+    video: &VideoData,
+    part: usize,
+    total_parts: usize,
+    duration: Option<i64>,
+    config: &Config,
+) -> Result<PartInfo> {
+    let title = get_video_title_from_twitch_video(video, part, total_parts)?;
+    info!("part Title: {}", title);
+    let description = get_video_description_from_twitch_video(video, part, total_parts, config)?;
+    Ok(PartInfo {
+        title,
+        description,
+        tags: config.youtube_tags.clone(),
+        public: video.streamer.public_videos_default == Some(true),
+        video_id: video.video.video_id,
+        streamer_login: video.streamer.login.clone(),
+        created_at: video.video.created_at,
+        part,
+        total_parts,
+        duration,
+        chapter: None,
+    })
+}
+
+/// The number of parts [split_video_into_parts] creates for a video of that
+/// length: one per soft cap, the last two are joined if they fit into the
+/// hard cap together.
+pub fn estimate_part_count(
+    duration: Duration,
+    duration_soft_cap: Duration,
+    duration_hard_cap: Duration,
+) -> usize {
+    estimate_part_durations(
+        duration.num_seconds().max(0) as f64,
+        &SplitLimits::from_durations(duration_soft_cap, duration_hard_cap),
+        None,
+        &[],
+    )
+    .map(|parts| parts.len())
+    .unwrap_or(1)
+}
+
+/// The length of every part [split_video_into_parts_with_limits] creates for
+/// a video of that length, without looking at the video.
+///
+/// The size of a part is estimated from the bit rate, silences and scene
+/// changes are expected right at the segment time.
+pub fn estimate_part_durations(
+    total_seconds: f64,
+    limits: &SplitLimits,
+    bit_rate: Option<u64>,
+    chapters: &[Chapter],
+) -> Result<Vec<f64>> {
+    let segment_time = limits.segment_time(bit_rate)?;
+    let bytes = |seconds: f64| (seconds * bit_rate.unwrap_or_default() as f64 / 8.0) as u64;
+    if limits.fits_in_one_part(total_seconds, bytes(total_seconds)) {
+        return Ok(vec![total_seconds]);
+    }
+    let cuts: Vec<f64> = match limits.split_points {
+        Some(points) => {
+            let chapters = match points.mode {
+                SplitPointMode::Chapters => chapters,
+                _ => &[],
+            };
+            split_points::chapter_split_points(
+                total_seconds,
+                segment_time,
+                limits.max_part_time(bit_rate)?,
+                chapters,
             )
-            .await
-            .map_err(|e| anyhow!("{}", e))?;
+        }
+        None => {
+            let step = segment_time.num_seconds() as f64;
+            (1..)
+                .map(|i| i as f64 * step)
+                .take_while(|cut| *cut < total_seconds)
+                .collect()
+        }
+    };
+    let mut durations = vec![];
+    let mut start = 0.0;
+    for cut in cuts.into_iter().chain(std::iter::once(total_seconds)) {
+        durations.push(cut - start);
+        start = cut;
+    }
+    if durations.len() >= 2 {
+        let joined = durations[durations.len() - 2] + durations[durations.len() - 1];
+        if limits.can_join(joined, bytes(joined)) {
+            durations.pop();
+            *durations.last_mut().unwrap() = joined;
+        }
+    }
+    Ok(durations)
+}
 
-        let playlist_title = get_playlist_title_from_twitch_video(&video)?;
-        let playlist = youtube_client
-            .find_playlist_or_create_by_name(&playlist_title, privacy)
-            .await
-            .map_err(|e| anyhow!("{}", e))?;
-        youtube_client
-            .add_video_to_playlist(&youtube_video, &playlist)
-            .await
-            .map_err(|e| anyhow!("{}", e))?;
-        video.metadata.youtube_playlist_url = playlist.id;
+/// the parts are planned a bit smaller than the size limit because ffmpeg
+/// can only cut at key frames and the bit rate is not the same everywhere
+const SPLIT_SIZE_SAFETY_MARGIN: f64 = 0.9;
+
+/// How long and how big the parts of a split video may get
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub struct SplitLimits {
+    /// the length the parts are cut at
+    pub duration_soft_cap: Option<Duration>,
+    /// the last two parts are only joined if they are shorter than this together
+    pub duration_hard_cap: Option<Duration>,
+    /// the largest file size of a part, needs the bit rate of the video
+    pub max_part_bytes: Option<u64>,
+    /// search for a silence or scene change near every cut instead of
+    /// cutting exactly at the segment time
+    pub split_points: Option<SplitPoints>,
+}
+
+impl SplitLimits {
+    pub fn from_durations(duration_soft_cap: Duration, duration_hard_cap: Duration) -> Self {
+        Self {
+            duration_soft_cap: Some(duration_soft_cap),
+            duration_hard_cap: Some(duration_hard_cap),
+            max_part_bytes: None,
+            split_points: None,
+        }
     }
 
-    Ok(())
+    /// the length of the segments ffmpeg cuts, the shorter of the soft cap
+    /// and the length that fits into the size limit at the bit rate
+    pub fn segment_time(&self, bit_rate: Option<u64>) -> Result<Duration> {
+        let by_size = self.duration_for_size(bit_rate)?;
+        let segment_time = match (self.duration_soft_cap, by_size) {
+            (Some(soft_cap), Some(by_size)) => soft_cap.min(by_size),
+            (Some(soft_cap), None) => soft_cap,
+            (None, Some(by_size)) => by_size,
+            (None, None) => {
+                return Err(DownloaderError::Config(
+                    "there is neither a duration nor a size limit for the parts".to_string(),
+                )
+                .into())
+            }
+        };
+        Ok(segment_time.max(Duration::seconds(1)))
+    }
+
+    /// the longest a part may be, the shorter of the hard cap and the length
+    /// that fits into the size limit at the bit rate
+    pub fn max_part_time(&self, bit_rate: Option<u64>) -> Result<Duration> {
+        let max_part_time = match (self.duration_hard_cap, self.duration_for_size(bit_rate)?) {
+            (Some(hard_cap), Some(by_size)) => hard_cap.min(by_size),
+            (Some(hard_cap), None) => hard_cap,
+            (None, Some(by_size)) => by_size,
+            (None, None) => self.segment_time(bit_rate)?,
+        };
+        Ok(max_part_time.max(Duration::seconds(1)))
+    }
+
+    fn duration_for_size(&self, bit_rate: Option<u64>) -> Result<Option<Duration>> {
+        match (self.max_part_bytes, bit_rate) {
+            (Some(max_part_bytes), Some(bit_rate)) if bit_rate > 0 => {
+                let seconds =
+                    max_part_bytes as f64 * 8.0 * SPLIT_SIZE_SAFETY_MARGIN / bit_rate as f64;
+                Ok(Some(Duration::seconds(seconds as i64)))
+            }
+            (Some(_), _) => Err(anyhow!("splitting by size needs the bit rate of the video")),
+            (None, _) => Ok(None),
+        }
+    }
+
+    /// if the whole video can be uploaded as a single part without splitting it
+    pub fn fits_in_one_part(&self, seconds: f64, bytes: u64) -> bool {
+        let fits_duration = match (self.duration_soft_cap, self.duration_hard_cap) {
+            (_, Some(hard_cap)) => seconds < hard_cap.num_seconds() as f64,
+            (Some(soft_cap), None) => seconds <= soft_cap.num_seconds() as f64,
+            (None, None) => true,
+        };
+        let fits_size = match self.max_part_bytes {
+            Some(max_part_bytes) => bytes <= max_part_bytes,
+            None => true,
+        };
+        fits_duration && fits_size
+    }
+
+    /// if the last two parts can be joined into one
+    pub fn can_join(&self, joined_seconds: f64, joined_bytes: u64) -> bool {
+        let fits_duration = match self.duration_hard_cap {
+            Some(hard_cap) => joined_seconds < hard_cap.num_seconds() as f64,
+            None => true,
+        };
+        let fits_size = match self.max_part_bytes {
+            Some(max_part_bytes) => joined_bytes <= max_part_bytes,
+            None => true,
+        };
+        fits_duration && fits_size
+    }
 }
 
 pub async fn split_video_into_parts(
     path: PathBuf,
     duration_soft_cap: Duration,
     duration_hard_cap: Duration,
+) -> Result<Vec<PathBuf>> {
+    split_video_into_parts_with_limits(
+        path,
+        &SplitLimits::from_durations(duration_soft_cap, duration_hard_cap),
+    )
+    .await
+}
+
+/// Split the video into parts that stay within the limits and remove the original.
+///
+/// The parts are cut at the [segment time](SplitLimits::segment_time), the
+/// last two parts are joined again if they [fit together](SplitLimits::can_join).
+pub async fn split_video_into_parts_with_limits(
+    path: PathBuf,
+    limits: &SplitLimits,
 ) -> Result<Vec<PathBuf>> {
     trace!("split video into parts");
     //region prepare paths
@@ -444,13 +1550,43 @@ pub async fn split_video_into_parts(
     }
     let parent_dir = parent_dir.expect("Could not canonicalize parent dir");
 
-    let file_playlist = clean(Path::join(&parent_dir, "output.m3u8"));
+    // named after the video, other videos can be split in the same folder at the same time
+    let file_stem = filepath
+        .file_stem()
+        .expect("could not get file_stem from path")
+        .to_string_lossy()
+        .to_string();
+    let file_playlist = clean(Path::join(
+        &parent_dir,
+        format!("{}_output.m3u8", file_stem),
+    ));
     //endregion
+    let media_info = if limits.max_part_bytes.is_some() || limits.split_points.is_some() {
+        Some(probe::probe(&filepath).await?)
+    } else {
+        None
+    };
+    let bit_rate = match limits.max_part_bytes {
+        Some(_) => Some(
+            media_info
+                .as_ref()
+                .and_then(|info| info.bit_rate)
+                .ok_or_else(|| {
+                    anyhow!(
+                        "ffprobe does not know the bit rate of {}",
+                        filepath.display()
+                    )
+                })?,
+        ),
+        None => None,
+    };
+    let segment_time = limits.segment_time(bit_rate)?;
     info!(
-        "Splitting video: {:?} into parts with soft cap duration: {} minutes and hard cap duration: {} minutes",
+        "Splitting video: {:?} into parts of {} seconds with limits: {:?} (bit rate: {:?})",
         filepath,
-        duration_soft_cap.num_minutes(),
-        duration_hard_cap.num_minutes()
+        segment_time.num_seconds(),
+        limits,
+        bit_rate
     );
 
     let output_path_pattern = Path::join(
@@ -468,46 +1604,75 @@ pub async fn split_video_into_parts(
     .expect("could not convert path to string")
     .to_string(); //TODO: maybe make the number of digits dynamic
     debug!("output path pattern: {}", output_path_pattern);
-    let duration_str = duration_to_string(&duration_soft_cap);
+    let duration_str = duration_to_string(&segment_time);
+    let total_seconds = media_info.as_ref().and_then(|info| info.duration);
+    let (segment_option, segment_value) = match (&limits.split_points, total_seconds) {
+        (Some(split_points), Some(total_seconds)) => {
+            let chapters = match chapters::read_sidecar(&filepath).await? {
+                Some(chapters) => chapters,
+                None => media_info
+                    .as_ref()
+                    .map(|info| info.chapters.clone())
+                    .unwrap_or_default(),
+            };
+            let cuts = find_split_points(
+                &filepath,
+                total_seconds,
+                segment_time,
+                limits.max_part_time(bit_rate)?,
+                split_points,
+                &chapters,
+            )
+            .await;
+            info!("Cutting the video at {:?} seconds", cuts);
+            if cuts.is_empty() {
+                ("-segment_time", duration_str)
+            } else {
+                let cuts: Vec<String> = cuts.iter().map(|c| format!("{:.3}", c)).collect();
+                ("-segment_times", cuts.join(","))
+            }
+        }
+        _ => ("-segment_time", duration_str),
+    };
 
     //region run ffmpeg split command
     //example: ffmpeg -i input.mp4 -c copy -map 0 -segment_time 00:20:00 -f segment output%03d.mp4
     debug!(
-        "Running ffmpeg command: ffmpeg -i {:?} -c copy -map 0 -segment_time {} -reset_timestamps 1 \
+        "Running ffmpeg command: ffmpeg -i {:?} -c copy -map 0 {} {} -reset_timestamps 1 \
          -segment_list {} -segment_list_type m3u8 -avoid_negative_ts 1 -f segment {}",
         filepath,
-        duration_str,
+        segment_option,
+        segment_value,
         file_playlist.display(),
         output_path_pattern
     );
-    Command::new("ffmpeg")
-        .args([
-            "-i",
-            filepath.to_str().unwrap(),
-            "-c",
-            "copy",
-            "-map",
-            "0",
-            "-segment_time",
-            &duration_str,
-            "-reset_timestamps",
-            "1",
-            "-segment_list",
-            file_playlist.to_str().unwrap(),
-            "-segment_list_type",
-            "m3u8",
-            "-avoid_negative_ts",
-            "1",
-            "-f",
-            "segment",
-            &output_path_pattern,
-        ])
-        .output()
-        .await?;
+    ffmpeg::run_ffmpeg([
+        "-i",
+        filepath.to_str().unwrap(),
+        "-c",
+        "copy",
+        "-map",
+        "0",
+        segment_option,
+        &segment_value,
+        "-reset_timestamps",
+        "1",
+        "-segment_list",
+        file_playlist.to_str().unwrap(),
+        "-segment_list_type",
+        "m3u8",
+        "-avoid_negative_ts",
+        "1",
+        "-f",
+        "segment",
+        &output_path_pattern,
+    ])
+    .await
+    .context("ffmpeg could not split the video")?;
     debug!("Finished running ffmpeg command");
     //endregion
 
-    //region extract parts from playlist file (create by ffmpeg 'output.m3u8')
+    //region extract parts from playlist file (create by ffmpeg '{stem}_output.m3u8')
     let (mut paths, second_last_time, last_time, second_last_path, last_path) =
         extract_track_info_from_playlist_file(&parent_dir, &file_playlist).await?;
     //endregion
@@ -517,18 +1682,21 @@ pub async fn split_video_into_parts(
     if let Some(second_last_path) = second_last_path {
         if let Some(last_path) = last_path {
             let joined_time = second_last_time + last_time;
+            let joined_bytes = tokio::fs::metadata(&second_last_path).await?.len()
+                + tokio::fs::metadata(&last_path).await?.len();
             let general_info = format!("second last part duration: {} seconds, \
-                    last part duration: {} seconds, joined duration: {} seconds (hard cap: {} seconds)",
-                    second_last_time, last_time, joined_time, duration_hard_cap.num_seconds());
-            if joined_time < duration_hard_cap.num_seconds() as f64 {
+                    last part duration: {} seconds, joined duration: {} seconds, joined size: {} bytes (limits: {:?})",
+                    second_last_time, last_time, joined_time, joined_bytes, limits);
+            if limits.can_join(joined_time, joined_bytes) {
                 //region join last two parts
                 info!("Joining last two parts. {}", general_info);
 
                 //remove the part from the result that is going to be joined
                 paths.pop();
 
-                let join_txt_path = Path::join(&parent_dir, "join.txt");
-                let join_mp4_path = Path::join(&parent_dir, "join.mp4");
+                let join_txt_path = Path::join(&parent_dir, format!("{}_join.txt", file_stem));
+                // not `{stem}_*.mp4`, that would be taken for a part by find_video_parts
+                let join_mp4_path = Path::join(&parent_dir, format!("{}.join.mp4", file_stem));
                 let second_last_path = clean(&second_last_path);
                 let second_last_path_str = second_last_path
                     .to_str()
@@ -553,34 +1721,32 @@ pub async fn split_video_into_parts(
                     "Running ffmpeg command: ffmpeg -f concat -safe 0 -i {:?} -c copy {:?}",
                     join_txt_path, join_mp4_path
                 );
-                Command::new("ffmpeg")
-                    .args([
-                        "-f",
-                        "concat",
-                        "-safe",
-                        "0",
-                        "-i",
-                        join_txt_path
-                            .to_str()
-                            .expect("to_str on join_txt_path did not work!"),
-                        "-c",
-                        "copy",
-                        join_mp4_path
-                            .to_str()
-                            .expect("to_str on join_mp4_path did not work!"),
-                    ])
-                    .output()
-                    .await?;
+                ffmpeg::run_ffmpeg([
+                    "-f",
+                    "concat",
+                    "-safe",
+                    "0",
+                    "-i",
+                    join_txt_path
+                        .to_str()
+                        .expect("to_str on join_txt_path did not work!"),
+                    "-c",
+                    "copy",
+                    join_mp4_path
+                        .to_str()
+                        .expect("to_str on join_mp4_path did not work!"),
+                ])
+                .await
+                .context("ffmpeg could not join the last two parts")?;
                 debug!("Finished running ffmpeg command");
                 //region remove files
                 debug!(
-                    "Removing files: {:?}, {:?}, {:?} {:?}",
-                    second_last_path, last_path, join_txt_path, file_playlist,
+                    "Removing files: {:?}, {:?}, {:?}",
+                    second_last_path, last_path, join_txt_path,
                 );
                 tokio::fs::remove_file(&second_last_path).await?;
                 tokio::fs::remove_file(&last_path).await?;
                 tokio::fs::remove_file(join_txt_path).await?;
-                tokio::fs::remove_file(file_playlist).await?;
                 //endregion
                 debug!(
                     "Renaming file: {:?} to {:?}",
@@ -600,6 +1766,7 @@ pub async fn split_video_into_parts(
     }
     //endregion
 
+    tokio::fs::remove_file(&file_playlist).await?;
     info!("removing the original file");
     tokio::fs::remove_file(&path).await?;
 
@@ -744,6 +1911,17 @@ pub fn get_video_title_from_twitch_video(
     video: &data::VideoData,
     part: usize,
     total_parts: usize,
+) -> Result<String> {
+    get_video_title_with_chapter(video, part, total_parts, None)
+}
+
+/// the title of a part with the name of the chapter it starts in after the
+/// title of the video, both are shortened together if they are too long
+pub fn get_video_title_with_chapter(
+    video: &data::VideoData,
+    part: usize,
+    total_parts: usize,
+    chapter: Option<&str>,
 ) -> Result<String> {
     trace!("get video title from twitch video");
     let prefix = match total_parts {
@@ -760,7 +1938,10 @@ pub fn get_video_title_from_twitch_video(
         .as_ref()
         .ok_or("Video has no title")
         .map_err(|e| anyhow!("{}", e))?;
-    let title = cap_long_title(title)?;
+    let title = match chapter {
+        Some(chapter) => cap_long_title(format!("{} | {}", title, chapter))?,
+        None => cap_long_title(title)?,
+    };
 
     let res = format!("{}{}", prefix, title);
     Ok(res)
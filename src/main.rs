@@ -2,9 +2,10 @@
 
 use std::error::Error;
 use std::fmt::Debug;
-use std::path::Path;
+use std::path::{Path, PathBuf};
 
 use anyhow::{anyhow, Result};
+use clap::{Parser, Subcommand};
 use downloader::prelude::*;
 use google_bigquery_v2::prelude::*;
 use google_youtube::scopes;
@@ -28,25 +29,147 @@ use twitch_data::{
 };
 
 use downloader::data::{Streamers, VideoMetadata};
-use downloader::start_backup;
+use downloader::dry_run::dry_run;
+use downloader::settings::Settings;
+use downloader::store::{create_store, Store};
+use downloader::{
+    admin, create_video_sources, split_video_into_parts_with_limits, start_backup, BackupOutcome,
+    Pipeline,
+};
+
+/// Backs up twitch vods (and other sources) to youtube and other destinations
+#[derive(Debug, Parser)]
+#[command(version, about)]
+struct Cli {
+    #[command(subcommand)]
+    command: Option<Command>,
+}
 
-//region constants
+#[derive(Debug, Subcommand)]
+enum Command {
+    /// run the backup forever (the default)
+    Run,
+    /// look for new videos and back up everything that is pending once
+    Once,
+    /// back up a single video right away, even if it failed before
+    Backup { video_id: i64 },
+    /// list the videos the next run would back up
+    ListPending,
+    /// remove the error of a failed video so it is tried again
+    ResetError { video_id: i64 },
+    /// manage the watched streamers
+    #[command(subcommand)]
+    Streamer(StreamerCommand),
+    /// split a video file into parts like the backup does
+    Split { file: PathBuf },
+    /// show what the next run would download and upload, without doing it
+    DryRun,
+}
 
-const SERVICE_ACCOUNT_PATH: &str = "auth/bigquery_service_account.json";
-const YOUTUBE_CLIENT_SECRET: &str = "auth/youtube_client_secret.json";
-const PROJECT_ID: &str = "twitchbackup-v1";
-const DATASET_ID: &str = "backup_data";
-//endregion
+#[derive(Debug, Subcommand)]
+enum StreamerCommand {
+    /// add a streamer and start watching it
+    Add {
+        login: String,
+        /// the youtube account the videos are uploaded to
+        #[arg(long)]
+        youtube_user: Option<String>,
+        /// make the uploaded videos public
+        #[arg(long)]
+        public: Option<bool>,
+    },
+    /// delete a streamer, its videos are kept (the bigquery store can only unwatch it)
+    Remove { login: String },
+    /// start looking for new videos of the streamer
+    Watch { login: String },
+    /// stop looking for new videos of the streamer
+    Unwatch { login: String },
+}
 
 #[tokio::main]
 async fn main() -> Result<(), Box<dyn Error>> {
+    let cli = Cli::parse();
     // initialize_logger2().await;
     let _guards = initialize_logger3().await;
     info!("Hello, world!");
-    start_backup().await?;
-    // sample().await?;
+    match cli.command.unwrap_or(Command::Run) {
+        Command::Run => start_backup().await?,
+        Command::Once => Pipeline::create().await?.run_iteration().await?,
+        Command::Backup { video_id } => {
+            match Pipeline::create().await?.backup_video(video_id).await? {
+                BackupOutcome::BackedUp => println!("video {} is backed up", video_id),
+                BackupOutcome::Skipped(reason) => {
+                    println!("video {} was not backed up: {}", video_id, reason)
+                }
+                BackupOutcome::Failed(e) => return Err(e.into()),
+            }
+        }
+        Command::ListPending => {
+            let store = open_store().await?;
+            for video in admin::pending_videos(store.as_ref()).await? {
+                println!(
+                    "{}\t{}\t{}\t{}\t{}",
+                    video.video.video_id,
+                    video.streamer.login,
+                    video.metadata.stage().as_str(),
+                    video.metadata.retry_count.unwrap_or(0),
+                    video.video.title.unwrap_or_default()
+                );
+            }
+        }
+        Command::ResetError { video_id } => {
+            let store = open_store().await?;
+            admin::reset_video_error(store.as_ref(), video_id).await?;
+        }
+        Command::Streamer(command) => {
+            let store = open_store().await?;
+            let store = store.as_ref();
+            match command {
+                StreamerCommand::Add {
+                    login,
+                    youtube_user,
+                    public,
+                } => {
+                    admin::add_streamer(store, &login, youtube_user, public).await?;
+                }
+                StreamerCommand::Remove { login } => admin::remove_streamer(store, &login).await?,
+                StreamerCommand::Watch { login } => {
+                    admin::set_streamer_watched(store, &login, true).await?
+                }
+                StreamerCommand::Unwatch { login } => {
+                    admin::set_streamer_watched(store, &login, false).await?
+                }
+            }
+        }
+        Command::Split { file } => {
+            let config = downloader_config::load_config();
+            let settings = Settings::load()?;
+            let parts =
+                split_video_into_parts_with_limits(file, &settings.split_limits(&config)).await?;
+            for part in parts {
+                println!("{}", part.display());
+            }
+        }
+        Command::DryRun => {
+            let config = downloader_config::load_config();
+            let settings = Settings::load()?;
+            let store = create_store(&config, &settings).await?;
+            let sources = create_video_sources(&settings).await?;
+            let report = dry_run(store.as_ref(), &sources, &config, &settings).await?;
+            print!("{}", report);
+        }
+    }
     Ok(())
 }
+
+/// connect only to the store, for the commands that do not need the sources
+/// and destinations
+async fn open_store() -> anyhow::Result<Box<dyn Store>> {
+    let config = downloader_config::load_config();
+    let settings = Settings::load()?;
+    create_store(&config, &settings).await
+}
+
 async fn initialize_logger3() -> Result<(WorkerGuard, WorkerGuard, WorkerGuard), Box<dyn Error>> {
     let (info_daily, guard_info_daily) =
         tracing_appender::non_blocking(rolling::daily("/downloader/logs", "info.log"));
@@ -174,119 +297,3 @@ async fn initialize_logger() -> Result<(), Box<dyn Error>> {
     log_panics::init();
     Ok(())
 }
-
-pub async fn sample() -> Result<(), Box<dyn Error>> {
-    info!("Hello from the downloader lib!");
-
-    let client = BigqueryClient::new(PROJECT_ID, DATASET_ID, Some(SERVICE_ACCOUNT_PATH)).await?;
-    sample_bigquery(&client).await?;
-
-    /*
-        let youtube_client = YoutubeClient::new(Some(YOUTUBE_CLIENT_SECRET),
-                                                vec![scopes::YOUTUBE_UPLOAD, scopes::YOUTUBE_READONLY, scopes::YOUTUBE],
-                                                Some("NopixelVODs")).await?;
-
-        sample_youtube(&youtube_client).await?;
-    */
-    /*
-    let twitch_client = get_client().await?;
-    sample_twitch(&twitch_client).await?;
-    */
-    Ok(())
-}
-
-async fn sample_twitch<'a>(client: &TwitchClient<'a>) -> Result<(), Box<dyn Error>> {
-    info!("\n\nGetting videos...");
-
-    let res = client.get_channel_info_from_login("burn").await?;
-    info!("got channel info: {:?}", res);
-    let channel_id = res.unwrap().broadcaster_id;
-
-    let videos: Vec<Video> = client
-        .get_videos_from_channel(&channel_id, 50)
-        .await?
-        .into_iter()
-        .map(convert_twitch_video_to_twitch_data_video)
-        .collect();
-
-    info!("got video ids: {:?}", videos.len());
-    for (i, video) in videos.iter().enumerate() {
-        info!("+======={:2}: {:?}", i, video);
-    }
-
-    info!("\n\nGetting video for short download...");
-    let short_video_id = twitch_data::VideoId::new("1710229470".to_string());
-    let video_info = client.get_video_info(&short_video_id).await?;
-    info!("got video info: {:?}", video_info);
-
-    let output_folder = Path::new("C:\\tmp\\videos\\");
-    let res = client
-        .download_video_by_id(&video_info.id, &VideoQuality::Source, output_folder)
-        .await?;
-    info!("downloaded video: {:?}", res);
-
-    info!("\n\nDone!");
-    Ok(())
-}
-
-async fn sample_bigquery<'a>(client: &'a BigqueryClient) -> Result<(), Box<dyn Error>> {
-    // let x = VideoMetadata::from_pk(&client, 1638184921).await?;
-    // let video_metadata = VideoMetadata::create_and_load_from_pk(&client, 1638184921).await?;
-    // info!("got video_metadata by id: {:?}", video_metadata);
-
-    let video_metadata = VideoMetadata::select()
-        .with_client(client.clone())
-        .add_where_eq(name_of!(backed_up in VideoMetadata), Some(&true))
-        .map_err(|e| anyhow!("{}", e))?
-        .set_limit(10)
-        .build_query()
-        .map_err(|e| anyhow!("{}", e))?
-        .run()
-        .await
-        .map_err(|e| anyhow!("{}", e))?
-        .map_err_with_data("Select has to return data")
-        .map_err(|e| anyhow!("{}", e))?;
-    print_vec_sample("got video_metadata by backed_up:", video_metadata);
-
-    let watched_streamers = Streamers::select()
-        .with_client(client.clone())
-        .add_where_eq(name_of!(watched in Streamers), Some(&true))
-        .map_err(|e| anyhow!("{}", e))?
-        .set_limit(100)
-        .build_query()
-        .map_err(|e| anyhow!("{}", e))?
-        .run()
-        .await
-        .map_err(|e| anyhow!("{}", e))?
-        .map_err_with_data("Select has to return data")
-        .map_err(|e| anyhow!("{}", e))?;
-    print_vec_sample("got watched_streamers:", watched_streamers);
-
-    fn print_vec_sample<T: Debug>(message: &str, watched_streamers: Vec<T>) {
-        info!("{} {:?}", message, watched_streamers.len());
-        for (i, streamer) in watched_streamers.iter().enumerate() {
-            info!("+======={}: {:?}", i, streamer);
-        }
-    }
-    Ok(())
-}
-
-async fn sample_youtube(client: &YoutubeClient) -> Result<(), Box<dyn Error>> {
-    info!("Opening video file...");
-    let file = Path::new("C:\\Users\\frede\\Videos\\test.mp4");
-    // let file = File::open(file).await?;
-
-    let description = "test video description";
-    let title = "test video2";
-    let tags = vec!["test".to_string(), "test2".to_string()];
-    let privacy_status = google_youtube::PrivacyStatus::Private;
-
-    info!("Uploading video...");
-    let video = &client
-        .upload_video(file, title, description, tags, privacy_status)
-        .await?;
-
-    info!("video: \n\n{:?}\n\n", video);
-
-    Ok(())
-}
@@ -0,0 +1,199 @@
+use std::path::Path;
+
+use anyhow::{anyhow, Context, Result};
+use chrono::Duration;
+use serde::Deserialize;
+
+use crate::chapters::Chapter;
+use crate::ffmpeg;
+use crate::prelude::*;
+
+/// What ffprobe knows about a media file
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct MediaInfo {
+    /// the names of the container format, like `mov,mp4,m4a,3gp,3g2,mj2`
+    pub format_name: Option<String>,
+    /// the length in seconds
+    pub duration: Option<f64>,
+    /// the size of the file in bytes
+    pub size: Option<u64>,
+    /// the overall bit rate in bits per second
+    pub bit_rate: Option<u64>,
+    pub streams: Vec<StreamInfo>,
+    /// the chapters stored in the file, sorted by their start
+    pub chapters: Vec<Chapter>,
+}
+
+/// A single stream of a media file
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct StreamInfo {
+    pub index: u32,
+    /// `video`, `audio`, `subtitle`, `data`, ...
+    pub codec_type: Option<String>,
+    pub codec_name: Option<String>,
+    pub width: Option<u32>,
+    pub height: Option<u32>,
+    pub bit_rate: Option<u64>,
+}
+
+impl MediaInfo {
+    /// parse the json written by `ffprobe -print_format json -show_format -show_streams -show_chapters`
+    pub fn from_ffprobe_json(json: &str) -> Result<Self> {
+        let output: FfprobeOutput =
+            serde_json::from_str(json).context("could not parse the output of ffprobe")?;
+        let format = output.format.unwrap_or_default();
+        Ok(Self {
+            format_name: format.format_name,
+            duration: parse_number(format.duration.as_deref()),
+            size: parse_number(format.size.as_deref()),
+            bit_rate: parse_number(format.bit_rate.as_deref()),
+            streams: output
+                .streams
+                .into_iter()
+                .map(|stream| StreamInfo {
+                    index: stream.index,
+                    codec_type: stream.codec_type,
+                    codec_name: stream.codec_name,
+                    width: stream.width,
+                    height: stream.height,
+                    bit_rate: parse_number(stream.bit_rate.as_deref()),
+                })
+                .collect(),
+            chapters: output
+                .chapters
+                .into_iter()
+                .enumerate()
+                .filter_map(|(i, chapter)| {
+                    Some(Chapter {
+                        start: parse_number(chapter.start_time.as_deref())?,
+                        title: chapter
+                            .tags
+                            .and_then(|t| t.title)
+                            .unwrap_or_else(|| format!("Chapter {}", i + 1)),
+                    })
+                })
+                .collect(),
+        })
+    }
+
+    /// the length of the file, rounded down to whole seconds
+    pub fn duration(&self) -> Option<Duration> {
+        self.duration
+            .map(|seconds| Duration::seconds(seconds as i64))
+    }
+
+    /// the first video stream
+    pub fn video_stream(&self) -> Option<&StreamInfo> {
+        self.stream_of_type("video")
+    }
+
+    /// the first audio stream
+    pub fn audio_stream(&self) -> Option<&StreamInfo> {
+        self.stream_of_type("audio")
+    }
+
+    pub fn video_codec(&self) -> Option<&str> {
+        self.video_stream()?.codec_name.as_deref()
+    }
+
+    pub fn audio_codec(&self) -> Option<&str> {
+        self.audio_stream()?.codec_name.as_deref()
+    }
+
+    /// width and height of the first video stream
+    pub fn resolution(&self) -> Option<(u32, u32)> {
+        let stream = self.video_stream()?;
+        Some((stream.width?, stream.height?))
+    }
+
+    fn stream_of_type(&self, codec_type: &str) -> Option<&StreamInfo> {
+        self.streams
+            .iter()
+            .find(|s| s.codec_type.as_deref() == Some(codec_type))
+    }
+}
+
+/// run ffprobe on the file and read its format, streams and chapters
+pub async fn probe(path: &Path) -> Result<MediaInfo> {
+    let output = ffmpeg::run_program(
+        "ffprobe",
+        [
+            "-v".as_ref(),
+            "error".as_ref(),
+            "-print_format".as_ref(),
+            "json".as_ref(),
+            "-show_format".as_ref(),
+            "-show_streams".as_ref(),
+            "-show_chapters".as_ref(),
+            path.as_os_str(),
+        ],
+    )
+    .await
+    .with_context(|| format!("could not probe {}", path.display()))?;
+    let info = MediaInfo::from_ffprobe_json(&output.stdout)
+        .with_context(|| format!("could not probe {}", path.display()))?;
+    debug!("Probed {}: {:?}", path.display(), info);
+    Ok(info)
+}
+
+/// Check that the file is as long as the source said the video is.
+///
+/// Downloads that were cut short by a dropped connection still end up as a
+/// playable file, this is the only way to notice them.
+pub fn check_duration(info: &MediaInfo, expected: Duration, tolerance: Duration) -> Result<()> {
+    let actual = info
+        .duration
+        .ok_or_else(|| anyhow!("the file has no duration"))?;
+    let difference = (actual - expected.num_seconds() as f64).abs();
+    if difference > tolerance.num_seconds() as f64 {
+        return Err(anyhow!(
+            "the file is {:.0} seconds long, but the video should be {} seconds long",
+            actual,
+            expected.num_seconds()
+        ));
+    }
+    Ok(())
+}
+
+/// ffprobe writes most numbers as strings and `N/A` if it does not know them
+fn parse_number<T: std::str::FromStr>(value: Option<&str>) -> Option<T> {
+    value?.trim().parse().ok()
+}
+
+#[derive(Debug, Deserialize)]
+struct FfprobeOutput {
+    #[serde(default)]
+    streams: Vec<FfprobeStream>,
+    format: Option<FfprobeFormat>,
+    #[serde(default)]
+    chapters: Vec<FfprobeChapter>,
+}
+
+#[derive(Debug, Default, Deserialize)]
+struct FfprobeFormat {
+    format_name: Option<String>,
+    duration: Option<String>,
+    size: Option<String>,
+    bit_rate: Option<String>,
+}
+
+#[derive(Debug, Deserialize)]
+struct FfprobeStream {
+    index: u32,
+    codec_type: Option<String>,
+    codec_name: Option<String>,
+    width: Option<u32>,
+    height: Option<u32>,
+    bit_rate: Option<String>,
+}
+
+#[derive(Debug, Deserialize)]
+struct FfprobeChapter {
+    start_time: Option<String>,
+    tags: Option<FfprobeTags>,
+}
+
+#[derive(Debug, Deserialize)]
+struct FfprobeTags {
+    title: Option<String>,
+}
@@ -0,0 +1,198 @@
+use chrono::{DateTime, Duration, Utc};
+
+use crate::data::VideoMetadata;
+use crate::error::DownloaderError;
+use crate::ffmpeg::FfmpegError;
+use crate::prelude::*;
+
+/// If trying again can fix a failed backup
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ErrorKind {
+    /// network problems, quota, server errors, ...
+    Transient,
+    /// the video is gone or can not be accessed, trying again will not help
+    Permanent,
+}
+
+/// words in error messages that mean a limit was hit that resets by itself,
+/// checked before [PERMANENT_MARKERS] because a quota error is a 403 on youtube
+const QUOTA_MARKERS: &[&str] = &["quota", "quotaexceeded", "rate limit", "too many requests"];
+
+/// words in error messages that mean the video can not be backed up at all
+const PERMANENT_MARKERS: &[&str] = &[
+    "deleted",
+    "sub-only",
+    "subscriber",
+    "subscribers",
+    "subscription",
+    "not found",
+    "forbidden",
+];
+
+/// words in error messages that mean the error goes away by itself
+const TRANSIENT_MARKERS: &[&str] = &[
+    "timed out",
+    "timeout",
+    "temporarily",
+    "connection reset",
+    "connection refused",
+    "connection closed",
+    "broken pipe",
+    "network is unreachable",
+    // reqwest errors that were turned into strings by the apis of the sources
+    "error sending request",
+    "error trying to connect",
+    "dns error",
+];
+
+/// decide if a failed backup should be retried
+///
+/// Looks at the known error types in the chain first and falls back to the
+/// messages: an http status in them, then whole words, the ones of permanent
+/// errors first. Unknown errors count as permanent, so they are looked at
+/// instead of being retried until the attempts run out, the error can be
+/// [reset](crate::admin::reset_video_error) once it is fixed.
+pub fn classify_error(error: &anyhow::Error) -> ErrorKind {
+    for cause in error.chain() {
+        if let Some(e) = cause.downcast_ref::<DownloaderError>() {
+            match e {
+                DownloaderError::Quota { .. } | DownloaderError::BrokenDownload { .. } => {
+                    return ErrorKind::Transient
+                }
+                DownloaderError::Config(_) | DownloaderError::NotFound(_) => {
+                    return ErrorKind::Permanent
+                }
+                // decided by the source, which is next in the chain
+                _ => {}
+            }
+        }
+        if let Some(e) = cause.downcast_ref::<FfmpegError>() {
+            return e.kind();
+        }
+        if let Some(e) = cause.downcast_ref::<reqwest::Error>() {
+            if e.is_timeout() || e.is_connect() {
+                return ErrorKind::Transient;
+            }
+            if let Some(status) = e.status() {
+                return classify_status(status.as_u16());
+            }
+        }
+        // missing tools, full disks, ... are fixed by whoever runs the downloader
+        if cause.downcast_ref::<std::io::Error>().is_some() {
+            return ErrorKind::Transient;
+        }
+    }
+    let message = format!("{:#}", error).to_lowercase();
+    if QUOTA_MARKERS.iter().any(|m| contains_word(&message, m)) {
+        ErrorKind::Transient
+    } else if let Some(status) = status_in_message(&message) {
+        classify_status(status)
+    } else if PERMANENT_MARKERS.iter().any(|m| contains_word(&message, m)) {
+        ErrorKind::Permanent
+    } else if TRANSIENT_MARKERS.iter().any(|m| contains_word(&message, m)) {
+        ErrorKind::Transient
+    } else {
+        ErrorKind::Permanent
+    }
+}
+
+/// if the word (or words) is in the message and not just a part of a longer word
+fn contains_word(message: &str, word: &str) -> bool {
+    message.match_indices(word).any(|(start, _)| {
+        let before = message[..start].chars().next_back();
+        let after = message[start + word.len()..].chars().next();
+        !before.is_some_and(char::is_alphanumeric) && !after.is_some_and(char::is_alphanumeric)
+    })
+}
+
+/// the http status in messages like `got status 503` or `status code: 404`
+fn status_in_message(message: &str) -> Option<u16> {
+    let words: Vec<&str> = message
+        .split(|c: char| !c.is_alphanumeric())
+        .filter(|w| !w.is_empty())
+        .collect();
+    words.windows(2).find_map(|pair| match pair {
+        ["status", number] | ["code", number] => number
+            .parse()
+            .ok()
+            .filter(|status| (100..=599).contains(status)),
+        _ => None,
+    })
+}
+
+fn classify_status(status: u16) -> ErrorKind {
+    match status {
+        408 | 429 | 500..=599 => ErrorKind::Transient,
+        _ => ErrorKind::Permanent,
+    }
+}
+
+/// How often and how fast failed backups are retried
+#[derive(Debug, Clone)]
+pub struct RetryPolicy {
+    /// how often a video is tried in total before it is parked
+    pub max_attempts: u32,
+    /// the wait after the first failure, doubled for every further failure
+    pub base_delay: Duration,
+    /// the longest wait between two attempts
+    pub max_delay: Duration,
+}
+
+impl Default for RetryPolicy {
+    fn default() -> Self {
+        Self {
+            max_attempts: 5,
+            base_delay: Duration::minutes(10),
+            max_delay: Duration::hours(24),
+        }
+    }
+}
+
+impl RetryPolicy {
+    /// the wait before the next attempt after `failures` failed attempts
+    pub fn delay(&self, failures: u32) -> Duration {
+        let mut delay = self.base_delay;
+        // stop doubling at the max, a large base delay would overflow otherwise
+        for _ in 0..failures.saturating_sub(1).min(30) {
+            if delay >= self.max_delay {
+                break;
+            }
+            delay = delay.checked_add(&delay).unwrap_or(self.max_delay);
+        }
+        delay.min(self.max_delay)
+    }
+
+    /// record a failed attempt in the metadata and schedule the next one
+    ///
+    /// permanent errors and videos that ran out of attempts are not scheduled
+    /// again and stay parked until the error is removed by hand
+    pub fn record_failure(
+        &self,
+        metadata: &mut VideoMetadata,
+        kind: ErrorKind,
+        now: DateTime<Utc>,
+    ) {
+        let failures = metadata.retry_count.unwrap_or(0) + 1;
+        metadata.retry_count = Some(failures);
+        let failures = failures.max(0) as u32;
+        if kind == ErrorKind::Transient && failures < self.max_attempts {
+            let next_attempt_at = now + self.delay(failures);
+            info!(
+                "Retrying video {} at {} (attempt {} of {})",
+                metadata.video_id,
+                next_attempt_at,
+                failures + 1,
+                self.max_attempts
+            );
+            metadata.error_transient = Some(true);
+            metadata.next_attempt_at = Some(next_attempt_at);
+        } else {
+            warn!(
+                "Not retrying video {} after {} attempts ({:?} error)",
+                metadata.video_id, failures, kind
+            );
+            metadata.error_transient = Some(false);
+            metadata.next_attempt_at = None;
+        }
+    }
+}
@@ -0,0 +1,152 @@
+use std::str::FromStr;
+use std::sync::Arc;
+
+use anyhow::{anyhow, Context, Result};
+use chrono::{DateTime, Duration, NaiveTime, Utc};
+use rand::Rng;
+use tokio::sync::Notify;
+
+use crate::prelude::*;
+
+/// When the main loop runs the next time.
+///
+/// Runs are either a fixed interval apart or follow a cron expression, both
+/// with an optional random delay on top so multiple instances do not all hit
+/// the APIs at the same moment. All times are in UTC.
+#[derive(Debug, Clone)]
+pub struct Schedule {
+    /// the wait between two runs if there is no cron expression
+    pub interval: Duration,
+    pub cron: Option<cron::Schedule>,
+    /// the longest random delay that is added to every wait
+    pub jitter: Duration,
+}
+
+impl Default for Schedule {
+    fn default() -> Self {
+        Self {
+            interval: Duration::hours(3),
+            cron: None,
+            jitter: Duration::zero(),
+        }
+    }
+}
+
+impl Schedule {
+    /// parse a cron expression with either the usual five fields
+    /// (`minute hour day month weekday`) or with seconds and an optional year
+    pub fn parse_cron(expression: &str) -> Result<cron::Schedule> {
+        let expression = expression.trim();
+        let expression = if expression.split_whitespace().count() == 5 {
+            format!("0 {}", expression)
+        } else {
+            expression.to_string()
+        };
+        cron::Schedule::from_str(&expression)
+            .map_err(|e| anyhow!("{}", e))
+            .with_context(|| format!("invalid cron expression: '{}'", expression))
+    }
+
+    /// the time of the next run after `now`, without the jitter
+    pub fn next_run(&self, now: DateTime<Utc>) -> DateTime<Utc> {
+        match &self.cron {
+            Some(cron) => match cron.after(&now).next() {
+                Some(next) => next,
+                None => {
+                    warn!("the cron schedule has no upcoming run, using the interval");
+                    now + self.interval
+                }
+            },
+            None => now + self.interval,
+        }
+    }
+
+    /// a random delay between zero and the jitter
+    pub fn random_jitter(&self) -> Duration {
+        let max = self.jitter.num_seconds();
+        if max <= 0 {
+            return Duration::zero();
+        }
+        Duration::seconds(rand::thread_rng().gen_range(0..=max))
+    }
+}
+
+/// A daily time range in which nothing is uploaded, `22:00-06:00` wraps
+/// around midnight. The times are in UTC.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct QuietHours {
+    pub start: NaiveTime,
+    pub end: NaiveTime,
+}
+
+impl QuietHours {
+    pub fn contains(&self, now: DateTime<Utc>) -> bool {
+        let time = now.time();
+        if self.start <= self.end {
+            self.start <= time && time < self.end
+        } else {
+            time >= self.start || time < self.end
+        }
+    }
+}
+
+impl FromStr for QuietHours {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        let (start, end) = s
+            .split_once('-')
+            .ok_or_else(|| anyhow!("quiet hours have to look like '22:00-06:00': '{}'", s))?;
+        let parse = |time: &str| {
+            let time = time.trim();
+            NaiveTime::parse_from_str(time, "%H:%M")
+                .with_context(|| format!("invalid time in quiet hours: '{}'", time))
+        };
+        Ok(Self {
+            start: parse(start)?,
+            end: parse(end)?,
+        })
+    }
+}
+
+/// Starts the next run of the main loop right away instead of waiting for
+/// the schedule.
+///
+/// A trigger while a run is going on starts another one as soon as it is done.
+#[derive(Debug, Clone, Default)]
+pub struct Trigger {
+    notify: Arc<Notify>,
+}
+
+impl Trigger {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn trigger(&self) {
+        info!("Immediate run triggered");
+        self.notify.notify_one();
+    }
+
+    /// wait until a run is triggered
+    pub async fn triggered(&self) {
+        self.notify.notified().await;
+    }
+
+    /// trigger a run on SIGUSR1, for example with `docker kill --signal=SIGUSR1`
+    pub fn listen_for_signals(&self) -> Result<()> {
+        #[cfg(unix)]
+        {
+            let mut user_signal =
+                tokio::signal::unix::signal(tokio::signal::unix::SignalKind::user_defined1())?;
+            let trigger = self.clone();
+            tokio::spawn(async move {
+                while user_signal.recv().await.is_some() {
+                    info!("Got SIGUSR1");
+                    trigger.trigger();
+                }
+            });
+        }
+        Ok(())
+    }
+}
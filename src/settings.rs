@@ -0,0 +1,433 @@
+use std::fmt::Debug;
+use std::str::FromStr;
+
+use anyhow::{anyhow, Result};
+use downloader_config::Config;
+
+use crate::destination::local::DEFAULT_ARCHIVE_LAYOUT;
+use crate::destination::S3Config;
+use crate::error::DownloaderError;
+use crate::prelude::*;
+use crate::retry::RetryPolicy;
+use crate::schedule::{QuietHours, Schedule};
+use crate::split_points::{SplitPointMode, SplitPoints};
+use crate::workers::StageLimits;
+use crate::SplitLimits;
+
+/// Settings that are not part of [downloader_config::Config].
+///
+/// They are read from environment variables, the same way the rest of the
+/// config is passed to the container. Every setting has a default that keeps
+/// the previous behaviour.
+#[derive(Debug, Clone)]
+pub struct Settings {
+    /// `STORE_BACKEND`: which database to use (`bigquery` or `sqlite`)
+    pub store_backend: StoreBackend,
+    /// `SQLITE_DB_PATH`: path of the database file for the sqlite backend
+    pub sqlite_db_path: String,
+    /// `LOCAL_INBOX_PATH`: folder that is checked for manually dropped recordings,
+    /// the local source is disabled if this is not set
+    pub local_inbox_path: Option<String>,
+    /// `HLS_PLAYLISTS_FILE`: json file with the HLS playlists to archive,
+    /// the hls source is disabled if this is not set
+    pub hls_playlists_file: Option<String>,
+    /// `HLS_MAX_BANDWIDTH`: the highest bandwidth of a variant that is downloaded
+    pub hls_max_bandwidth: Option<u64>,
+    /// `HLS_SEGMENT_RETRIES`: how often a failed segment download is retried
+    pub hls_segment_retries: u32,
+    /// `LOCAL_ARCHIVE_PATH`: root folder of the local archive destination
+    pub local_archive_path: Option<String>,
+    /// `LOCAL_ARCHIVE_LAYOUT`: where the parts are put inside the local archive,
+    /// see [LocalArchiveDestination](crate::destination::LocalArchiveDestination)
+    pub local_archive_layout: String,
+    /// `LOCAL_ARCHIVE_STREAMERS`: comma separated logins of the streamers that are
+    /// backed up to the local archive instead of youtube
+    pub local_archive_streamers: Vec<String>,
+    /// `S3_ENDPOINT`: url of the S3 compatible storage, the s3 destination is
+    /// disabled if this is not set
+    pub s3_endpoint: Option<String>,
+    /// `S3_REGION`
+    pub s3_region: String,
+    /// `S3_BUCKET`
+    pub s3_bucket: String,
+    /// `S3_ACCESS_KEY`
+    pub s3_access_key: String,
+    /// `S3_SECRET_KEY`
+    pub s3_secret_key: String,
+    /// `S3_KEY_PREFIX`: prepended to the key of every uploaded object
+    pub s3_key_prefix: String,
+    /// `S3_CHUNK_SIZE_MB`: size of the chunks of the multipart uploads
+    pub s3_chunk_size_mb: usize,
+    /// `S3_STREAMERS`: comma separated logins of the streamers that are
+    /// backed up to s3 instead of youtube
+    pub s3_streamers: Vec<String>,
+    /// `RETRY_MAX_ATTEMPTS`: how often a video is tried before it is parked
+    pub retry_max_attempts: u32,
+    /// `RETRY_BASE_DELAY_MINUTES`: the wait after the first failure,
+    /// doubled after every further failure
+    pub retry_base_delay_minutes: i64,
+    /// `RETRY_MAX_DELAY_HOURS`: the longest wait between two attempts
+    pub retry_max_delay_hours: i64,
+    /// `HEALTH_FILE`: where the state of the main loop is written as json
+    /// after every iteration, nothing is written if this is not set
+    pub health_file: Option<String>,
+    /// `FATAL_ERROR_CODES`: comma separated [error codes](crate::error::DownloaderError::code)
+    /// that stop the main loop instead of trying again on the next cycle
+    pub fatal_error_codes: Vec<String>,
+    /// `MAX_CONSECUTIVE_FAILURES`: stop the main loop after this many failed
+    /// iterations in a row, it never stops if this is not set
+    pub max_consecutive_failures: Option<u32>,
+    /// `SHUTDOWN_DEADLINE_SECONDS`: how long the current part upload may take
+    /// after a SIGTERM/SIGINT, the stop timeout of the container has to be longer
+    pub shutdown_deadline_seconds: u64,
+    /// `POLL_INTERVAL_MINUTES`: the wait between two runs of the main loop
+    pub poll_interval_minutes: i64,
+    /// `SCHEDULE_CRON`: cron expression (UTC) for the runs of the main loop,
+    /// replaces the poll interval if it is set
+    pub schedule_cron: Option<String>,
+    /// `SCHEDULE_JITTER_SECONDS`: the longest random delay added to every wait
+    pub schedule_jitter_seconds: i64,
+    /// `QUIET_HOURS`: daily time range (UTC) like `22:00-06:00` in which nothing
+    /// is uploaded, downloads and splitting still happen
+    pub quiet_hours: Option<QuietHours>,
+    /// `MAX_CONCURRENT_VIDEOS`: how many videos are backed up at the same time,
+    /// the streamers take turns
+    pub max_concurrent_videos: usize,
+    /// `MAX_CONCURRENT_DOWNLOADS`
+    pub max_concurrent_downloads: usize,
+    /// `MAX_CONCURRENT_SPLITS`: every split runs its own ffmpeg
+    pub max_concurrent_splits: usize,
+    /// `MAX_CONCURRENT_UPLOADS`: uploads to the same youtube account always
+    /// run one after the other
+    pub max_concurrent_uploads: usize,
+    /// `SPLIT_MAX_PART_SIZE_MB`: the largest file size of a part, videos are
+    /// only split by their length if this is not set
+    pub split_max_part_size_mb: Option<u64>,
+    /// `SPLIT_BY_DURATION`: if the soft and hard caps of the config are used
+    /// for splitting, can only be turned off together with a size limit
+    pub split_by_duration: bool,
+    /// `SPLIT_POINTS`: where the parts are cut, `time` (exactly at the segment
+    /// time), `silence` or `scene` (a silence or scene change near it) or
+    /// `chapters` (the start of a chapter, see [crate::chapters])
+    pub split_points: SplitPointMode,
+    /// `SPLIT_SEARCH_WINDOW_SECONDS`: how far before and after the segment time
+    /// a silence or scene change is searched for
+    pub split_search_window_seconds: i64,
+    /// `CHAPTER_IN_TITLE`: add the chapter a part starts in to its title
+    pub chapter_in_title: bool,
+    /// `DOWNLOAD_DURATION_TOLERANCE_SECONDS`: how many seconds the length of a
+    /// downloaded file may differ from the length the source reported, files
+    /// that are off by more are downloaded again. The length is not checked if
+    /// this is not set or set to `off`
+    pub download_duration_tolerance_seconds: Option<i64>,
+}
+
+impl Default for Settings {
+    fn default() -> Self {
+        Self {
+            store_backend: StoreBackend::Bigquery,
+            sqlite_db_path: "/downloader/db/downloader.sqlite".to_string(),
+            local_inbox_path: None,
+            hls_playlists_file: None,
+            hls_max_bandwidth: None,
+            hls_segment_retries: 3,
+            local_archive_path: None,
+            local_archive_layout: DEFAULT_ARCHIVE_LAYOUT.to_string(),
+            local_archive_streamers: vec![],
+            s3_endpoint: None,
+            s3_region: "us-east-1".to_string(),
+            s3_bucket: "downloader".to_string(),
+            s3_access_key: String::new(),
+            s3_secret_key: String::new(),
+            s3_key_prefix: String::new(),
+            s3_chunk_size_mb: 16,
+            s3_streamers: vec![],
+            retry_max_attempts: 5,
+            retry_base_delay_minutes: 10,
+            retry_max_delay_hours: 24,
+            health_file: None,
+            fatal_error_codes: vec!["config".to_string()],
+            max_consecutive_failures: None,
+            shutdown_deadline_seconds: 60,
+            poll_interval_minutes: 3 * 60,
+            schedule_cron: None,
+            schedule_jitter_seconds: 0,
+            quiet_hours: None,
+            max_concurrent_videos: 1,
+            max_concurrent_downloads: 1,
+            max_concurrent_splits: 1,
+            max_concurrent_uploads: 1,
+            split_max_part_size_mb: None,
+            split_by_duration: true,
+            split_points: SplitPointMode::Time,
+            split_search_window_seconds: 120,
+            chapter_in_title: false,
+            download_duration_tolerance_seconds: None,
+        }
+    }
+}
+
+impl Settings {
+    /// load the settings from the environment, falling back to the defaults
+    pub fn load() -> Result<Self> {
+        let default = Self::default();
+        let settings = Self {
+            store_backend: env_parse("STORE_BACKEND", default.store_backend)?,
+            sqlite_db_path: env_or("SQLITE_DB_PATH", default.sqlite_db_path),
+            local_inbox_path: env_opt("LOCAL_INBOX_PATH"),
+            hls_playlists_file: env_opt("HLS_PLAYLISTS_FILE"),
+            hls_max_bandwidth: env_opt("HLS_MAX_BANDWIDTH")
+                .map(|v| v.parse())
+                .transpose()
+                .map_err(|e| anyhow!("invalid value for HLS_MAX_BANDWIDTH: {}", e))?,
+            hls_segment_retries: env_parse("HLS_SEGMENT_RETRIES", default.hls_segment_retries)?,
+            local_archive_path: env_opt("LOCAL_ARCHIVE_PATH"),
+            local_archive_layout: env_or("LOCAL_ARCHIVE_LAYOUT", default.local_archive_layout),
+            local_archive_streamers: env_list("LOCAL_ARCHIVE_STREAMERS"),
+            s3_endpoint: env_opt("S3_ENDPOINT"),
+            s3_region: env_or("S3_REGION", default.s3_region),
+            s3_bucket: env_or("S3_BUCKET", default.s3_bucket),
+            s3_access_key: env_or("S3_ACCESS_KEY", default.s3_access_key),
+            s3_secret_key: env_or("S3_SECRET_KEY", default.s3_secret_key),
+            s3_key_prefix: env_or("S3_KEY_PREFIX", default.s3_key_prefix),
+            s3_chunk_size_mb: env_parse("S3_CHUNK_SIZE_MB", default.s3_chunk_size_mb)?,
+            s3_streamers: env_list("S3_STREAMERS"),
+            retry_max_attempts: env_parse("RETRY_MAX_ATTEMPTS", default.retry_max_attempts)?,
+            retry_base_delay_minutes: env_parse(
+                "RETRY_BASE_DELAY_MINUTES",
+                default.retry_base_delay_minutes,
+            )?,
+            retry_max_delay_hours: env_parse(
+                "RETRY_MAX_DELAY_HOURS",
+                default.retry_max_delay_hours,
+            )?,
+            health_file: env_opt("HEALTH_FILE"),
+            fatal_error_codes: match env_opt("FATAL_ERROR_CODES") {
+                Some(_) => env_list("FATAL_ERROR_CODES"),
+                None => default.fatal_error_codes,
+            },
+            max_consecutive_failures: env_opt("MAX_CONSECUTIVE_FAILURES")
+                .map(|v| v.parse())
+                .transpose()
+                .map_err(|e| anyhow!("invalid value for MAX_CONSECUTIVE_FAILURES: {}", e))?,
+            shutdown_deadline_seconds: env_parse(
+                "SHUTDOWN_DEADLINE_SECONDS",
+                default.shutdown_deadline_seconds,
+            )?,
+            poll_interval_minutes: env_parse(
+                "POLL_INTERVAL_MINUTES",
+                default.poll_interval_minutes,
+            )?,
+            schedule_cron: env_opt("SCHEDULE_CRON"),
+            schedule_jitter_seconds: env_parse(
+                "SCHEDULE_JITTER_SECONDS",
+                default.schedule_jitter_seconds,
+            )?,
+            quiet_hours: env_opt("QUIET_HOURS")
+                .map(|v| v.parse())
+                .transpose()
+                .map_err(|e| anyhow!("invalid value for QUIET_HOURS: {:#}", e))?,
+            max_concurrent_videos: env_parse(
+                "MAX_CONCURRENT_VIDEOS",
+                default.max_concurrent_videos,
+            )?,
+            max_concurrent_downloads: env_parse(
+                "MAX_CONCURRENT_DOWNLOADS",
+                default.max_concurrent_downloads,
+            )?,
+            max_concurrent_splits: env_parse(
+                "MAX_CONCURRENT_SPLITS",
+                default.max_concurrent_splits,
+            )?,
+            max_concurrent_uploads: env_parse(
+                "MAX_CONCURRENT_UPLOADS",
+                default.max_concurrent_uploads,
+            )?,
+            split_max_part_size_mb: env_opt("SPLIT_MAX_PART_SIZE_MB")
+                .map(|v| v.parse())
+                .transpose()
+                .map_err(|e| anyhow!("invalid value for SPLIT_MAX_PART_SIZE_MB: {}", e))?,
+            split_by_duration: env_parse("SPLIT_BY_DURATION", default.split_by_duration)?,
+            split_points: env_parse("SPLIT_POINTS", default.split_points)?,
+            split_search_window_seconds: env_parse(
+                "SPLIT_SEARCH_WINDOW_SECONDS",
+                default.split_search_window_seconds,
+            )?,
+            chapter_in_title: env_parse("CHAPTER_IN_TITLE", default.chapter_in_title)?,
+            download_duration_tolerance_seconds: match env_opt(
+                "DOWNLOAD_DURATION_TOLERANCE_SECONDS",
+            ) {
+                Some(v) if v.eq_ignore_ascii_case("off") => None,
+                Some(v) => Some(v.parse().map_err(|e| {
+                    anyhow!(
+                        "invalid value for DOWNLOAD_DURATION_TOLERANCE_SECONDS: {}",
+                        e
+                    )
+                })?),
+                None => default.download_duration_tolerance_seconds,
+            },
+        };
+        settings.validate()?;
+        Ok(settings)
+    }
+
+    /// fail for combinations of settings that can not work together
+    pub fn validate(&self) -> Result<()> {
+        if !self.split_by_duration && self.split_max_part_size_mb.is_none() {
+            return Err(DownloaderError::Config(
+                "SPLIT_BY_DURATION=false needs SPLIT_MAX_PART_SIZE_MB, \
+                 otherwise there is nothing to split the videos by"
+                    .to_string(),
+            )
+            .into());
+        }
+        Ok(())
+    }
+
+    /// if the streamer is backed up to the local archive instead of youtube
+    pub fn uses_local_archive(&self, streamer_login: &str) -> bool {
+        self.local_archive_path.is_some()
+            && self
+                .local_archive_streamers
+                .iter()
+                .any(|s| s.eq_ignore_ascii_case(streamer_login))
+    }
+
+    /// if the streamer is backed up to s3 instead of youtube
+    pub fn uses_s3(&self, streamer_login: &str) -> bool {
+        self.s3_endpoint.is_some()
+            && self
+                .s3_streamers
+                .iter()
+                .any(|s| s.eq_ignore_ascii_case(streamer_login))
+    }
+
+    /// the config for the s3 destination, `None` if it is disabled
+    pub fn s3_config(&self) -> Option<S3Config> {
+        Some(S3Config {
+            endpoint: self.s3_endpoint.clone()?,
+            region: self.s3_region.clone(),
+            bucket: self.s3_bucket.clone(),
+            access_key: self.s3_access_key.clone(),
+            secret_key: self.s3_secret_key.clone(),
+            key_prefix: self.s3_key_prefix.clone(),
+            chunk_size: self.s3_chunk_size_mb * 1024 * 1024,
+        })
+    }
+
+    pub fn shutdown_deadline(&self) -> std::time::Duration {
+        std::time::Duration::from_secs(self.shutdown_deadline_seconds)
+    }
+
+    /// the schedule of the main loop, fails if the cron expression is invalid
+    pub fn schedule(&self) -> Result<Schedule> {
+        Ok(Schedule {
+            interval: chrono::Duration::minutes(self.poll_interval_minutes),
+            cron: self
+                .schedule_cron
+                .as_deref()
+                .map(Schedule::parse_cron)
+                .transpose()?,
+            jitter: chrono::Duration::seconds(self.schedule_jitter_seconds),
+        })
+    }
+
+    /// the limits for the parts of a split, with the caps from the config
+    pub fn split_limits(&self, config: &Config) -> SplitLimits {
+        let (duration_soft_cap, duration_hard_cap) = if self.split_by_duration {
+            (
+                Some(chrono::Duration::minutes(
+                    config.youtube_video_length_minutes_soft_cap,
+                )),
+                Some(chrono::Duration::minutes(
+                    config.youtube_video_length_minutes_hard_cap,
+                )),
+            )
+        } else {
+            (None, None)
+        };
+        SplitLimits {
+            duration_soft_cap,
+            duration_hard_cap,
+            max_part_bytes: self.split_max_part_size_mb.map(|mb| mb * 1024 * 1024),
+            split_points: match self.split_points {
+                SplitPointMode::Time => None,
+                mode => Some(SplitPoints {
+                    mode,
+                    window: chrono::Duration::seconds(self.split_search_window_seconds),
+                }),
+            },
+        }
+    }
+
+    pub fn stage_limits(&self) -> StageLimits {
+        StageLimits::new(
+            self.max_concurrent_downloads,
+            self.max_concurrent_splits,
+            self.max_concurrent_uploads,
+        )
+    }
+
+    pub fn retry_policy(&self) -> RetryPolicy {
+        RetryPolicy {
+            max_attempts: self.retry_max_attempts,
+            base_delay: chrono::Duration::minutes(self.retry_base_delay_minutes),
+            max_delay: chrono::Duration::hours(self.retry_max_delay_hours),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum StoreBackend {
+    Bigquery,
+    Sqlite,
+}
+
+impl FromStr for StoreBackend {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        match s.trim().to_lowercase().as_str() {
+            "bigquery" => Ok(StoreBackend::Bigquery),
+            "sqlite" => Ok(StoreBackend::Sqlite),
+            other => Err(anyhow!("unknown store backend: {}", other)),
+        }
+    }
+}
+
+fn env_or(key: &str, default: String) -> String {
+    std::env::var(key).unwrap_or(default)
+}
+
+fn env_opt(key: &str) -> Option<String> {
+    std::env::var(key).ok().filter(|v| !v.trim().is_empty())
+}
+
+fn env_list(key: &str) -> Vec<String> {
+    env_opt(key)
+        .map(|v| {
+            v.split(',')
+                .map(str::trim)
+                .filter(|s| !s.is_empty())
+                .map(str::to_string)
+                .collect()
+        })
+        .unwrap_or_default()
+}
+
+fn env_parse<T>(key: &str, default: T) -> Result<T>
+where
+    T: FromStr + Debug,
+    T::Err: std::fmt::Display,
+{
+    match std::env::var(key) {
+        Ok(value) => {
+            let value = value
+                .parse::<T>()
+                .map_err(|e| anyhow!("invalid value for {}: '{}': {}", key, value, e))?;
+            trace!("setting {} from env: {:?}", key, value);
+            Ok(value)
+        }
+        Err(_) => Ok(default),
+    }
+}
@@ -0,0 +1,85 @@
+use std::sync::Arc;
+use std::time::Duration;
+
+use anyhow::Result;
+use tokio::sync::watch;
+
+use crate::prelude::*;
+
+/// Tells the pipeline that it should stop.
+///
+/// Once a shutdown is requested no new work is started and uploads stop
+/// after the part they are working on. Everything that is still running
+/// after the deadline is dropped, the saved stages and parts make sure it
+/// continues from there on the next start.
+#[derive(Debug, Clone)]
+pub struct Shutdown {
+    sender: Arc<watch::Sender<bool>>,
+}
+
+impl Default for Shutdown {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Shutdown {
+    pub fn new() -> Self {
+        let (sender, _) = watch::channel(false);
+        Self {
+            sender: Arc::new(sender),
+        }
+    }
+
+    pub fn request(&self) {
+        if !self.sender.send_replace(true) {
+            info!("Shutdown requested");
+        }
+    }
+
+    pub fn is_requested(&self) -> bool {
+        *self.sender.borrow()
+    }
+
+    /// wait until a shutdown is requested
+    pub async fn requested(&self) {
+        let mut receiver = self.sender.subscribe();
+        while !*receiver.borrow_and_update() {
+            if receiver.changed().await.is_err() {
+                return;
+            }
+        }
+    }
+
+    /// wait until the deadline after a requested shutdown is over
+    pub async fn deadline_passed(&self, deadline: Duration) {
+        self.requested().await;
+        info!(
+            "Waiting up to {} seconds for the current work to finish",
+            deadline.as_secs()
+        );
+        tokio::time::sleep(deadline).await;
+        warn!("Shutdown deadline passed, stopping the current work");
+    }
+
+    /// request a shutdown on SIGINT and SIGTERM
+    pub fn listen_for_signals(&self) -> Result<()> {
+        #[cfg(unix)]
+        let mut terminate =
+            tokio::signal::unix::signal(tokio::signal::unix::SignalKind::terminate())?;
+        let shutdown = self.clone();
+        tokio::spawn(async move {
+            #[cfg(unix)]
+            tokio::select! {
+                _ = tokio::signal::ctrl_c() => info!("Got SIGINT"),
+                _ = terminate.recv() => info!("Got SIGTERM"),
+            }
+            #[cfg(not(unix))]
+            if tokio::signal::ctrl_c().await.is_ok() {
+                info!("Got SIGINT");
+            }
+            shutdown.request();
+        });
+        Ok(())
+    }
+}
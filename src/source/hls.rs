@@ -0,0 +1,429 @@
+use std::collections::HashMap;
+use std::path::{Path, PathBuf};
+use std::sync::Mutex;
+
+use anyhow::{anyhow, Context, Result};
+use async_trait::async_trait;
+use chrono::{DateTime, Utc};
+use reqwest::Url;
+use serde::{Deserialize, Serialize};
+use tokio::io::AsyncWriteExt;
+
+use crate::data::{Streamers, VideoData, VideoMetadata, Videos};
+use crate::prelude::*;
+use crate::source::{synthetic_video_id, VideoSource};
+
+pub const HLS_SOURCE_NAME: &str = "hls";
+
+/// A HLS VOD playlist that should be archived
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct HlsPlaylist {
+    pub streamer_login: String,
+    /// url of a master or media playlist
+    pub url: String,
+    #[serde(default)]
+    pub title: Option<String>,
+    #[serde(default)]
+    pub description: Option<String>,
+    /// when the stream happened, defaults to the first `#EXT-X-PROGRAM-DATE-TIME`
+    /// of the playlist or the time the playlist was first seen
+    #[serde(default)]
+    pub created_at: Option<DateTime<Utc>>,
+}
+
+impl HlsPlaylist {
+    pub fn video_id(&self) -> i64 {
+        synthetic_video_id(HLS_SOURCE_NAME, &self.url)
+    }
+
+    /// the video of the playlist, without a `created_at` if the file has none
+    /// (see [HlsSource::created_at])
+    pub fn to_video_data(&self) -> VideoData {
+        let video_id = self.video_id();
+        let title = self.title.clone().unwrap_or_else(|| {
+            let file = self.url.rsplit('/').next().unwrap_or(&self.url);
+            format!("{} {}", self.streamer_login, file)
+        });
+        VideoData {
+            video: Videos {
+                video_id,
+                title: Some(title),
+                description: self.description.clone(),
+                user_login: Some(self.streamer_login.to_lowercase()),
+                created_at: self.created_at,
+                url: Some(self.url.clone()),
+                video_type: Some("hls".to_string()),
+                source: Some(HLS_SOURCE_NAME.to_string()),
+                ..Default::default()
+            },
+            metadata: VideoMetadata {
+                video_id,
+                backed_up: Some(false),
+                download_playlist_url: Some(self.url.clone()),
+                ..Default::default()
+            },
+            streamer: Streamers::default(),
+        }
+    }
+}
+
+/// A variant stream from a master playlist
+#[derive(Debug, Clone, PartialEq)]
+pub struct HlsVariant {
+    pub bandwidth: u64,
+    pub uri: String,
+}
+
+/// The parts of a media playlist that are needed to download it
+#[derive(Debug, Clone, PartialEq, Default)]
+pub struct HlsMediaPlaylist {
+    /// the init segment for fragmented mp4 streams (`#EXT-X-MAP`)
+    pub init_segment: Option<String>,
+    pub segments: Vec<(String, f64)>,
+    /// the time of the first segment (`#EXT-X-PROGRAM-DATE-TIME`)
+    pub program_date_time: Option<DateTime<Utc>>,
+}
+
+impl HlsMediaPlaylist {
+    pub fn duration(&self) -> f64 {
+        self.segments.iter().map(|(_, duration)| duration).sum()
+    }
+}
+
+/// get the variants of a master playlist, empty if it is a media playlist
+pub fn parse_master_playlist(playlist: &str) -> Vec<HlsVariant> {
+    let mut variants = vec![];
+    let mut bandwidth = None;
+    for line in playlist.lines().map(str::trim) {
+        if let Some(attributes) = line.strip_prefix("#EXT-X-STREAM-INF:") {
+            bandwidth = Some(
+                get_attribute(attributes, "BANDWIDTH")
+                    .and_then(|b| b.parse::<u64>().ok())
+                    .unwrap_or(0),
+            );
+        } else if line.is_empty() || line.starts_with('#') {
+            continue;
+        } else if let Some(bandwidth) = bandwidth.take() {
+            variants.push(HlsVariant {
+                bandwidth,
+                uri: line.to_string(),
+            });
+        }
+    }
+    variants
+}
+
+/// get the segments of a media playlist
+pub fn parse_media_playlist(playlist: &str) -> Result<HlsMediaPlaylist> {
+    let mut result = HlsMediaPlaylist::default();
+    let mut duration = None;
+    for line in playlist.lines().map(str::trim) {
+        if let Some(info) = line.strip_prefix("#EXTINF:") {
+            let time_str = info.split(',').next().unwrap_or(info).trim();
+            duration = Some(time_str.parse::<f64>()?);
+        } else if let Some(time) = line.strip_prefix("#EXT-X-PROGRAM-DATE-TIME:") {
+            if result.program_date_time.is_none() {
+                result.program_date_time = DateTime::parse_from_rfc3339(time.trim())
+                    .ok()
+                    .map(|t| t.with_timezone(&Utc));
+            }
+        } else if let Some(attributes) = line.strip_prefix("#EXT-X-MAP:") {
+            result.init_segment = get_attribute(attributes, "URI");
+        } else if let Some(attributes) = line.strip_prefix("#EXT-X-KEY:") {
+            if get_attribute(attributes, "METHOD").as_deref() != Some("NONE") {
+                return Err(anyhow!("encrypted HLS playlists are not supported"));
+            }
+        } else if line.starts_with("#EXT-X-ENDLIST") {
+            break;
+        } else if line.is_empty() || line.starts_with('#') {
+            continue;
+        } else if let Some(duration) = duration.take() {
+            result.segments.push((line.to_string(), duration));
+        }
+    }
+    Ok(result)
+}
+
+/// if asking again can help: a server error or the request did not get through
+fn is_retryable(error: &reqwest::Error) -> bool {
+    match error.status() {
+        Some(status) => status.is_server_error(),
+        None => error.is_timeout() || error.is_connect() || error.is_request() || error.is_body(),
+    }
+}
+
+/// get the value of an attribute from an attribute list like `BANDWIDTH=1280000,URI="a.ts"`
+fn get_attribute(attributes: &str, name: &str) -> Option<String> {
+    let mut rest = attributes;
+    while !rest.is_empty() {
+        let (key, value_and_rest) = rest.split_once('=')?;
+        let (value, next) = if let Some(quoted) = value_and_rest.strip_prefix('"') {
+            let end = quoted.find('"')?;
+            let next = quoted[end + 1..].trim_start_matches(',');
+            (&quoted[..end], next)
+        } else {
+            match value_and_rest.split_once(',') {
+                Some((value, next)) => (value, next),
+                None => (value_and_rest, ""),
+            }
+        };
+        if key.trim() == name {
+            return Some(value.to_string());
+        }
+        rest = next;
+    }
+    None
+}
+
+/// [VideoSource] for arbitrary HLS VOD playlists.
+///
+/// The playlists are read from a JSON file (a list of [HlsPlaylist]) every
+/// time the source is checked, so new ones can be added without a restart.
+pub struct HlsSource {
+    playlists_file: PathBuf,
+    client: reqwest::Client,
+    /// the highest bandwidth a variant may have, the best one is used if `None`
+    max_bandwidth: Option<u64>,
+    /// how often a segment download is retried before giving up
+    retries: u32,
+    /// the `created_at` of the playlists that have none in the file, by video id
+    created_at: Mutex<HashMap<i64, DateTime<Utc>>>,
+}
+
+impl HlsSource {
+    pub fn new<P: Into<PathBuf>>(playlists_file: P) -> Self {
+        Self {
+            playlists_file: playlists_file.into(),
+            client: reqwest::Client::new(),
+            max_bandwidth: None,
+            retries: 3,
+            created_at: Mutex::new(HashMap::new()),
+        }
+    }
+
+    pub fn with_max_bandwidth(mut self, max_bandwidth: Option<u64>) -> Self {
+        self.max_bandwidth = max_bandwidth;
+        self
+    }
+
+    pub fn with_retries(mut self, retries: u32) -> Self {
+        self.retries = retries;
+        self
+    }
+
+    pub async fn get_playlists(&self) -> Result<Vec<HlsPlaylist>> {
+        let content = tokio::fs::read_to_string(&self.playlists_file)
+            .await
+            .with_context(|| {
+                format!(
+                    "could not read hls playlists file {}",
+                    self.playlists_file.display()
+                )
+            })?;
+        let playlists = serde_json::from_str(&content)?;
+        Ok(playlists)
+    }
+
+    /// pick the variant with the highest bandwidth that is allowed
+    pub fn pick_variant<'v>(&self, variants: &'v [HlsVariant]) -> Option<&'v HlsVariant> {
+        let allowed = variants
+            .iter()
+            .filter(|v| match self.max_bandwidth {
+                Some(max) => v.bandwidth <= max,
+                None => true,
+            })
+            .max_by_key(|v| v.bandwidth);
+        // if every variant is too big, the smallest one is the best we can do
+        allowed.or_else(|| variants.iter().min_by_key(|v| v.bandwidth))
+    }
+
+    /// Get the url, retrying server errors and network problems.
+    ///
+    /// Other errors (like a 404) do not go away by asking again.
+    async fn get_with_retries(&self, url: &Url) -> Result<Vec<u8>> {
+        let mut attempt = 0;
+        loop {
+            let result = async {
+                let response = self.client.get(url.clone()).send().await?;
+                let bytes = response.error_for_status()?.bytes().await?;
+                Ok::<_, reqwest::Error>(bytes.to_vec())
+            }
+            .await;
+            match result {
+                Ok(bytes) => return Ok(bytes),
+                Err(e) if attempt < self.retries && is_retryable(&e) => {
+                    attempt += 1;
+                    let wait = std::time::Duration::from_millis(500 * 2u64.pow(attempt - 1));
+                    warn!(
+                        "Failed to get {} (attempt {}/{}), retrying in {:?}: {}",
+                        url,
+                        attempt,
+                        self.retries + 1,
+                        wait,
+                        e
+                    );
+                    tokio::time::sleep(wait).await;
+                }
+                Err(e) => {
+                    // the reqwest error stays in the chain for crate::retry::classify_error
+                    return Err(anyhow::Error::new(e).context(format!(
+                        "could not get {} after {} attempts",
+                        url,
+                        attempt + 1
+                    )));
+                }
+            }
+        }
+    }
+
+    /// When the stream of the playlist happened.
+    ///
+    /// Taken from the playlists file, else from the first
+    /// `#EXT-X-PROGRAM-DATE-TIME` of the playlist, else it is the time the
+    /// playlist was first seen. It is only looked up once, so the video does
+    /// not change between two checks.
+    pub async fn created_at(&self, playlist: &HlsPlaylist) -> DateTime<Utc> {
+        if let Some(created_at) = playlist.created_at {
+            return created_at;
+        }
+        let video_id = playlist.video_id();
+        let known = self
+            .created_at
+            .lock()
+            .ok()
+            .and_then(|known| known.get(&video_id).copied());
+        if let Some(created_at) = known {
+            return created_at;
+        }
+        let program_date_time = match self.resolve_media_playlist(&playlist.url).await {
+            Ok((_, media_playlist)) => media_playlist.program_date_time,
+            Err(e) => {
+                warn!(
+                    "Could not read the time of playlist {}: {:?}",
+                    playlist.url, e
+                );
+                None
+            }
+        };
+        let created_at = program_date_time.unwrap_or_else(Utc::now);
+        if let Ok(mut known) = self.created_at.lock() {
+            known.insert(video_id, created_at);
+        }
+        created_at
+    }
+
+    async fn to_video_data(&self, playlist: &HlsPlaylist) -> VideoData {
+        let mut video = playlist.to_video_data();
+        video.video.created_at = Some(self.created_at(playlist).await);
+        video
+    }
+
+    async fn get_text(&self, url: &Url) -> Result<String> {
+        let bytes = self.get_with_retries(url).await?;
+        Ok(String::from_utf8(bytes)?)
+    }
+
+    /// get the media playlist for the url, following a master playlist if needed
+    pub async fn resolve_media_playlist(&self, url: &str) -> Result<(Url, HlsMediaPlaylist)> {
+        let url = Url::parse(url)?;
+        let playlist = self.get_text(&url).await?;
+        let variants = parse_master_playlist(&playlist);
+        if variants.is_empty() {
+            return Ok((url, parse_media_playlist(&playlist)?));
+        }
+        let variant = self
+            .pick_variant(&variants)
+            .ok_or_else(|| anyhow!("master playlist has no variants: {}", url))?;
+        info!(
+            "Using variant with bandwidth {}: {}",
+            variant.bandwidth, variant.uri
+        );
+        let media_url = url.join(&variant.uri)?;
+        let playlist = self.get_text(&media_url).await?;
+        Ok((media_url, parse_media_playlist(&playlist)?))
+    }
+
+    /// Download all segments of the playlist and concatenate them into one file.
+    ///
+    /// The file is created in the folder with the given file stem, the extension
+    /// depends on the segment format (`.mp4` for fragmented mp4, `.ts` otherwise).
+    pub async fn download_playlist(&self, url: &str, folder: &Path, stem: &str) -> Result<PathBuf> {
+        let (media_url, playlist) = self.resolve_media_playlist(url).await?;
+        if playlist.segments.is_empty() {
+            return Err(anyhow!("media playlist has no segments: {}", media_url));
+        }
+        let extension = match playlist.init_segment {
+            Some(_) => "mp4",
+            None => "ts",
+        };
+        let target = folder.join(format!("{}.{}", stem, extension));
+        let target = target.as_path();
+        info!(
+            "Downloading {} segments ({} seconds) from {}",
+            playlist.segments.len(),
+            playlist.duration(),
+            media_url
+        );
+        tokio::fs::create_dir_all(folder).await?;
+        let mut file = tokio::fs::File::create(target)
+            .await
+            .with_context(|| format!("could not create {}", target.display()))?;
+        if let Some(init_segment) = &playlist.init_segment {
+            let bytes = self
+                .get_with_retries(&media_url.join(init_segment)?)
+                .await?;
+            file.write_all(&bytes).await?;
+        }
+        let count = playlist.segments.len();
+        for (i, (segment, _)) in playlist.segments.iter().enumerate() {
+            trace!("Downloading segment {}/{}: {}", i + 1, count, segment);
+            let bytes = self.get_with_retries(&media_url.join(segment)?).await?;
+            file.write_all(&bytes).await?;
+        }
+        file.flush().await?;
+        Ok(target.to_path_buf())
+    }
+}
+
+#[async_trait(?Send)]
+impl VideoSource for HlsSource {
+    fn name(&self) -> &str {
+        HLS_SOURCE_NAME
+    }
+
+    async fn get_videos_for_streamer(&self, streamer: &Streamers) -> Result<Vec<VideoData>> {
+        let login = streamer.login.to_lowercase();
+        let mut videos = vec![];
+        for playlist in self.get_playlists().await? {
+            if playlist.streamer_login.to_lowercase() == login {
+                videos.push(self.to_video_data(&playlist).await);
+            }
+        }
+        Ok(videos)
+    }
+
+    async fn get_video(&self, video_id: i64) -> Result<Option<VideoData>> {
+        let playlist = self
+            .get_playlists()
+            .await?
+            .into_iter()
+            .find(|p| p.video_id() == video_id);
+        Ok(match playlist {
+            Some(playlist) => Some(self.to_video_data(&playlist).await),
+            None => None,
+        })
+    }
+
+    async fn download_video(&self, video: &VideoData, folder: &Path) -> Result<PathBuf> {
+        let video_id = video.video.video_id;
+        let url = video
+            .metadata
+            .download_playlist_url
+            .as_ref()
+            .or(video.video.url.as_ref())
+            .ok_or_else(|| anyhow!("video {} has no playlist url", video_id))?;
+        let folder = folder.join(video_id.to_string());
+        self.download_playlist(url, &folder, &video_id.to_string())
+            .await
+    }
+}
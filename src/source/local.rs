@@ -0,0 +1,210 @@
+use std::path::{Path, PathBuf};
+
+use anyhow::{anyhow, Context, Result};
+use async_trait::async_trait;
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+
+use crate::chapters::{self, Chapter};
+use crate::data::{Streamers, VideoData, VideoMetadata, Videos};
+use crate::error::DownloaderError;
+use crate::prelude::*;
+use crate::source::{synthetic_video_id, VideoSource};
+
+pub const LOCAL_SOURCE_NAME: &str = "local";
+
+/// the file extensions that are picked up from the inbox
+const VIDEO_EXTENSIONS: &[&str] = &["mp4", "mkv", "flv", "ts", "mov"];
+
+/// The info about a video that is dropped into the inbox.
+///
+/// It is read from a `.json` or `.toml` file next to the video with the same
+/// file stem (`recording.mp4` => `recording.json`).
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Sidecar {
+    pub title: String,
+    pub streamer_login: String,
+    pub created_at: DateTime<Utc>,
+    #[serde(default)]
+    pub description: Option<String>,
+    /// the chapters of the recording, see [crate::chapters]
+    #[serde(default)]
+    pub chapters: Vec<Chapter>,
+}
+
+impl Sidecar {
+    /// read the sidecar file, the format is picked by the extension
+    pub async fn read(path: &Path) -> Result<Self> {
+        let content = tokio::fs::read_to_string(path)
+            .await
+            .with_context(|| format!("could not read sidecar file {}", path.display()))?;
+        let sidecar = match path.extension().and_then(|e| e.to_str()) {
+            Some("json") => serde_json::from_str(&content)?,
+            Some("toml") => toml::from_str(&content)?,
+            _ => return Err(anyhow!("unknown sidecar format: {}", path.display())),
+        };
+        Ok(sidecar)
+    }
+}
+
+/// A video file in the inbox together with its sidecar
+#[derive(Debug, Clone)]
+pub struct InboxEntry {
+    pub video_id: i64,
+    pub path: PathBuf,
+    pub sidecar: Sidecar,
+}
+
+impl InboxEntry {
+    pub fn to_video_data(&self) -> VideoData {
+        VideoData {
+            video: Videos {
+                video_id: self.video_id,
+                title: Some(self.sidecar.title.clone()),
+                description: self.sidecar.description.clone(),
+                user_login: Some(self.sidecar.streamer_login.to_lowercase()),
+                created_at: Some(self.sidecar.created_at),
+                video_type: Some("recording".to_string()),
+                source: Some(LOCAL_SOURCE_NAME.to_string()),
+                ..Default::default()
+            },
+            metadata: VideoMetadata {
+                video_id: self.video_id,
+                backed_up: Some(false),
+                ..Default::default()
+            },
+            streamer: Streamers::default(),
+        }
+    }
+}
+
+/// [VideoSource] for recordings that are dropped into a local folder.
+///
+/// Every video file in the inbox needs a sidecar file (see [Sidecar]), files
+/// without one are ignored until it shows up. The files in the inbox are not
+/// touched, the video gets copied into the download folder, so they can be
+/// removed by hand once the video is backed up.
+pub struct LocalFolderSource {
+    inbox: PathBuf,
+}
+
+impl LocalFolderSource {
+    pub fn new<P: Into<PathBuf>>(inbox: P) -> Self {
+        Self {
+            inbox: inbox.into(),
+        }
+    }
+
+    /// get all videos in the inbox that have a readable sidecar
+    pub async fn scan(&self) -> Result<Vec<InboxEntry>> {
+        trace!("Scanning inbox: {}", self.inbox.display());
+        let mut entries = vec![];
+        let mut dir = tokio::fs::read_dir(&self.inbox)
+            .await
+            .with_context(|| format!("could not read inbox {}", self.inbox.display()))?;
+        while let Some(entry) = dir.next_entry().await? {
+            let path = entry.path();
+            if !is_video_file(&path) {
+                continue;
+            }
+            let sidecar_path = match find_sidecar(&path) {
+                Some(sidecar_path) => sidecar_path,
+                None => {
+                    debug!("No sidecar file for {}, skipping it", path.display());
+                    continue;
+                }
+            };
+            let sidecar = match Sidecar::read(&sidecar_path).await {
+                Ok(sidecar) => sidecar,
+                Err(e) => {
+                    warn!("Could not read sidecar {}: {:?}", sidecar_path.display(), e);
+                    continue;
+                }
+            };
+            let file_name = path
+                .file_name()
+                .and_then(|n| n.to_str())
+                .ok_or_else(|| anyhow!("invalid file name: {}", path.display()))?;
+            // file names are reused over time, so the same name can belong to another streamer
+            let key = format!("{}/{}", sidecar.streamer_login.to_lowercase(), file_name);
+            entries.push(InboxEntry {
+                video_id: synthetic_video_id(LOCAL_SOURCE_NAME, &key),
+                path,
+                sidecar,
+            });
+        }
+        entries.sort_by_key(|e| e.sidecar.created_at);
+        Ok(entries)
+    }
+
+    async fn find_entry(&self, video_id: i64) -> Result<Option<InboxEntry>> {
+        let entries = self.scan().await?;
+        Ok(entries.into_iter().find(|e| e.video_id == video_id))
+    }
+}
+
+fn is_video_file(path: &Path) -> bool {
+    path.is_file()
+        && path
+            .extension()
+            .and_then(|e| e.to_str())
+            .map(|e| VIDEO_EXTENSIONS.contains(&e.to_lowercase().as_str()))
+            .unwrap_or(false)
+}
+
+fn find_sidecar(video_path: &Path) -> Option<PathBuf> {
+    ["json", "toml"]
+        .iter()
+        .map(|extension| video_path.with_extension(extension))
+        .find(|path| path.is_file())
+}
+
+#[async_trait(?Send)]
+impl VideoSource for LocalFolderSource {
+    fn name(&self) -> &str {
+        LOCAL_SOURCE_NAME
+    }
+
+    async fn get_videos_for_streamer(&self, streamer: &Streamers) -> Result<Vec<VideoData>> {
+        let login = streamer.login.to_lowercase();
+        let videos = self
+            .scan()
+            .await?
+            .iter()
+            .filter(|e| e.sidecar.streamer_login.to_lowercase() == login)
+            .map(InboxEntry::to_video_data)
+            .collect();
+        Ok(videos)
+    }
+
+    async fn get_video(&self, video_id: i64) -> Result<Option<VideoData>> {
+        let entry = self.find_entry(video_id).await?;
+        Ok(entry.map(|e| e.to_video_data()))
+    }
+
+    async fn download_video(&self, video: &VideoData, folder: &Path) -> Result<PathBuf> {
+        let video_id = video.video.video_id;
+        let entry = self
+            .find_entry(video_id)
+            .await?
+            .ok_or_else(|| DownloaderError::NotFound(format!("video {} in the inbox", video_id)))?;
+        let file_name = entry
+            .path
+            .file_name()
+            .ok_or_else(|| anyhow!("invalid file name: {}", entry.path.display()))?;
+        // every video gets its own folder, the splitting puts the parts next to the file
+        let target_folder = folder.join(video_id.to_string());
+        tokio::fs::create_dir_all(&target_folder).await?;
+        let target = target_folder.join(file_name);
+        info!(
+            "Copying {} from the inbox to {}",
+            entry.path.display(),
+            target.display()
+        );
+        tokio::fs::copy(&entry.path, &target).await?;
+        if !entry.sidecar.chapters.is_empty() {
+            chapters::write_sidecar(&target, &entry.sidecar.chapters).await?;
+        }
+        Ok(target)
+    }
+}
@@ -0,0 +1,87 @@
+use std::path::{Path, PathBuf};
+
+use anyhow::Result;
+use async_trait::async_trait;
+
+use crate::data::{Streamers, VideoData};
+
+pub use hls::HlsSource;
+pub use local::LocalFolderSource;
+pub use twitch::TwitchSource;
+
+pub mod hls;
+pub mod local;
+pub mod twitch;
+
+/// Somewhere videos come from (twitch, ...).
+///
+/// A source finds new videos for the watched streamers, can fetch the metadata
+/// of a single video again and downloads videos so they can go through the
+/// same split/upload pipeline, no matter where they came from.
+#[async_trait(?Send)]
+pub trait VideoSource {
+    /// The name of this source.
+    ///
+    /// It is stored in [Videos::source](crate::data::Videos::source) so a video
+    /// can be routed back to the source it came from.
+    fn name(&self) -> &str;
+    /// get all videos this source currently has for the streamer
+    async fn get_videos_for_streamer(&self, streamer: &Streamers) -> Result<Vec<VideoData>>;
+    /// fetch the metadata for a single video
+    async fn get_video(&self, video_id: i64) -> Result<Option<VideoData>>;
+    /// download the video into the folder and return the path of the downloaded file
+    async fn download_video(&self, video: &VideoData, folder: &Path) -> Result<PathBuf>;
+}
+
+/// All sources the pipeline knows about
+#[derive(Default)]
+pub struct VideoSources<'a> {
+    sources: Vec<Box<dyn VideoSource + 'a>>,
+}
+
+impl<'a> VideoSources<'a> {
+    pub fn new() -> Self {
+        Self { sources: vec![] }
+    }
+
+    pub fn add<S: VideoSource + 'a>(&mut self, source: S) {
+        self.sources.push(Box::new(source));
+    }
+
+    pub fn get(&self, name: &str) -> Option<&(dyn VideoSource + 'a)> {
+        self.sources
+            .iter()
+            .find(|s| s.name() == name)
+            .map(|s| s.as_ref())
+    }
+
+    /// get the source a video came from
+    pub fn for_video(&self, video: &VideoData) -> Option<&(dyn VideoSource + 'a)> {
+        self.get(video.video.source_name())
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = &(dyn VideoSource + 'a)> {
+        self.sources.iter().map(|s| s.as_ref())
+    }
+
+    pub fn len(&self) -> usize {
+        self.sources.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.sources.is_empty()
+    }
+}
+
+/// Creates a stable id for a video from a source that has no numeric ids.
+///
+/// The ids are always negative so they can never collide with twitch ids.
+pub fn synthetic_video_id(source: &str, key: &str) -> i64 {
+    // FNV-1a, the std hashers are not guaranteed to be stable between versions
+    let mut hash: u64 = 0xcbf29ce484222325;
+    for byte in source.bytes().chain([0]).chain(key.bytes()) {
+        hash ^= byte as u64;
+        hash = hash.wrapping_mul(0x100000001b3);
+    }
+    -((hash >> 1) as i64) - 1
+}
@@ -0,0 +1,221 @@
+use std::path::{Path, PathBuf};
+
+use anyhow::{anyhow, Context, Result};
+use async_trait::async_trait;
+use serde::Deserialize;
+use twitch_data::{convert_twitch_video_to_twitch_data_video, TwitchClient, VideoId};
+
+use crate::chapters::{self, Chapter};
+use crate::data::{Streamers, VideoData};
+use crate::prelude::*;
+use crate::source::VideoSource;
+
+/// The name of the twitch source, also used for videos without a source
+pub const TWITCH_SOURCE_NAME: &str = "twitch";
+
+/// The api the twitch website gets the chapters of a video from
+pub const TWITCH_GQL_URL: &str = "https://gql.twitch.tv/gql";
+
+/// the client id of the twitch website, the gql api does not accept the ones of apps
+const TWITCH_WEB_CLIENT_ID: &str = "kimne78kx3ncx6brgo4mv6wki5h1ko";
+
+/// the stored query the player of the website loads its chapter list with
+const CHAPTERS_QUERY_NAME: &str = "VideoPlayer_ChapterSelectButtonVideo";
+const CHAPTERS_QUERY_HASH: &str =
+    "8d2793384aac3773beab5e59bd5d6f585aedb923d292800119e03d40cd0f9b41";
+
+/// [VideoSource] for the VODs of twitch channels.
+///
+/// The chapters (the games that were played) are written to the
+/// [chapters sidecar](crate::chapters) of every download.
+pub struct TwitchSource<'a> {
+    client: TwitchClient<'a>,
+    http: reqwest::Client,
+    gql_url: String,
+}
+
+impl<'a> TwitchSource<'a> {
+    pub fn new(client: TwitchClient<'a>) -> Self {
+        Self {
+            client,
+            http: reqwest::Client::new(),
+            gql_url: TWITCH_GQL_URL.to_string(),
+        }
+    }
+
+    pub fn with_gql_url<S: Into<String>>(mut self, gql_url: S) -> Self {
+        self.gql_url = gql_url.into();
+        self
+    }
+}
+
+#[async_trait(?Send)]
+impl<'a> VideoSource for TwitchSource<'a> {
+    fn name(&self) -> &str {
+        TWITCH_SOURCE_NAME
+    }
+
+    async fn get_videos_for_streamer(&self, streamer: &Streamers) -> Result<Vec<VideoData>> {
+        trace!("Getting videos from streamer {}", streamer.login);
+        let videos = self
+            .client
+            .get_videos_from_login(&streamer.login, None)
+            .await
+            .map_err(|e| anyhow!("{}", e))?;
+
+        videos
+            .iter()
+            .map(|video| VideoData::from_twitch_video(video).map_err(|e| anyhow!("{}", e)))
+            .collect()
+    }
+
+    async fn get_video(&self, video_id: i64) -> Result<Option<VideoData>> {
+        let video_id = VideoId::new(video_id.to_string());
+        let video = self
+            .client
+            .get_video_info(&video_id)
+            .await
+            .map_err(|e| anyhow!("{}", e))?;
+        let video = convert_twitch_video_to_twitch_data_video(video);
+        let video = VideoData::from_twitch_video(&video).map_err(|e| anyhow!("{}", e))?;
+        Ok(Some(video))
+    }
+
+    async fn download_video(&self, video: &VideoData, folder: &Path) -> Result<PathBuf> {
+        let video_file_path = self
+            .client
+            .download_video(video.video.video_id.to_string(), "", folder)
+            .await
+            .map_err(|e| anyhow!("{}", e))?;
+        // the chapters are only used for the titles of the parts, the video can
+        // be backed up without them
+        match get_chapters(&self.http, &self.gql_url, video.video.video_id).await {
+            Ok(chapters) if chapters.is_empty() => {
+                debug!("Video {} has no chapters", video.video.video_id)
+            }
+            Ok(chapters) => chapters::write_sidecar(&video_file_path, &chapters).await?,
+            Err(e) => warn!(
+                "Could not get the chapters of video {}: {:?}",
+                video.video.video_id, e
+            ),
+        }
+        Ok(video_file_path)
+    }
+}
+
+/// Get the chapters of a video from the api of the twitch website.
+///
+/// Twitch only has chapters for videos in which the game changed, the others
+/// have none.
+pub async fn get_chapters(
+    http: &reqwest::Client,
+    gql_url: &str,
+    video_id: i64,
+) -> Result<Vec<Chapter>> {
+    let body = serde_json::json!({
+        "operationName": CHAPTERS_QUERY_NAME,
+        "variables": {
+            "includePrivate": false,
+            "videoID": video_id.to_string(),
+        },
+        "extensions": {
+            "persistedQuery": {
+                "version": 1,
+                "sha256Hash": CHAPTERS_QUERY_HASH,
+            },
+        },
+    });
+    let response = http
+        .post(gql_url)
+        .header("Client-Id", TWITCH_WEB_CLIENT_ID)
+        .header("Content-Type", "application/json")
+        .body(body.to_string())
+        .send()
+        .await?
+        .error_for_status()?;
+    let json = response.text().await?;
+    parse_chapters(&json).with_context(|| format!("invalid chapters of video {}", video_id))
+}
+
+/// read the chapters from the answer to the chapter query, sorted by their start
+pub fn parse_chapters(json: &str) -> Result<Vec<Chapter>> {
+    let response: GqlResponse = serde_json::from_str(json)?;
+    if let Some(error) = response.errors.first() {
+        return Err(anyhow!("twitch returned an error: {}", error.message));
+    }
+    let video = response
+        .data
+        .and_then(|data| data.video)
+        .ok_or_else(|| anyhow!("twitch does not know the video"))?;
+    let mut chapters: Vec<Chapter> = video
+        .moments
+        .map(|moments| moments.edges)
+        .unwrap_or_default()
+        .into_iter()
+        .map(|edge| {
+            let game = edge
+                .node
+                .details
+                .and_then(|d| d.game)
+                .map(|g| g.display_name);
+            Chapter {
+                start: edge.node.position_milliseconds as f64 / 1000.0,
+                title: game.or(edge.node.description).unwrap_or_default(),
+            }
+        })
+        .collect();
+    chapters.sort_by(|a, b| a.start.total_cmp(&b.start));
+    Ok(chapters)
+}
+
+#[derive(Debug, Deserialize)]
+struct GqlResponse {
+    data: Option<GqlData>,
+    #[serde(default)]
+    errors: Vec<GqlError>,
+}
+
+#[derive(Debug, Deserialize)]
+struct GqlError {
+    message: String,
+}
+
+#[derive(Debug, Deserialize)]
+struct GqlData {
+    video: Option<GqlVideo>,
+}
+
+#[derive(Debug, Deserialize)]
+struct GqlVideo {
+    moments: Option<GqlMoments>,
+}
+
+#[derive(Debug, Deserialize)]
+struct GqlMoments {
+    #[serde(default)]
+    edges: Vec<GqlMomentEdge>,
+}
+
+#[derive(Debug, Deserialize)]
+struct GqlMomentEdge {
+    node: GqlMoment,
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct GqlMoment {
+    position_milliseconds: i64,
+    description: Option<String>,
+    details: Option<GqlMomentDetails>,
+}
+
+#[derive(Debug, Deserialize)]
+struct GqlMomentDetails {
+    game: Option<GqlGame>,
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct GqlGame {
+    display_name: String,
+}
@@ -0,0 +1,255 @@
+use std::path::Path;
+use std::str::FromStr;
+
+use anyhow::{anyhow, Context, Result};
+use chrono::Duration;
+
+use crate::chapters::{self, Chapter};
+use crate::ffmpeg;
+use crate::prelude::*;
+
+/// the volume below which audio counts as silence
+const SILENCE_NOISE: &str = "-35dB";
+/// how long a silence has to last to be a candidate
+const SILENCE_MIN_SECONDS: f64 = 0.5;
+/// how different two frames have to be to count as a scene change (0 to 1)
+const SCENE_THRESHOLD: f64 = 0.3;
+/// ffmpeg cuts at the first keyframe after a split point, so the parts can
+/// end up this much longer than planned
+const KEYFRAME_SLACK_SECONDS: f64 = 10.0;
+
+/// Where the parts of a split are cut
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum SplitPointMode {
+    /// every segment time, wherever that is
+    #[default]
+    Time,
+    /// in a silence near the segment time
+    Silence,
+    /// at a scene change near the segment time
+    Scene,
+    /// at the start of a chapter, if one fits into the part
+    Chapters,
+}
+
+impl FromStr for SplitPointMode {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        match s.trim().to_lowercase().as_str() {
+            "time" => Ok(SplitPointMode::Time),
+            "silence" => Ok(SplitPointMode::Silence),
+            "scene" => Ok(SplitPointMode::Scene),
+            "chapters" => Ok(SplitPointMode::Chapters),
+            other => Err(anyhow!("unknown split point mode: {}", other)),
+        }
+    }
+}
+
+/// How the split points are searched for
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct SplitPoints {
+    pub mode: SplitPointMode,
+    /// how far before and after the segment time a cut may be placed
+    pub window: Duration,
+}
+
+/// A silence found by the `silencedetect` filter, in seconds
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Silence {
+    pub start: f64,
+    pub end: f64,
+}
+
+/// Find the times (in seconds from the start) the video should be cut at.
+///
+/// Every cut is placed near `segment_time` after the previous one, but a part
+/// is never longer than `max_part_time`. The last part is whatever is left
+/// once it is shorter than that. If nothing is found in the window, or the
+/// search fails, the cut falls back to the segment time.
+///
+/// With [SplitPointMode::Chapters] the window reaches from a quarter of the
+/// segment time up to the longest a part may be, so a part rather ends early
+/// or late than in the middle of a chapter.
+pub async fn find_split_points(
+    path: &Path,
+    total_seconds: f64,
+    segment_time: Duration,
+    max_part_time: Duration,
+    split_points: &SplitPoints,
+    chapters: &[Chapter],
+) -> Vec<f64> {
+    if split_points.mode == SplitPointMode::Chapters {
+        return chapter_split_points(total_seconds, segment_time, max_part_time, chapters);
+    }
+    let (target, max_part) = part_lengths(segment_time, max_part_time);
+    let window = split_points.window.num_seconds() as f64;
+    let mut cuts = vec![];
+    let mut start = 0.0;
+    while total_seconds - start >= max_part {
+        let earliest = start + (target - window).max(1.0);
+        let latest = (start + target + window).min(start + max_part);
+        let candidates = match find_candidates(path, split_points.mode, earliest, latest - earliest)
+            .await
+        {
+            Ok(candidates) => candidates,
+            Err(e) => {
+                warn!(
+                    "Could not search for split points around {:.0} seconds, cutting at the segment time: {:?}",
+                    start + target,
+                    e
+                );
+                vec![]
+            }
+        };
+        let cut = pick_split_point(start + target, earliest, latest, &candidates);
+        debug!("Cutting at {:.2} seconds", cut);
+        cuts.push(cut);
+        start = cut;
+    }
+    cuts
+}
+
+/// The cuts of [SplitPointMode::Chapters], they only depend on the chapters.
+///
+/// Without chapters every cut is at the segment time.
+pub fn chapter_split_points(
+    total_seconds: f64,
+    segment_time: Duration,
+    max_part_time: Duration,
+    chapters: &[Chapter],
+) -> Vec<f64> {
+    let (target, max_part) = part_lengths(segment_time, max_part_time);
+    let boundaries = chapters::boundaries(chapters);
+    let mut cuts = vec![];
+    let mut start = 0.0;
+    while total_seconds - start >= max_part {
+        let cut = pick_split_point(
+            start + target,
+            start + (target / 4.0).max(1.0),
+            start + max_part,
+            &boundaries,
+        );
+        debug!("Cutting at {:.2} seconds", cut);
+        cuts.push(cut);
+        start = cut;
+    }
+    cuts
+}
+
+/// the length a part is cut at and the longest it may be, in seconds
+fn part_lengths(segment_time: Duration, max_part_time: Duration) -> (f64, f64) {
+    let target = segment_time.num_seconds() as f64;
+    let max_part = (max_part_time.num_seconds() as f64 - KEYFRAME_SLACK_SECONDS).max(target);
+    (target, max_part)
+}
+
+/// the candidate closest to the target that is between earliest and latest,
+/// or the target itself (but not after latest) if there is none
+pub fn pick_split_point(target: f64, earliest: f64, latest: f64, candidates: &[f64]) -> f64 {
+    candidates
+        .iter()
+        .copied()
+        .filter(|c| *c >= earliest && *c <= latest)
+        .min_by(|a, b| (a - target).abs().total_cmp(&(b - target).abs()))
+        .unwrap_or_else(|| target.min(latest))
+}
+
+/// search `length` seconds of the video, starting at `from`, for split points
+async fn find_candidates(
+    path: &Path,
+    mode: SplitPointMode,
+    from: f64,
+    length: f64,
+) -> Result<Vec<f64>> {
+    let path = path
+        .to_str()
+        .ok_or_else(|| anyhow!("the path is not valid utf-8: {:?}", path))?;
+    let from_str = format!("{:.3}", from);
+    let length_str = format!("{:.3}", length);
+    let candidates = match mode {
+        SplitPointMode::Time | SplitPointMode::Chapters => return Ok(vec![]),
+        SplitPointMode::Silence => {
+            let filter = format!(
+                "silencedetect=noise={}:d={}",
+                SILENCE_NOISE, SILENCE_MIN_SECONDS
+            );
+            let output = ffmpeg::run_ffmpeg([
+                "-ss",
+                &from_str,
+                "-t",
+                &length_str,
+                "-i",
+                path,
+                "-vn",
+                "-af",
+                &filter,
+                "-f",
+                "null",
+                "-",
+            ])
+            .await
+            .context("ffmpeg could not search for silence")?;
+            // cut in the middle of the silence, away from the words around it
+            parse_silences(&output.stderr)
+                .into_iter()
+                .map(|s| (s.start + s.end) / 2.0)
+                .collect::<Vec<_>>()
+        }
+        SplitPointMode::Scene => {
+            let filter = format!("select='gt(scene,{})',showinfo", SCENE_THRESHOLD);
+            let output = ffmpeg::run_ffmpeg([
+                "-ss",
+                &from_str,
+                "-t",
+                &length_str,
+                "-i",
+                path,
+                "-an",
+                "-vf",
+                &filter,
+                "-f",
+                "null",
+                "-",
+            ])
+            .await
+            .context("ffmpeg could not search for scene changes")?;
+            parse_scene_changes(&output.stderr)
+        }
+    };
+    // the timestamps start at 0 after seeking to `from`
+    Ok(candidates.into_iter().map(|c| c + from).collect())
+}
+
+/// read the silences from the stderr of the `silencedetect` filter
+///
+/// a silence that lasts until the end of the input has no end and is skipped
+pub fn parse_silences(stderr: &str) -> Vec<Silence> {
+    let mut silences = vec![];
+    let mut start = None;
+    for line in stderr.lines() {
+        if let Some(value) = value_after(line, "silence_start:") {
+            start = Some(value);
+        } else if let Some(end) = value_after(line, "silence_end:") {
+            if let Some(start) = start.take() {
+                silences.push(Silence { start, end });
+            }
+        }
+    }
+    silences
+}
+
+/// read the times of the frames the `showinfo` filter printed
+pub fn parse_scene_changes(stderr: &str) -> Vec<f64> {
+    stderr
+        .lines()
+        .filter(|line| line.contains("Parsed_showinfo"))
+        .filter_map(|line| value_after(line, "pts_time:"))
+        .collect()
+}
+
+/// the number after the key, like `12.5` in `silence_end: 12.5 | silence_duration: 2`
+fn value_after(line: &str, key: &str) -> Option<f64> {
+    let (_, rest) = line.split_once(key)?;
+    rest.split_whitespace().next()?.parse().ok()
+}
@@ -0,0 +1,344 @@
+use anyhow::{anyhow, Context, Result};
+use async_trait::async_trait;
+use google_bigquery_v2::prelude::*;
+use nameof::name_of;
+
+use crate::data::{
+    StreamerDestinations, Streamers, VideoMetadata, VideoParts, VideoUploads, Videos,
+};
+use crate::prelude::*;
+use crate::store::Store;
+
+/// [Store] implementation that keeps everything in BigQuery tables
+#[derive(Debug, Clone)]
+pub struct BigqueryStore {
+    client: BigqueryClient,
+}
+
+impl BigqueryStore {
+    pub fn new(client: BigqueryClient) -> Self {
+        Self { client }
+    }
+
+    /// create the [BigqueryClient] with the given project/dataset and
+    /// wrap it in a store
+    pub async fn connect(
+        project_id: &str,
+        dataset_id: &str,
+        service_account_path: &str,
+    ) -> Result<Self> {
+        let client = BigqueryClient::new(project_id, dataset_id, Some(service_account_path))
+            .await
+            .map_err(|e| anyhow!("{}", e))?;
+        let store = Self::new(client);
+        store.check_schema().await?;
+        Ok(store)
+    }
+
+    /// Fail on start if the dataset is missing a table or column.
+    ///
+    /// Every query selects all columns of its struct, so a dataset that was
+    /// created for an older version would otherwise only fail in the middle
+    /// of a backup. `bigquery_schema.sql` next to this file adds what is missing.
+    pub async fn check_schema(&self) -> Result<()> {
+        let hint = |table: &str| {
+            format!(
+                "the bigquery table {} does not match the schema, \
+                 run src/store/bigquery_schema.sql on the dataset",
+                table
+            )
+        };
+        Videos::select()
+            .with_client(self.client.clone())
+            .set_limit(1)
+            .build_query()?
+            .run()
+            .await
+            .with_context(|| hint("videos"))?
+            .map_err_with_data("Error checking the videos table")
+            .with_context(|| hint("videos"))?;
+        VideoMetadata::select()
+            .with_client(self.client.clone())
+            .set_limit(1)
+            .build_query()?
+            .run()
+            .await
+            .with_context(|| hint("video_metadata"))?
+            .map_err_with_data("Error checking the video_metadata table")
+            .with_context(|| hint("video_metadata"))?;
+        VideoParts::select()
+            .with_client(self.client.clone())
+            .set_limit(1)
+            .build_query()?
+            .run()
+            .await
+            .with_context(|| hint("video_parts"))?
+            .map_err_with_data("Error checking the video_parts table")
+            .with_context(|| hint("video_parts"))?;
+        StreamerDestinations::select()
+            .with_client(self.client.clone())
+            .set_limit(1)
+            .build_query()?
+            .run()
+            .await
+            .with_context(|| hint("streamer_destinations"))?
+            .map_err_with_data("Error checking the streamer_destinations table")
+            .with_context(|| hint("streamer_destinations"))?;
+        VideoUploads::select()
+            .with_client(self.client.clone())
+            .set_limit(1)
+            .build_query()?
+            .run()
+            .await
+            .with_context(|| hint("video_uploads"))?
+            .map_err_with_data("Error checking the video_uploads table")
+            .with_context(|| hint("video_uploads"))?;
+        Ok(())
+    }
+
+    pub fn client(&self) -> &BigqueryClient {
+        &self.client
+    }
+}
+
+#[async_trait(?Send)]
+impl Store for BigqueryStore {
+    async fn get_watched_streamers(&self) -> Result<Vec<Streamers>> {
+        trace!("Getting watched streamers");
+        let watched = Streamers::select()
+            .with_client(self.client.clone())
+            .add_where_eq(name_of!(watched in Streamers), Some(&true))
+            .map_err(|e| anyhow!("{}", e))?
+            .set_limit(1000)
+            .build_query()
+            .map_err(|e| anyhow!("{}", e))?
+            .run()
+            .await
+            .map_err(|e| anyhow!("{}", e))?;
+        let watched = watched
+            .map_err_with_data("Error getting watched streamers")
+            .map_err(|e| anyhow!("{}", e))?;
+        Ok(watched)
+    }
+
+    async fn get_streamer(&self, login: &str) -> Result<Option<Streamers>> {
+        // not get_by_pk, it fails the same way for a missing row and a failed query
+        let streamers = Streamers::select()
+            .with_client(self.client.clone())
+            .add_where_eq(name_of!(login in Streamers), Some(&login.to_string()))
+            .context("could not add login where")?
+            .set_limit(1)
+            .build_query()?
+            .run()
+            .await?
+            .map_err_with_data("Error getting streamer from db")?;
+        Ok(streamers.into_iter().next())
+    }
+
+    async fn upsert_streamer(&self, streamer: &Streamers) -> Result<()> {
+        let mut streamer = streamer.clone();
+        streamer.client = self.client.clone();
+        streamer
+            .upsert()
+            .await
+            .map_err(|e| anyhow!("error saving streamer: {}", e))?;
+        Ok(())
+    }
+
+    async fn delete_streamer(&self, login: &str) -> Result<bool> {
+        // the query builder of the client can only select, insert and update rows
+        Err(anyhow!(
+            "the bigquery store can not delete streamer {}, stop watching it instead",
+            login
+        ))
+    }
+
+    fn can_delete_streamers(&self) -> bool {
+        false
+    }
+
+    async fn get_video(&self, video_id: i64) -> Result<Option<Videos>> {
+        let videos = Videos::select()
+            .with_client(self.client.clone())
+            .add_where_eq(name_of!(video_id in Videos), Some(&video_id))
+            .context("could not add video_id where")?
+            .set_limit(1)
+            .build_query()?
+            .run()
+            .await?
+            .map_err_with_data("Error getting video from db")?;
+        Ok(videos.into_iter().next())
+    }
+
+    async fn get_video_metadata(&self, video_id: i64) -> Result<Option<VideoMetadata>> {
+        let video_metadata = VideoMetadata::select()
+            .with_client(self.client.clone())
+            .add_where_eq(name_of!(video_id in VideoMetadata), Some(&video_id))
+            .context("could not add video_id where")?
+            .set_limit(1)
+            .build_query()?
+            .run()
+            .await?
+            .map_err_with_data("Error getting video metadata from db")?;
+        Ok(video_metadata.into_iter().next())
+    }
+
+    async fn get_not_downloaded_video_metadata(&self) -> Result<Vec<VideoMetadata>> {
+        //TODO: make sure that this is sorted by date (oldest first)
+        let mut video_metadata_list = VideoMetadata::select()
+            .with_client(self.client.clone())
+            .add_where_eq(name_of!(backed_up in VideoMetadata), Some(&false))
+            .context("could not add backed_up where")?
+            .add_where_eq::<String>(name_of!(error in VideoMetadata), None)
+            .context("could not add error where")?
+            .add_order_by(
+                name_of!(video_id in VideoMetadata),
+                OrderDirection::Ascending,
+            )
+            //TODO: check if ordering by video_id is correct (should be oldest first)
+            //TODO: sort this by streamer (join is needed)
+            .set_limit(1000)
+            .build_query()?
+            .run()
+            .await?
+            .map_err_with_data("Error getting not downloaded videos from db")?;
+
+        // the query builder can only AND equality checks, so the videos that
+        // wait for a retry are loaded separately and filtered here
+        let now = chrono::Utc::now();
+        let retries = VideoMetadata::select()
+            .with_client(self.client.clone())
+            .add_where_eq(name_of!(backed_up in VideoMetadata), Some(&false))
+            .context("could not add backed_up where")?
+            .add_where_eq(name_of!(error_transient in VideoMetadata), Some(&true))
+            .context("could not add error_transient where")?
+            .set_limit(1000)
+            .build_query()?
+            .run()
+            .await?
+            .map_err_with_data("Error getting videos to retry from db")?;
+        video_metadata_list.extend(retries.into_iter().filter(|m| m.is_pending(now)));
+        video_metadata_list.sort_by_key(|m| m.video_id);
+        video_metadata_list.dedup_by_key(|m| m.video_id);
+        video_metadata_list.truncate(1000);
+        Ok(video_metadata_list)
+    }
+
+    async fn upsert_video(&self, video: &Videos) -> Result<()> {
+        let mut video = video.clone();
+        video.client = self.client.clone();
+        video
+            .upsert()
+            .await
+            .map_err(|e| anyhow!("error saving video data: {}", e))?;
+        Ok(())
+    }
+
+    async fn upsert_video_metadata(&self, metadata: &VideoMetadata) -> Result<()> {
+        let mut metadata = metadata.clone();
+        metadata.client = self.client.clone();
+        metadata
+            .upsert()
+            .await
+            .map_err(|e| anyhow!("error saving video metadata: {}", e))?;
+        Ok(())
+    }
+
+    async fn save_video_metadata(&self, metadata: &VideoMetadata) -> Result<()> {
+        let mut metadata = metadata.clone();
+        metadata.client = self.client.clone();
+        metadata
+            .save()
+            .await
+            .map_err(|e| anyhow!("error saving video metadata: {}", e))?;
+        Ok(())
+    }
+
+    async fn get_video_parts(&self, video_id: i64) -> Result<Vec<VideoParts>> {
+        let video_parts = VideoParts::select()
+            .with_client(self.client.clone())
+            .add_where_eq(name_of!(video_id in VideoParts), Some(&video_id))
+            .context("could not add video_id where")?
+            .add_order_by(
+                name_of!(destination in VideoParts),
+                OrderDirection::Ascending,
+            )
+            .add_order_by(name_of!(part in VideoParts), OrderDirection::Ascending)
+            .set_limit(1000)
+            .build_query()?
+            .run()
+            .await?
+            .map_err_with_data("Error getting video parts from db")?;
+        Ok(video_parts)
+    }
+
+    async fn upsert_video_part(&self, video_part: &VideoParts) -> Result<()> {
+        let mut video_part = video_part.clone();
+        video_part.client = self.client.clone();
+        video_part
+            .upsert()
+            .await
+            .map_err(|e| anyhow!("error saving video part: {}", e))?;
+        Ok(())
+    }
+
+    async fn get_streamer_destinations(
+        &self,
+        streamer_login: &str,
+    ) -> Result<Vec<StreamerDestinations>> {
+        let destinations = StreamerDestinations::select()
+            .with_client(self.client.clone())
+            .add_where_eq(
+                name_of!(streamer_login in StreamerDestinations),
+                Some(&streamer_login.to_string()),
+            )
+            .context("could not add streamer_login where")?
+            .add_order_by(
+                name_of!(destination in StreamerDestinations),
+                OrderDirection::Ascending,
+            )
+            .set_limit(1000)
+            .build_query()?
+            .run()
+            .await?
+            .map_err_with_data("Error getting streamer destinations from db")?;
+        Ok(destinations)
+    }
+
+    async fn upsert_streamer_destination(&self, destination: &StreamerDestinations) -> Result<()> {
+        let mut destination = destination.clone();
+        destination.client = self.client.clone();
+        destination
+            .upsert()
+            .await
+            .map_err(|e| anyhow!("error saving streamer destination: {}", e))?;
+        Ok(())
+    }
+
+    async fn get_video_uploads(&self, video_id: i64) -> Result<Vec<VideoUploads>> {
+        let video_uploads = VideoUploads::select()
+            .with_client(self.client.clone())
+            .add_where_eq(name_of!(video_id in VideoUploads), Some(&video_id))
+            .context("could not add video_id where")?
+            .add_order_by(
+                name_of!(destination in VideoUploads),
+                OrderDirection::Ascending,
+            )
+            .set_limit(1000)
+            .build_query()?
+            .run()
+            .await?
+            .map_err_with_data("Error getting video uploads from db")?;
+        Ok(video_uploads)
+    }
+
+    async fn upsert_video_upload(&self, video_upload: &VideoUploads) -> Result<()> {
+        let mut video_upload = video_upload.clone();
+        video_upload.client = self.client.clone();
+        video_upload
+            .upsert()
+            .await
+            .map_err(|e| anyhow!("error saving video upload: {}", e))?;
+        Ok(())
+    }
+}
@@ -0,0 +1,52 @@
+-- Brings a dataset that was created for an older version to the schema of the
+-- structs in `crate::data`, it mirrors the migrations of the sqlite store.
+--
+-- Every statement can be run again, run it with the dataset as default:
+--   bq query --use_legacy_sql=false --dataset_id=<project>:<dataset> < src/store/bigquery_schema.sql
+
+-- videos can come from different sources
+ALTER TABLE videos
+    ADD COLUMN IF NOT EXISTS source STRING;
+
+-- the stage of the backup, so it can continue after a restart, and the
+-- retries of failed videos with the kind of their error
+ALTER TABLE video_metadata
+    ADD COLUMN IF NOT EXISTS stage STRING,
+    ADD COLUMN IF NOT EXISTS stage_updated_at TIMESTAMP,
+    ADD COLUMN IF NOT EXISTS download_path STRING,
+    ADD COLUMN IF NOT EXISTS retry_count INT64,
+    ADD COLUMN IF NOT EXISTS next_attempt_at TIMESTAMP,
+    ADD COLUMN IF NOT EXISTS error_transient BOOL,
+    ADD COLUMN IF NOT EXISTS error_code STRING;
+
+-- uploaded parts, so failed uploads can resume from the first missing part
+CREATE TABLE IF NOT EXISTS video_parts (
+    id STRING NOT NULL,
+    video_id INT64,
+    destination STRING,
+    part INT64,
+    remote_id STRING,
+    url STRING,
+    uploaded_at TIMESTAMP,
+    duration INT64,
+    file_hash STRING,
+    status STRING
+);
+
+-- multiple destinations per streamer
+CREATE TABLE IF NOT EXISTS streamer_destinations (
+    id STRING NOT NULL,
+    streamer_login STRING,
+    destination STRING,
+    required BOOL
+);
+
+CREATE TABLE IF NOT EXISTS video_uploads (
+    id STRING NOT NULL,
+    video_id INT64,
+    destination STRING,
+    backed_up BOOL,
+    error STRING,
+    collection_url STRING,
+    updated_at TIMESTAMP
+);
@@ -0,0 +1,188 @@
+use std::collections::BTreeMap;
+use std::sync::{Mutex, MutexGuard};
+
+use anyhow::{anyhow, Result};
+use async_trait::async_trait;
+
+use crate::data::{
+    StreamerDestinations, Streamers, VideoMetadata, VideoParts, VideoUploads, Videos,
+};
+use crate::store::Store;
+
+/// [Store] implementation that only keeps everything in memory.
+///
+/// Nothing is persisted, so this is mostly useful for tests and for trying
+/// out the pipeline without any credentials.
+#[derive(Debug, Default)]
+pub struct InMemoryStore {
+    tables: Mutex<Tables>,
+}
+
+#[derive(Debug, Default)]
+struct Tables {
+    streamers: BTreeMap<String, Streamers>,
+    videos: BTreeMap<i64, Videos>,
+    video_metadata: BTreeMap<i64, VideoMetadata>,
+    video_parts: BTreeMap<String, VideoParts>,
+    streamer_destinations: BTreeMap<String, StreamerDestinations>,
+    video_uploads: BTreeMap<String, VideoUploads>,
+}
+
+impl InMemoryStore {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    fn tables(&self) -> Result<MutexGuard<'_, Tables>> {
+        self.tables
+            .lock()
+            .map_err(|e| anyhow!("in memory store mutex is poisoned: {}", e))
+    }
+}
+
+#[async_trait(?Send)]
+impl Store for InMemoryStore {
+    async fn get_watched_streamers(&self) -> Result<Vec<Streamers>> {
+        let tables = self.tables()?;
+        let watched = tables
+            .streamers
+            .values()
+            .filter(|s| s.watched == Some(true))
+            .cloned()
+            .collect();
+        Ok(watched)
+    }
+
+    async fn get_streamer(&self, login: &str) -> Result<Option<Streamers>> {
+        Ok(self.tables()?.streamers.get(login).cloned())
+    }
+
+    async fn upsert_streamer(&self, streamer: &Streamers) -> Result<()> {
+        let mut tables = self.tables()?;
+        tables
+            .streamers
+            .insert(streamer.login.clone(), streamer.clone());
+        Ok(())
+    }
+
+    async fn delete_streamer(&self, login: &str) -> Result<bool> {
+        let mut tables = self.tables()?;
+        tables
+            .streamer_destinations
+            .retain(|_, d| d.streamer_login.as_deref() != Some(login));
+        Ok(tables.streamers.remove(login).is_some())
+    }
+
+    async fn get_video(&self, video_id: i64) -> Result<Option<Videos>> {
+        Ok(self.tables()?.videos.get(&video_id).cloned())
+    }
+
+    async fn get_video_metadata(&self, video_id: i64) -> Result<Option<VideoMetadata>> {
+        Ok(self.tables()?.video_metadata.get(&video_id).cloned())
+    }
+
+    async fn get_not_downloaded_video_metadata(&self) -> Result<Vec<VideoMetadata>> {
+        let tables = self.tables()?;
+        let now = chrono::Utc::now();
+        // the BTreeMap is ordered by the video id already
+        let pending = tables
+            .video_metadata
+            .values()
+            .filter(|m| m.is_pending(now))
+            .take(1000)
+            .cloned()
+            .collect();
+        Ok(pending)
+    }
+
+    async fn upsert_video(&self, video: &Videos) -> Result<()> {
+        let mut tables = self.tables()?;
+        tables.videos.insert(video.video_id, video.clone());
+        Ok(())
+    }
+
+    async fn upsert_video_metadata(&self, metadata: &VideoMetadata) -> Result<()> {
+        let mut tables = self.tables()?;
+        tables
+            .video_metadata
+            .insert(metadata.video_id, metadata.clone());
+        Ok(())
+    }
+
+    async fn save_video_metadata(&self, metadata: &VideoMetadata) -> Result<()> {
+        let mut tables = self.tables()?;
+        match tables.video_metadata.get_mut(&metadata.video_id) {
+            Some(existing) => {
+                *existing = metadata.clone();
+                Ok(())
+            }
+            None => Err(anyhow!(
+                "could not save metadata for video {}: it is not in the store",
+                metadata.video_id
+            )),
+        }
+    }
+
+    async fn get_video_parts(&self, video_id: i64) -> Result<Vec<VideoParts>> {
+        let tables = self.tables()?;
+        let mut video_parts: Vec<VideoParts> = tables
+            .video_parts
+            .values()
+            .filter(|p| p.video_id == Some(video_id))
+            .cloned()
+            .collect();
+        video_parts.sort_by(|a, b| (&a.destination, a.part).cmp(&(&b.destination, b.part)));
+        Ok(video_parts)
+    }
+
+    async fn upsert_video_part(&self, video_part: &VideoParts) -> Result<()> {
+        let mut tables = self.tables()?;
+        tables
+            .video_parts
+            .insert(video_part.id.clone(), video_part.clone());
+        Ok(())
+    }
+
+    async fn get_streamer_destinations(
+        &self,
+        streamer_login: &str,
+    ) -> Result<Vec<StreamerDestinations>> {
+        let tables = self.tables()?;
+        let mut destinations: Vec<StreamerDestinations> = tables
+            .streamer_destinations
+            .values()
+            .filter(|d| d.streamer_login.as_deref() == Some(streamer_login))
+            .cloned()
+            .collect();
+        destinations.sort_by(|a, b| a.destination.cmp(&b.destination));
+        Ok(destinations)
+    }
+
+    async fn upsert_streamer_destination(&self, destination: &StreamerDestinations) -> Result<()> {
+        let mut tables = self.tables()?;
+        tables
+            .streamer_destinations
+            .insert(destination.id.clone(), destination.clone());
+        Ok(())
+    }
+
+    async fn get_video_uploads(&self, video_id: i64) -> Result<Vec<VideoUploads>> {
+        let tables = self.tables()?;
+        let mut video_uploads: Vec<VideoUploads> = tables
+            .video_uploads
+            .values()
+            .filter(|u| u.video_id == Some(video_id))
+            .cloned()
+            .collect();
+        video_uploads.sort_by(|a, b| a.destination.cmp(&b.destination));
+        Ok(video_uploads)
+    }
+
+    async fn upsert_video_upload(&self, video_upload: &VideoUploads) -> Result<()> {
+        let mut tables = self.tables()?;
+        tables
+            .video_uploads
+            .insert(video_upload.id.clone(), video_upload.clone());
+        Ok(())
+    }
+}
@@ -0,0 +1,89 @@
+use anyhow::Result;
+use async_trait::async_trait;
+use downloader_config::Config;
+
+use crate::data::{
+    StreamerDestinations, Streamers, VideoMetadata, VideoParts, VideoUploads, Videos,
+};
+use crate::prelude::*;
+use crate::settings::{Settings, StoreBackend};
+
+pub use bigquery::BigqueryStore;
+pub use memory::InMemoryStore;
+pub use sqlite::SqliteStore;
+
+pub mod bigquery;
+pub mod memory;
+pub mod sqlite;
+
+/// The persistence layer of the pipeline.
+///
+/// Every query the backup pipeline performs goes through this trait, so the rest
+/// of the crate does not need to know which database is behind it.
+#[async_trait(?Send)]
+pub trait Store {
+    /// get all streamers that have the `watched` flag set
+    async fn get_watched_streamers(&self) -> Result<Vec<Streamers>>;
+    /// get a streamer by its login (primary key)
+    async fn get_streamer(&self, login: &str) -> Result<Option<Streamers>>;
+    /// insert the streamer or update it if it already exists
+    async fn upsert_streamer(&self, streamer: &Streamers) -> Result<()>;
+    /// delete a streamer and its destinations, returns false if it did not exist
+    ///
+    /// the videos of the streamer are kept
+    async fn delete_streamer(&self, login: &str) -> Result<bool>;
+    /// if [Store::delete_streamer] is supported, checked before anything is changed
+    fn can_delete_streamers(&self) -> bool {
+        true
+    }
+    /// get a video by its id (primary key)
+    async fn get_video(&self, video_id: i64) -> Result<Option<Videos>>;
+    /// get the metadata of a video by its id (primary key)
+    async fn get_video_metadata(&self, video_id: i64) -> Result<Option<VideoMetadata>>;
+    /// get the metadata of all videos that are not backed up yet and have no error
+    /// or a transient one that is due for a retry, ordered by their id (oldest first),
+    /// see [VideoMetadata::is_pending]
+    async fn get_not_downloaded_video_metadata(&self) -> Result<Vec<VideoMetadata>>;
+    /// insert the video or update it if it already exists
+    async fn upsert_video(&self, video: &Videos) -> Result<()>;
+    /// insert the metadata or update it if it already exists
+    async fn upsert_video_metadata(&self, metadata: &VideoMetadata) -> Result<()>;
+    /// save changes to the metadata of a video that is already in the store
+    async fn save_video_metadata(&self, metadata: &VideoMetadata) -> Result<()>;
+    /// get all parts of a video at all destinations, ordered by destination and part
+    async fn get_video_parts(&self, video_id: i64) -> Result<Vec<VideoParts>>;
+    /// insert the part or update it if it already exists
+    async fn upsert_video_part(&self, video_part: &VideoParts) -> Result<()>;
+    /// get the destinations of a streamer, ordered by their name
+    async fn get_streamer_destinations(
+        &self,
+        streamer_login: &str,
+    ) -> Result<Vec<StreamerDestinations>>;
+    /// insert the destination of a streamer or update it if it already exists
+    async fn upsert_streamer_destination(&self, destination: &StreamerDestinations) -> Result<()>;
+    /// get the state of a video at all destinations it was uploaded to
+    async fn get_video_uploads(&self, video_id: i64) -> Result<Vec<VideoUploads>>;
+    /// insert the state of a video at a destination or update it if it already exists
+    async fn upsert_video_upload(&self, video_upload: &VideoUploads) -> Result<()>;
+}
+
+/// create the store that is selected by the `STORE_BACKEND` setting
+pub async fn create_store(config: &Config, settings: &Settings) -> Result<Box<dyn Store>> {
+    match settings.store_backend {
+        StoreBackend::Bigquery => {
+            info!("creating BigqueryStore");
+            let store = BigqueryStore::connect(
+                &config.bigquery_project_id,
+                &config.bigquery_dataset_id,
+                &config.bigquery_service_account_path,
+            )
+            .await?;
+            Ok(Box::new(store))
+        }
+        StoreBackend::Sqlite => {
+            info!("creating SqliteStore");
+            let store = SqliteStore::open(&settings.sqlite_db_path)?;
+            Ok(Box::new(store))
+        }
+    }
+}
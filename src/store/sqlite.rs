@@ -0,0 +1,600 @@
+use std::path::Path;
+use std::sync::{Mutex, MutexGuard};
+
+use anyhow::{anyhow, Context, Result};
+use async_trait::async_trait;
+use rusqlite::{params, Connection, OptionalExtension, Row};
+
+use crate::data::{
+    StreamerDestinations, Streamers, VideoMetadata, VideoParts, VideoUploads, Videos,
+};
+use crate::prelude::*;
+use crate::store::Store;
+
+/// The migrations that bring the database schema to the current version.
+///
+/// The position in this list is the schema version (stored in `PRAGMA user_version`),
+/// so existing entries must never be changed, only new ones appended.
+const MIGRATIONS: &[&str] = &[
+    // 1: initial schema, mirrors the structs in `crate::data`
+    "CREATE TABLE streamers (
+        login TEXT PRIMARY KEY NOT NULL,
+        display_name TEXT,
+        watched INTEGER,
+        youtube_user TEXT,
+        public_videos_default INTEGER,
+        youtube_google_ident TEXT
+    );
+    CREATE TABLE videos (
+        video_id INTEGER PRIMARY KEY NOT NULL,
+        title TEXT,
+        description TEXT,
+        bool_test INTEGER,
+        user_login TEXT,
+        created_at TEXT,
+        url TEXT,
+        viewable TEXT,
+        language TEXT,
+        view_count INTEGER,
+        video_type TEXT,
+        duration INTEGER,
+        thumbnail_url TEXT
+    );
+    CREATE TABLE video_metadata (
+        video_id INTEGER PRIMARY KEY NOT NULL,
+        backed_up INTEGER,
+        total_clips_amount INTEGER,
+        parts_backed_up_id INTEGER,
+        parts_size INTEGER,
+        error TEXT,
+        download_playlist_url TEXT,
+        youtube_playlist_url TEXT
+    );",
+    // 2: videos can come from different sources
+    "ALTER TABLE videos ADD COLUMN source TEXT;",
+    // 3: uploaded parts, so the destinations can be looked up later
+    "CREATE TABLE part_uploads (
+        id TEXT PRIMARY KEY NOT NULL,
+        video_id INTEGER,
+        destination TEXT,
+        part INTEGER,
+        remote_id TEXT,
+        url TEXT,
+        uploaded_at TEXT
+    );
+    CREATE INDEX part_uploads_video_id ON part_uploads (video_id);",
+    // 4: multiple destinations per streamer
+    "CREATE TABLE streamer_destinations (
+        id TEXT PRIMARY KEY NOT NULL,
+        streamer_login TEXT,
+        destination TEXT,
+        required INTEGER
+    );
+    CREATE INDEX streamer_destinations_streamer_login ON streamer_destinations (streamer_login);
+    CREATE TABLE video_uploads (
+        id TEXT PRIMARY KEY NOT NULL,
+        video_id INTEGER,
+        destination TEXT,
+        backed_up INTEGER,
+        error TEXT,
+        collection_url TEXT,
+        updated_at TEXT
+    );
+    CREATE INDEX video_uploads_video_id ON video_uploads (video_id);",
+    // 5: parts keep their state so failed uploads can resume from the first missing part,
+    // everything recorded before was uploaded
+    "ALTER TABLE part_uploads RENAME TO video_parts;
+    ALTER TABLE video_parts ADD COLUMN duration INTEGER;
+    ALTER TABLE video_parts ADD COLUMN file_hash TEXT;
+    ALTER TABLE video_parts ADD COLUMN status TEXT;
+    UPDATE video_parts SET status = 'uploaded';
+    DROP INDEX part_uploads_video_id;
+    CREATE INDEX video_parts_video_id ON video_parts (video_id);",
+    // 6: the stage of the backup, so it can continue after a restart
+    "ALTER TABLE video_metadata ADD COLUMN stage TEXT;
+    ALTER TABLE video_metadata ADD COLUMN stage_updated_at TEXT;
+    ALTER TABLE video_metadata ADD COLUMN download_path TEXT;",
+    // 7: failed videos are retried
+    "ALTER TABLE video_metadata ADD COLUMN retry_count INTEGER;
+    ALTER TABLE video_metadata ADD COLUMN next_attempt_at TEXT;
+    ALTER TABLE video_metadata ADD COLUMN error_transient INTEGER;",
+    // 8: the kind of error next to the message
+    "ALTER TABLE video_metadata ADD COLUMN error_code TEXT;",
+];
+
+const STREAMER_COLUMNS: &str =
+    "login, display_name, watched, youtube_user, public_videos_default, youtube_google_ident";
+const VIDEO_COLUMNS: &str = "video_id, title, description, bool_test, user_login, created_at, \
+    url, viewable, language, view_count, video_type, duration, thumbnail_url, source";
+const VIDEO_METADATA_COLUMNS: &str = "video_id, backed_up, total_clips_amount, \
+    parts_backed_up_id, parts_size, error, download_playlist_url, youtube_playlist_url, \
+    stage, stage_updated_at, download_path, retry_count, next_attempt_at, error_transient, \
+    error_code";
+const STREAMER_DESTINATION_COLUMNS: &str = "id, streamer_login, destination, required";
+const VIDEO_UPLOAD_COLUMNS: &str =
+    "id, video_id, destination, backed_up, error, collection_url, updated_at";
+const VIDEO_PART_COLUMNS: &str = "id, video_id, destination, part, remote_id, url, uploaded_at, \
+    duration, file_hash, status";
+
+/// [Store] implementation backed by an embedded sqlite database.
+///
+/// Meant for self-hosted deployments where a few thousand rows do not justify
+/// a BigQuery project. The schema is created/migrated when the store is opened.
+pub struct SqliteStore {
+    connection: Mutex<Connection>,
+}
+
+impl SqliteStore {
+    /// open (or create) the database file at the given path and migrate it
+    pub fn open<P: AsRef<Path>>(path: P) -> Result<Self> {
+        let path = path.as_ref();
+        info!("Opening sqlite database: {}", path.display());
+        if let Some(parent) = path.parent() {
+            std::fs::create_dir_all(parent)
+                .with_context(|| format!("could not create folder for {}", path.display()))?;
+        }
+        let connection = Connection::open(path)
+            .with_context(|| format!("could not open sqlite database {}", path.display()))?;
+        Self::from_connection(connection)
+    }
+
+    /// open a fresh database that only lives in memory
+    pub fn open_in_memory() -> Result<Self> {
+        let connection = Connection::open_in_memory()?;
+        Self::from_connection(connection)
+    }
+
+    fn from_connection(mut connection: Connection) -> Result<Self> {
+        migrate(&mut connection)?;
+        Ok(Self {
+            connection: Mutex::new(connection),
+        })
+    }
+
+    /// the schema version the database is at
+    pub fn schema_version(&self) -> Result<usize> {
+        let connection = self.connection()?;
+        get_schema_version(&connection)
+    }
+
+    fn connection(&self) -> Result<MutexGuard<'_, Connection>> {
+        self.connection
+            .lock()
+            .map_err(|e| anyhow!("sqlite connection mutex is poisoned: {}", e))
+    }
+}
+
+fn get_schema_version(connection: &Connection) -> Result<usize> {
+    let version: i64 = connection.query_row("PRAGMA user_version", [], |row| row.get(0))?;
+    Ok(version as usize)
+}
+
+/// run all migrations the database has not seen yet
+fn migrate(connection: &mut Connection) -> Result<()> {
+    let current = get_schema_version(connection)?;
+    if current > MIGRATIONS.len() {
+        return Err(anyhow!(
+            "database schema version {} is newer than this program knows ({})",
+            current,
+            MIGRATIONS.len()
+        ));
+    }
+    for (i, migration) in MIGRATIONS.iter().enumerate().skip(current) {
+        let version = i + 1;
+        info!("Migrating sqlite database to schema version {}", version);
+        let transaction = connection.transaction()?;
+        transaction
+            .execute_batch(migration)
+            .with_context(|| format!("could not run migration {}", version))?;
+        transaction.pragma_update(None, "user_version", version as i64)?;
+        transaction.commit()?;
+    }
+    Ok(())
+}
+
+//region row mapping
+fn streamer_from_row(row: &Row) -> rusqlite::Result<Streamers> {
+    Ok(Streamers {
+        login: row.get(0)?,
+        display_name: row.get(1)?,
+        watched: row.get(2)?,
+        youtube_user: row.get(3)?,
+        public_videos_default: row.get(4)?,
+        youtube_google_ident: row.get(5)?,
+        ..Default::default()
+    })
+}
+
+fn video_from_row(row: &Row) -> rusqlite::Result<Videos> {
+    Ok(Videos {
+        video_id: row.get(0)?,
+        title: row.get(1)?,
+        description: row.get(2)?,
+        bool_test: row.get(3)?,
+        user_login: row.get(4)?,
+        created_at: row.get(5)?,
+        url: row.get(6)?,
+        viewable: row.get(7)?,
+        language: row.get(8)?,
+        view_count: row.get(9)?,
+        video_type: row.get(10)?,
+        duration: row.get(11)?,
+        thumbnail_url: row.get(12)?,
+        source: row.get(13)?,
+        ..Default::default()
+    })
+}
+
+fn video_metadata_from_row(row: &Row) -> rusqlite::Result<VideoMetadata> {
+    Ok(VideoMetadata {
+        video_id: row.get(0)?,
+        backed_up: row.get(1)?,
+        total_clips_amount: row.get(2)?,
+        parts_backed_up_id: row.get(3)?,
+        parts_size: row.get(4)?,
+        error: row.get(5)?,
+        download_playlist_url: row.get(6)?,
+        youtube_playlist_url: row.get(7)?,
+        stage: row.get(8)?,
+        stage_updated_at: row.get(9)?,
+        download_path: row.get(10)?,
+        retry_count: row.get(11)?,
+        next_attempt_at: row.get(12)?,
+        error_transient: row.get(13)?,
+        error_code: row.get(14)?,
+        ..Default::default()
+    })
+}
+
+fn video_part_from_row(row: &Row) -> rusqlite::Result<VideoParts> {
+    Ok(VideoParts {
+        id: row.get(0)?,
+        video_id: row.get(1)?,
+        destination: row.get(2)?,
+        part: row.get(3)?,
+        remote_id: row.get(4)?,
+        url: row.get(5)?,
+        uploaded_at: row.get(6)?,
+        duration: row.get(7)?,
+        file_hash: row.get(8)?,
+        status: row.get(9)?,
+        ..Default::default()
+    })
+}
+
+fn streamer_destination_from_row(row: &Row) -> rusqlite::Result<StreamerDestinations> {
+    Ok(StreamerDestinations {
+        id: row.get(0)?,
+        streamer_login: row.get(1)?,
+        destination: row.get(2)?,
+        required: row.get(3)?,
+        ..Default::default()
+    })
+}
+
+fn video_upload_from_row(row: &Row) -> rusqlite::Result<VideoUploads> {
+    Ok(VideoUploads {
+        id: row.get(0)?,
+        video_id: row.get(1)?,
+        destination: row.get(2)?,
+        backed_up: row.get(3)?,
+        error: row.get(4)?,
+        collection_url: row.get(5)?,
+        updated_at: row.get(6)?,
+        ..Default::default()
+    })
+}
+//endregion
+
+#[async_trait(?Send)]
+impl Store for SqliteStore {
+    async fn get_watched_streamers(&self) -> Result<Vec<Streamers>> {
+        trace!("Getting watched streamers");
+        let connection = self.connection()?;
+        let mut statement = connection.prepare(&format!(
+            "SELECT {} FROM streamers WHERE watched = 1 LIMIT 1000",
+            STREAMER_COLUMNS
+        ))?;
+        let streamers = statement
+            .query_map([], streamer_from_row)?
+            .collect::<rusqlite::Result<Vec<_>>>()?;
+        Ok(streamers)
+    }
+
+    async fn get_streamer(&self, login: &str) -> Result<Option<Streamers>> {
+        let connection = self.connection()?;
+        let streamer = connection
+            .query_row(
+                &format!(
+                    "SELECT {} FROM streamers WHERE login = ?1",
+                    STREAMER_COLUMNS
+                ),
+                params![login],
+                streamer_from_row,
+            )
+            .optional()?;
+        Ok(streamer)
+    }
+
+    async fn upsert_streamer(&self, streamer: &Streamers) -> Result<()> {
+        let connection = self.connection()?;
+        connection
+            .execute(
+                &format!(
+                    "INSERT OR REPLACE INTO streamers ({}) VALUES (?1, ?2, ?3, ?4, ?5, ?6)",
+                    STREAMER_COLUMNS
+                ),
+                params![
+                    streamer.login,
+                    streamer.display_name,
+                    streamer.watched,
+                    streamer.youtube_user,
+                    streamer.public_videos_default,
+                    streamer.youtube_google_ident,
+                ],
+            )
+            .context("error saving streamer")?;
+        Ok(())
+    }
+
+    async fn delete_streamer(&self, login: &str) -> Result<bool> {
+        let connection = self.connection()?;
+        connection
+            .execute(
+                "DELETE FROM streamer_destinations WHERE streamer_login = ?1",
+                params![login],
+            )
+            .context("error deleting streamer destinations")?;
+        let deleted = connection
+            .execute("DELETE FROM streamers WHERE login = ?1", params![login])
+            .context("error deleting streamer")?;
+        Ok(deleted > 0)
+    }
+
+    async fn get_video(&self, video_id: i64) -> Result<Option<Videos>> {
+        let connection = self.connection()?;
+        let video = connection
+            .query_row(
+                &format!("SELECT {} FROM videos WHERE video_id = ?1", VIDEO_COLUMNS),
+                params![video_id],
+                video_from_row,
+            )
+            .optional()?;
+        Ok(video)
+    }
+
+    async fn get_video_metadata(&self, video_id: i64) -> Result<Option<VideoMetadata>> {
+        let connection = self.connection()?;
+        let metadata = connection
+            .query_row(
+                &format!(
+                    "SELECT {} FROM video_metadata WHERE video_id = ?1",
+                    VIDEO_METADATA_COLUMNS
+                ),
+                params![video_id],
+                video_metadata_from_row,
+            )
+            .optional()?;
+        Ok(metadata)
+    }
+
+    async fn get_not_downloaded_video_metadata(&self) -> Result<Vec<VideoMetadata>> {
+        let connection = self.connection()?;
+        let mut statement = connection.prepare(&format!(
+            "SELECT {} FROM video_metadata WHERE backed_up = 0 AND (error IS NULL \
+             OR (error_transient = 1 AND (next_attempt_at IS NULL OR next_attempt_at <= ?1))) \
+             ORDER BY video_id ASC LIMIT 1000",
+            VIDEO_METADATA_COLUMNS
+        ))?;
+        let metadata = statement
+            .query_map(params![chrono::Utc::now()], video_metadata_from_row)?
+            .collect::<rusqlite::Result<Vec<_>>>()?;
+        Ok(metadata)
+    }
+
+    async fn upsert_video(&self, video: &Videos) -> Result<()> {
+        let connection = self.connection()?;
+        connection
+            .execute(
+                &format!(
+                    "INSERT OR REPLACE INTO videos ({}) \
+                     VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8, ?9, ?10, ?11, ?12, ?13, ?14)",
+                    VIDEO_COLUMNS
+                ),
+                params![
+                    video.video_id,
+                    video.title,
+                    video.description,
+                    video.bool_test,
+                    video.user_login,
+                    video.created_at,
+                    video.url,
+                    video.viewable,
+                    video.language,
+                    video.view_count,
+                    video.video_type,
+                    video.duration,
+                    video.thumbnail_url,
+                    video.source,
+                ],
+            )
+            .context("error saving video data")?;
+        Ok(())
+    }
+
+    async fn upsert_video_metadata(&self, metadata: &VideoMetadata) -> Result<()> {
+        let connection = self.connection()?;
+        connection
+            .execute(
+                &format!(
+                    "INSERT OR REPLACE INTO video_metadata ({}) \
+                     VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8, ?9, ?10, ?11, ?12, ?13, ?14, ?15)",
+                    VIDEO_METADATA_COLUMNS
+                ),
+                params![
+                    metadata.video_id,
+                    metadata.backed_up,
+                    metadata.total_clips_amount,
+                    metadata.parts_backed_up_id,
+                    metadata.parts_size,
+                    metadata.error,
+                    metadata.download_playlist_url,
+                    metadata.youtube_playlist_url,
+                    metadata.stage,
+                    metadata.stage_updated_at,
+                    metadata.download_path,
+                    metadata.retry_count,
+                    metadata.next_attempt_at,
+                    metadata.error_transient,
+                    metadata.error_code,
+                ],
+            )
+            .context("error saving video metadata")?;
+        Ok(())
+    }
+
+    async fn save_video_metadata(&self, metadata: &VideoMetadata) -> Result<()> {
+        let connection = self.connection()?;
+        let changed = connection
+            .execute(
+                "UPDATE video_metadata SET backed_up = ?2, total_clips_amount = ?3, \
+                 parts_backed_up_id = ?4, parts_size = ?5, error = ?6, \
+                 download_playlist_url = ?7, youtube_playlist_url = ?8, stage = ?9, \
+                 stage_updated_at = ?10, download_path = ?11, retry_count = ?12, \
+                 next_attempt_at = ?13, error_transient = ?14, error_code = ?15 \
+                 WHERE video_id = ?1",
+                params![
+                    metadata.video_id,
+                    metadata.backed_up,
+                    metadata.total_clips_amount,
+                    metadata.parts_backed_up_id,
+                    metadata.parts_size,
+                    metadata.error,
+                    metadata.download_playlist_url,
+                    metadata.youtube_playlist_url,
+                    metadata.stage,
+                    metadata.stage_updated_at,
+                    metadata.download_path,
+                    metadata.retry_count,
+                    metadata.next_attempt_at,
+                    metadata.error_transient,
+                    metadata.error_code,
+                ],
+            )
+            .context("error saving video metadata")?;
+        if changed == 0 {
+            return Err(anyhow!(
+                "could not save metadata for video {}: it is not in the database",
+                metadata.video_id
+            ));
+        }
+        Ok(())
+    }
+
+    async fn get_video_parts(&self, video_id: i64) -> Result<Vec<VideoParts>> {
+        let connection = self.connection()?;
+        let mut statement = connection.prepare(&format!(
+            "SELECT {} FROM video_parts WHERE video_id = ?1 ORDER BY destination, part",
+            VIDEO_PART_COLUMNS
+        ))?;
+        let video_parts = statement
+            .query_map(params![video_id], video_part_from_row)?
+            .collect::<rusqlite::Result<Vec<_>>>()?;
+        Ok(video_parts)
+    }
+
+    async fn upsert_video_part(&self, video_part: &VideoParts) -> Result<()> {
+        let connection = self.connection()?;
+        connection
+            .execute(
+                &format!(
+                    "INSERT OR REPLACE INTO video_parts ({}) \
+                     VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8, ?9, ?10)",
+                    VIDEO_PART_COLUMNS
+                ),
+                params![
+                    video_part.id,
+                    video_part.video_id,
+                    video_part.destination,
+                    video_part.part,
+                    video_part.remote_id,
+                    video_part.url,
+                    video_part.uploaded_at,
+                    video_part.duration,
+                    video_part.file_hash,
+                    video_part.status,
+                ],
+            )
+            .context("error saving video part")?;
+        Ok(())
+    }
+
+    async fn get_streamer_destinations(
+        &self,
+        streamer_login: &str,
+    ) -> Result<Vec<StreamerDestinations>> {
+        let connection = self.connection()?;
+        let mut statement = connection.prepare(&format!(
+            "SELECT {} FROM streamer_destinations WHERE streamer_login = ?1 \
+             ORDER BY destination",
+            STREAMER_DESTINATION_COLUMNS
+        ))?;
+        let destinations = statement
+            .query_map(params![streamer_login], streamer_destination_from_row)?
+            .collect::<rusqlite::Result<Vec<_>>>()?;
+        Ok(destinations)
+    }
+
+    async fn upsert_streamer_destination(&self, destination: &StreamerDestinations) -> Result<()> {
+        let connection = self.connection()?;
+        connection
+            .execute(
+                &format!(
+                    "INSERT OR REPLACE INTO streamer_destinations ({}) VALUES (?1, ?2, ?3, ?4)",
+                    STREAMER_DESTINATION_COLUMNS
+                ),
+                params![
+                    destination.id,
+                    destination.streamer_login,
+                    destination.destination,
+                    destination.required,
+                ],
+            )
+            .context("error saving streamer destination")?;
+        Ok(())
+    }
+
+    async fn get_video_uploads(&self, video_id: i64) -> Result<Vec<VideoUploads>> {
+        let connection = self.connection()?;
+        let mut statement = connection.prepare(&format!(
+            "SELECT {} FROM video_uploads WHERE video_id = ?1 ORDER BY destination",
+            VIDEO_UPLOAD_COLUMNS
+        ))?;
+        let video_uploads = statement
+            .query_map(params![video_id], video_upload_from_row)?
+            .collect::<rusqlite::Result<Vec<_>>>()?;
+        Ok(video_uploads)
+    }
+
+    async fn upsert_video_upload(&self, video_upload: &VideoUploads) -> Result<()> {
+        let connection = self.connection()?;
+        connection
+            .execute(
+                &format!(
+                    "INSERT OR REPLACE INTO video_uploads ({}) VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7)",
+                    VIDEO_UPLOAD_COLUMNS
+                ),
+                params![
+                    video_upload.id,
+                    video_upload.video_id,
+                    video_upload.destination,
+                    video_upload.backed_up,
+                    video_upload.error,
+                    video_upload.collection_url,
+                    video_upload.updated_at,
+                ],
+            )
+            .context("error saving video upload")?;
+        Ok(())
+    }
+}
@@ -0,0 +1,125 @@
+use std::collections::{HashMap, VecDeque};
+use std::future::Future;
+use std::hash::Hash;
+
+use anyhow::Result;
+use futures_util::StreamExt;
+use tokio::sync::{Semaphore, SemaphorePermit};
+
+use crate::prelude::*;
+
+/// How many videos can be in each stage at the same time.
+///
+/// Multiple videos are backed up at the same time, but downloading,
+/// splitting and uploading each need different resources (bandwidth, cpu,
+/// api quota), so every stage has its own limit. Waiting videos get their
+/// turn in the order they asked for it.
+#[derive(Debug)]
+pub struct StageLimits {
+    downloads: Semaphore,
+    splits: Semaphore,
+    uploads: Semaphore,
+}
+
+impl Default for StageLimits {
+    fn default() -> Self {
+        Self::new(1, 1, 1)
+    }
+}
+
+impl StageLimits {
+    /// limits of zero are treated as one
+    pub fn new(downloads: usize, splits: usize, uploads: usize) -> Self {
+        Self {
+            downloads: Semaphore::new(downloads.max(1)),
+            splits: Semaphore::new(splits.max(1)),
+            uploads: Semaphore::new(uploads.max(1)),
+        }
+    }
+
+    /// wait until the video can be downloaded, the permit has to be kept
+    /// until the download is done
+    pub async fn download(&self) -> SemaphorePermit<'_> {
+        trace!("waiting for a download slot");
+        acquire(&self.downloads).await
+    }
+
+    pub async fn split(&self) -> SemaphorePermit<'_> {
+        trace!("waiting for a split slot");
+        acquire(&self.splits).await
+    }
+
+    pub async fn upload(&self) -> SemaphorePermit<'_> {
+        trace!("waiting for an upload slot");
+        acquire(&self.uploads).await
+    }
+}
+
+async fn acquire(semaphore: &Semaphore) -> SemaphorePermit<'_> {
+    semaphore
+        .acquire()
+        .await
+        .expect("the semaphores of the stage limits are never closed")
+}
+
+/// Order the items so the groups take turns: the first item of every group,
+/// then the second item of every group and so on.
+///
+/// Used to keep one streamer with a lot of videos from blocking all others.
+/// The order inside a group and the order of the groups stay the same.
+pub fn fair_order<T, K, F>(items: Vec<T>, group: F) -> Vec<T>
+where
+    K: Eq + Hash,
+    F: Fn(&T) -> K,
+{
+    let count = items.len();
+    let mut group_indices: HashMap<K, usize> = HashMap::new();
+    let mut groups: Vec<VecDeque<T>> = vec![];
+    for item in items {
+        let index = *group_indices.entry(group(&item)).or_insert_with(|| {
+            groups.push(VecDeque::new());
+            groups.len() - 1
+        });
+        groups[index].push_back(item);
+    }
+    let mut result = Vec::with_capacity(count);
+    while result.len() < count {
+        for group in groups.iter_mut() {
+            if let Some(item) = group.pop_front() {
+                result.push(item);
+            }
+        }
+    }
+    result
+}
+
+/// Run `f` for every item, at most `limit` of them at the same time.
+///
+/// A failure does not cancel the other runs, every one of them gets to finish
+/// (and save its state) first. The first error is returned, with the messages
+/// of all errors as its context.
+pub async fn run_to_completion<T, F, Fut>(items: Vec<T>, limit: usize, f: F) -> Result<()>
+where
+    F: FnMut(T) -> Fut,
+    Fut: Future<Output = Result<()>>,
+{
+    let results: Vec<Result<()>> = futures_util::stream::iter(items)
+        .map(f)
+        .buffer_unordered(limit.max(1))
+        .collect()
+        .await;
+    let mut errors: Vec<anyhow::Error> = results.into_iter().filter_map(|r| r.err()).collect();
+    if errors.is_empty() {
+        return Ok(());
+    }
+    for error in &errors {
+        warn!("{:?}", error);
+    }
+    let messages: Vec<String> = errors.iter().map(|e| format!("{:#}", e)).collect();
+    // the chain of the first error is kept, so its error code can still be found
+    Err(errors.remove(0).context(format!(
+        "{} of the runs failed: {}",
+        messages.len(),
+        messages.join("; ")
+    )))
+}
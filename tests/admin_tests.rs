@@ -0,0 +1,107 @@
+use downloader::admin;
+use downloader::data::{StreamerDestinations, Streamers, VideoMetadata, Videos};
+use downloader::store::{InMemoryStore, Store};
+
+async fn add_sample_video(store: &dyn Store, video_id: i64, error: Option<&str>) {
+    store
+        .upsert_video(&Videos {
+            video_id,
+            title: Some(format!("Test Video {}", video_id)),
+            user_login: Some("NopixelVods".to_string()),
+            ..Default::default()
+        })
+        .await
+        .unwrap();
+    store
+        .upsert_video_metadata(&VideoMetadata {
+            video_id,
+            backed_up: Some(false),
+            error: error.map(str::to_string),
+            error_code: error.map(|_| "download".to_string()),
+            error_transient: error.map(|_| false),
+            retry_count: error.map(|_| 5),
+            ..Default::default()
+        })
+        .await
+        .unwrap();
+}
+
+#[tokio::test]
+async fn reset_error_makes_the_video_pending_again() {
+    let store = InMemoryStore::new();
+    admin::add_streamer(&store, "NopixelVods", None, None)
+        .await
+        .unwrap();
+    add_sample_video(&store, 1, None).await;
+    add_sample_video(&store, 2, Some("Error while backing up video")).await;
+    let pending = admin::pending_videos(&store).await.unwrap();
+    assert_eq!(1, pending.len());
+    assert_eq!(1, pending[0].video.video_id);
+
+    let metadata = admin::reset_video_error(&store, 2).await.unwrap();
+    assert_eq!(None, metadata.error);
+    assert_eq!(None, metadata.retry_count);
+    let pending = admin::pending_videos(&store).await.unwrap();
+    assert_eq!(2, pending.len());
+
+    assert!(admin::reset_video_error(&store, 3).await.is_err());
+}
+
+#[tokio::test]
+async fn add_watch_and_remove_streamers() {
+    let store = InMemoryStore::new();
+    let streamer = admin::add_streamer(&store, "SomeOne", Some("SomeChannel".to_string()), None)
+        .await
+        .unwrap();
+    assert_eq!("someone", streamer.login);
+    assert_eq!(1, store.get_watched_streamers().await.unwrap().len());
+
+    admin::set_streamer_watched(&store, "someone", false)
+        .await
+        .unwrap();
+    assert!(store.get_watched_streamers().await.unwrap().is_empty());
+
+    // adding it again watches it and keeps the youtube user
+    let streamer = admin::add_streamer(&store, "someone", None, Some(true))
+        .await
+        .unwrap();
+    assert_eq!(Some(true), streamer.watched);
+    assert_eq!(Some("SomeChannel".to_string()), streamer.youtube_user);
+    assert_eq!(Some(true), streamer.public_videos_default);
+
+    store
+        .upsert_streamer_destination(&StreamerDestinations::new("someone", "s3", true))
+        .await
+        .unwrap();
+    admin::remove_streamer(&store, "someone").await.unwrap();
+    assert!(store.get_streamer("someone").await.unwrap().is_none());
+    assert!(store
+        .get_streamer_destinations("someone")
+        .await
+        .unwrap()
+        .is_empty());
+
+    assert!(admin::remove_streamer(&store, "someone").await.is_err());
+    assert!(admin::set_streamer_watched(&store, "nobody", true)
+        .await
+        .is_err());
+}
+
+#[tokio::test]
+async fn add_streamer_keeps_other_fields() {
+    let store = InMemoryStore::new();
+    store
+        .upsert_streamer(&Streamers {
+            login: "someone".to_string(),
+            display_name: Some("SomeOne".to_string()),
+            watched: Some(false),
+            ..Default::default()
+        })
+        .await
+        .unwrap();
+    let streamer = admin::add_streamer(&store, "someone", None, None)
+        .await
+        .unwrap();
+    assert_eq!(Some("SomeOne".to_string()), streamer.display_name);
+    assert_eq!(Some(true), streamer.watched);
+}
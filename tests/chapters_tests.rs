@@ -0,0 +1,90 @@
+use std::path::{Path, PathBuf};
+
+use downloader::chapters::{self, Chapter};
+use downloader::chapters_of_parts;
+
+fn sample_chapters() -> Vec<Chapter> {
+    vec![
+        Chapter {
+            start: 0.0,
+            title: "Just Chatting".to_string(),
+        },
+        Chapter {
+            start: 1800.0,
+            title: "Grand Theft Auto V".to_string(),
+        },
+        Chapter {
+            start: 5400.0,
+            title: "Minecraft".to_string(),
+        },
+    ]
+}
+
+#[test]
+fn sidecar_is_next_to_the_video() {
+    assert_eq!(
+        PathBuf::from("downloads/123/video.chapters.json"),
+        chapters::sidecar_path(Path::new("downloads/123/video.mp4"))
+    );
+}
+
+#[test]
+fn chapter_at_finds_the_running_chapter() {
+    let chapters = sample_chapters();
+    let title = |seconds| chapters::chapter_at(&chapters, seconds).map(|c| c.title.as_str());
+    assert_eq!(Some("Just Chatting"), title(0.0));
+    assert_eq!(Some("Just Chatting"), title(1799.0));
+    assert_eq!(Some("Grand Theft Auto V"), title(1800.0));
+    assert_eq!(Some("Minecraft"), title(9000.0));
+    assert_eq!(None, chapters::chapter_at(&[], 10.0));
+    assert_eq!(vec![1800.0, 5400.0], chapters::boundaries(&chapters));
+}
+
+#[tokio::test]
+async fn sidecar_round_trip() {
+    let folder = PathBuf::from("tests/test_data/tmp_chapters");
+    if folder.exists() {
+        std::fs::remove_dir_all(&folder).unwrap();
+    }
+    std::fs::create_dir_all(&folder).unwrap();
+    let video = folder.join("video.mp4");
+
+    let missing = chapters::read_sidecar(&video).await.unwrap();
+    let mut unsorted = sample_chapters();
+    unsorted.reverse();
+    chapters::write_sidecar(&video, &unsorted).await.unwrap();
+    let read = chapters::read_sidecar(&video).await.unwrap();
+    chapters::remove_sidecar(&video).await.unwrap();
+    let removed = !chapters::sidecar_path(&video).exists();
+    std::fs::remove_dir_all(&folder).unwrap();
+
+    assert_eq!(None, missing);
+    assert_eq!(Some(sample_chapters()), read);
+    assert!(removed);
+}
+
+#[test]
+fn chapters_of_parts_follow_the_part_lengths() {
+    let chapters = sample_chapters();
+    assert_eq!(
+        vec![
+            Some("Just Chatting".to_string()),
+            Some("Grand Theft Auto V".to_string()),
+            Some("Grand Theft Auto V".to_string()),
+            Some("Minecraft".to_string()),
+        ],
+        chapters_of_parts(
+            &chapters,
+            &[Some(1800.2), Some(1800.0), Some(1800.0), Some(600.0)]
+        )
+    );
+    // the start of the parts after one without a length is unknown
+    assert_eq!(
+        vec![Some("Just Chatting".to_string()), None, None],
+        chapters_of_parts(&chapters, &[None, Some(1800.0), Some(1800.0)])
+    );
+    assert_eq!(
+        vec![None, None],
+        chapters_of_parts(&[], &[Some(10.0), None])
+    );
+}
@@ -0,0 +1,95 @@
+use anyhow::anyhow;
+use chrono::{Duration, TimeZone, Utc};
+
+use downloader::data::VideoMetadata;
+use downloader::error::DownloaderError;
+use downloader::probe::{check_duration, MediaInfo};
+use downloader::retry::{classify_error, ErrorKind, RetryPolicy};
+
+#[test]
+fn message_contains_all_causes() {
+    let error = DownloaderError::Upload {
+        destination: "s3".to_string(),
+        source: anyhow!("connection reset").context("could not upload part 2"),
+    };
+    assert_eq!("upload", error.code());
+    assert_eq!(
+        "upload to s3 failed: could not upload part 2: connection reset",
+        error.message()
+    );
+}
+
+#[test]
+fn kind_depends_on_the_variant_and_the_source() {
+    let quota = DownloaderError::Quota {
+        destination: "youtube".to_string(),
+        source: anyhow!("403"),
+    };
+    assert_eq!(ErrorKind::Transient, quota.kind());
+    let not_found = DownloaderError::NotFound("video 1".to_string());
+    assert_eq!(ErrorKind::Permanent, not_found.kind());
+    let deleted = DownloaderError::Download {
+        video_id: 1,
+        source: anyhow!("the video was deleted"),
+    };
+    assert_eq!(ErrorKind::Permanent, deleted.kind());
+    let timeout = DownloaderError::Download {
+        video_id: 1,
+        source: anyhow!("request timed out"),
+    };
+    assert_eq!(ErrorKind::Transient, timeout.kind());
+}
+
+#[test]
+fn or_wrap_keeps_downloader_errors() {
+    let not_found: anyhow::Error = DownloaderError::NotFound("video 1".to_string()).into();
+    let error = DownloaderError::or_wrap(not_found, |source| DownloaderError::Download {
+        video_id: 1,
+        source,
+    });
+    assert_eq!("not_found", error.code());
+
+    let error = DownloaderError::or_wrap(anyhow!("boom"), |source| DownloaderError::Download {
+        video_id: 1,
+        source,
+    });
+    assert_eq!("download", error.code());
+}
+
+#[test]
+fn classify_error_finds_downloader_errors_in_the_chain() {
+    let error = anyhow::Error::from(DownloaderError::NotFound("video 1".to_string()))
+        .context("Failed to download video");
+    assert_eq!(ErrorKind::Permanent, classify_error(&error));
+}
+
+#[test]
+fn broken_downloads_are_retried() {
+    let too_short = MediaInfo {
+        duration: Some(600.0),
+        ..Default::default()
+    };
+    let source = check_duration(&too_short, Duration::hours(3), Duration::minutes(1)).unwrap_err();
+    let error = DownloaderError::BrokenDownload {
+        video_id: 1,
+        source,
+    };
+    assert_eq!("broken_download", error.code());
+    let kind = error.kind();
+    assert_eq!(ErrorKind::Transient, kind);
+    assert_eq!(
+        ErrorKind::Transient,
+        classify_error(&anyhow::Error::from(error))
+    );
+
+    let now = Utc.with_ymd_and_hms(2023, 1, 1, 0, 0, 0).unwrap();
+    let mut metadata = VideoMetadata {
+        video_id: 1,
+        backed_up: Some(false),
+        ..Default::default()
+    };
+    let policy = RetryPolicy::default();
+    policy.record_failure(&mut metadata, kind, now);
+    assert_eq!(Some(true), metadata.error_transient);
+    assert_eq!(Some(now + policy.base_delay), metadata.next_attempt_at);
+}
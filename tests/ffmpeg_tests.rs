@@ -0,0 +1,79 @@
+use downloader::error::DownloaderError;
+use downloader::ffmpeg::{parse_stderr, run_program, FfmpegError};
+use downloader::retry::{classify_error, ErrorKind};
+
+#[test]
+fn parse_stderr_finds_well_known_failures() {
+    let stderr = "Input #0, mov,mp4,m4a,3gp,3g2,mj2, from 'input.mp4':\n\
+                  [mov,mp4,m4a,3gp,3g2,mj2 @ 0x55] moov atom not found\n\
+                  input.mp4: Invalid data found when processing input\n";
+    assert!(matches!(
+        parse_stderr(stderr),
+        Some(FfmpegError::InvalidInput(_))
+    ));
+    assert!(matches!(
+        parse_stderr("missing.mp4: No such file or directory"),
+        Some(FfmpegError::FileNotFound(_))
+    ));
+    assert!(matches!(
+        parse_stderr("av_interleaved_write_frame(): No space left on device"),
+        Some(FfmpegError::NoSpace(_))
+    ));
+    assert!(matches!(
+        parse_stderr("Unrecognized option 'segment_tim'."),
+        Some(FfmpegError::Unsupported(_))
+    ));
+    assert!(parse_stderr("frame= 100 fps=0.0 q=-1.0 size=1024kB").is_none());
+}
+
+#[tokio::test]
+async fn run_program_fails_for_missing_programs() {
+    let error = run_program("this-program-does-not-exist", ["-version"])
+        .await
+        .unwrap_err();
+    assert!(matches!(error, FfmpegError::NotInstalled { .. }));
+}
+
+#[cfg(unix)]
+#[tokio::test]
+async fn run_program_checks_the_exit_status() {
+    let output = run_program("sh", ["-c", "echo out; echo err >&2"])
+        .await
+        .unwrap();
+    assert_eq!("out\n", output.stdout);
+    assert_eq!("err\n", output.stderr);
+
+    let error = run_program("sh", ["-c", "echo something broke >&2; exit 3"])
+        .await
+        .unwrap_err();
+    match error {
+        FfmpegError::Failed { status, stderr, .. } => {
+            assert!(status.contains('3'));
+            assert_eq!("something broke", stderr);
+        }
+        other => panic!("unexpected error: {:?}", other),
+    }
+
+    let error = run_program(
+        "sh",
+        [
+            "-c",
+            "echo 'in.mp4: Invalid data found when processing input' >&2; exit 1",
+        ],
+    )
+    .await
+    .unwrap_err();
+    assert!(matches!(error, FfmpegError::InvalidInput(_)));
+}
+
+#[test]
+fn ffmpeg_errors_are_classified_by_their_kind() {
+    let invalid = DownloaderError::Split {
+        video_id: 1,
+        source: anyhow::Error::new(FfmpegError::InvalidInput("moov atom not found".to_string()))
+            .context("ffmpeg could not split the video"),
+    };
+    assert_eq!(ErrorKind::Permanent, invalid.kind());
+    let no_space = anyhow::Error::new(FfmpegError::NoSpace("No space left on device".to_string()));
+    assert_eq!(ErrorKind::Transient, classify_error(&no_space));
+}
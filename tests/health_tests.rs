@@ -0,0 +1,65 @@
+use anyhow::anyhow;
+use chrono::{TimeZone, Utc};
+
+use downloader::error::DownloaderError;
+use downloader::health::LoopHealth;
+
+#[test]
+fn consecutive_failures_are_reset_by_a_success() {
+    let now = Utc.with_ymd_and_hms(2023, 1, 1, 0, 0, 0).unwrap();
+    let mut health = LoopHealth::default();
+    health.record_failure(&anyhow!("twitch is down"), now);
+    health.record_failure(
+        &DownloaderError::Storage(anyhow!("bigquery is down")).into(),
+        now,
+    );
+    assert_eq!(2, health.consecutive_failures);
+    assert_eq!(Some("storage".to_string()), health.last_error_code);
+    assert_eq!(
+        Some("could not access the storage: bigquery is down".to_string()),
+        health.last_error
+    );
+
+    health.record_success(now);
+    assert_eq!(0, health.consecutive_failures);
+    assert_eq!(2, health.total_failures);
+    assert_eq!(3, health.iterations);
+    assert_eq!(Some(now), health.last_success_at);
+}
+
+#[test]
+fn only_configured_conditions_are_fatal() {
+    let now = Utc.with_ymd_and_hms(2023, 1, 1, 0, 0, 0).unwrap();
+    let fatal_codes = vec!["config".to_string()];
+    let mut health = LoopHealth::default();
+
+    let error = anyhow!("twitch is down");
+    health.record_failure(&error, now);
+    assert!(!health.is_fatal(&error, &fatal_codes, None));
+    assert!(!health.is_fatal(&error, &fatal_codes, Some(2)));
+    health.record_failure(&error, now);
+    assert!(health.is_fatal(&error, &fatal_codes, Some(2)));
+
+    let config_error = anyhow::Error::from(DownloaderError::Config("no client secret".to_string()))
+        .context("could not create upload destinations");
+    assert!(health.is_fatal(&config_error, &fatal_codes, None));
+    assert!(!health.is_fatal(&config_error, &[], None));
+}
+
+#[test]
+fn health_is_written_as_json() {
+    let path = std::env::temp_dir()
+        .join("downloader_health_test")
+        .join("health.json");
+    let mut health = LoopHealth::default();
+    health.record_failure(
+        &anyhow!("twitch is down"),
+        Utc.with_ymd_and_hms(2023, 1, 1, 0, 0, 0).unwrap(),
+    );
+    health.write(&path).unwrap();
+
+    let written: LoopHealth =
+        serde_json::from_str(&std::fs::read_to_string(&path).unwrap()).unwrap();
+    assert_eq!(health, written);
+    assert!(!path.with_extension("partial").exists());
+}
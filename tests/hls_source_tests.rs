@@ -0,0 +1,340 @@
+use std::collections::{HashMap, HashSet};
+use std::path::PathBuf;
+use std::sync::{Arc, Mutex};
+
+use chrono::{TimeZone, Utc};
+use tokio::io::{AsyncReadExt, AsyncWriteExt};
+use tokio::net::TcpListener;
+
+use downloader::data::Streamers;
+use downloader::retry::{classify_error, ErrorKind};
+use downloader::source::hls::{parse_master_playlist, parse_media_playlist, HlsVariant};
+use downloader::source::{HlsSource, VideoSource};
+
+/// A very small http server that serves fixed responses.
+///
+/// Every path in `fail_once` answers with a 500 the first time it is requested.
+struct TestServer {
+    base_url: String,
+    /// the paths of all requests
+    requests: Arc<Mutex<Vec<String>>>,
+}
+
+impl TestServer {
+    async fn start(routes: HashMap<String, Vec<u8>>, fail_once: HashSet<String>) -> Self {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let address = listener.local_addr().unwrap();
+        let routes = Arc::new(routes);
+        let fail_once = Arc::new(Mutex::new(fail_once));
+        let requests = Arc::new(Mutex::new(vec![]));
+        let recorded = requests.clone();
+        tokio::spawn(async move {
+            loop {
+                let (mut socket, _) = listener.accept().await.unwrap();
+                let routes = routes.clone();
+                let fail_once = fail_once.clone();
+                let recorded = recorded.clone();
+                tokio::spawn(async move {
+                    let mut request = vec![];
+                    let mut buffer = [0u8; 1024];
+                    while !request.windows(4).any(|w| w == b"\r\n\r\n") {
+                        let read = socket.read(&mut buffer).await.unwrap();
+                        if read == 0 {
+                            return;
+                        }
+                        request.extend_from_slice(&buffer[..read]);
+                    }
+                    let request = String::from_utf8_lossy(&request);
+                    let path = request.split_whitespace().nth(1).unwrap_or("").to_string();
+                    recorded.lock().unwrap().push(path.clone());
+                    let fail = fail_once.lock().unwrap().remove(&path);
+                    let (status, body) = match routes.get(&path) {
+                        _ if fail => ("500 Internal Server Error", vec![]),
+                        Some(body) => ("200 OK", body.clone()),
+                        None => ("404 Not Found", vec![]),
+                    };
+                    let header = format!(
+                        "HTTP/1.1 {}\r\nContent-Length: {}\r\nConnection: close\r\n\r\n",
+                        status,
+                        body.len()
+                    );
+                    socket.write_all(header.as_bytes()).await.unwrap();
+                    socket.write_all(&body).await.unwrap();
+                    socket.shutdown().await.ok();
+                });
+            }
+        });
+        Self {
+            base_url: format!("http://{}", address),
+            requests,
+        }
+    }
+
+    fn requests_for(&self, path: &str) -> usize {
+        self.requests
+            .lock()
+            .unwrap()
+            .iter()
+            .filter(|p| *p == path)
+            .count()
+    }
+}
+
+const MASTER_PLAYLIST: &str = "#EXTM3U
+#EXT-X-STREAM-INF:BANDWIDTH=800000,RESOLUTION=640x360,CODECS=\"avc1.4d401e,mp4a.40.2\"
+low/index.m3u8
+#EXT-X-STREAM-INF:BANDWIDTH=5000000,RESOLUTION=1920x1080
+high/index.m3u8
+";
+
+fn media_playlist(segments: usize) -> String {
+    let mut playlist = "#EXTM3U\n#EXT-X-VERSION:3\n#EXT-X-TARGETDURATION:4\n".to_string();
+    for i in 0..segments {
+        playlist.push_str(&format!(
+            "#EXTINF:4.000,\n#EXT-X-PROGRAM-DATE-TIME:2021-01-01T00:00:0{}Z\nsegment_{}.ts\n",
+            i, i
+        ));
+    }
+    playlist.push_str("#EXT-X-ENDLIST\n");
+    playlist
+}
+
+fn routes() -> HashMap<String, Vec<u8>> {
+    let mut routes = HashMap::new();
+    routes.insert("/vod/master.m3u8".to_string(), MASTER_PLAYLIST.into());
+    for variant in ["low", "high"] {
+        routes.insert(
+            format!("/vod/{}/index.m3u8", variant),
+            media_playlist(3).into_bytes(),
+        );
+        for i in 0..3 {
+            routes.insert(
+                format!("/vod/{}/segment_{}.ts", variant, i),
+                format!("[{} segment {}]", variant, i).into_bytes(),
+            );
+        }
+    }
+    routes
+}
+
+fn prepare_folder(name: &str) -> PathBuf {
+    let folder = PathBuf::from(format!("tests/test_data/tmp_hls_{}", name));
+    if folder.exists() {
+        std::fs::remove_dir_all(&folder).unwrap();
+    }
+    std::fs::create_dir_all(&folder).unwrap();
+    folder
+}
+
+#[test]
+fn parse_master_playlist_variants() {
+    let variants = parse_master_playlist(MASTER_PLAYLIST);
+    assert_eq!(
+        vec![
+            HlsVariant {
+                bandwidth: 800000,
+                uri: "low/index.m3u8".to_string()
+            },
+            HlsVariant {
+                bandwidth: 5000000,
+                uri: "high/index.m3u8".to_string()
+            },
+        ],
+        variants
+    );
+    assert!(parse_master_playlist(&media_playlist(2)).is_empty());
+}
+
+#[test]
+fn parse_media_playlist_ignores_other_tags() {
+    let playlist = parse_media_playlist(&media_playlist(3)).unwrap();
+    assert_eq!(3, playlist.segments.len());
+    assert_eq!(("segment_2.ts".to_string(), 4.0), playlist.segments[2]);
+    assert_eq!(12.0, playlist.duration());
+    assert_eq!(None, playlist.init_segment);
+    assert_eq!(
+        Some(Utc.with_ymd_and_hms(2021, 1, 1, 0, 0, 0).unwrap()),
+        playlist.program_date_time
+    );
+
+    let encrypted = "#EXTM3U\n#EXT-X-KEY:METHOD=AES-128,URI=\"key\"\n#EXTINF:4,\na.ts\n";
+    assert!(parse_media_playlist(encrypted).is_err());
+}
+
+#[tokio::test]
+async fn download_best_variant_with_retries() {
+    let mut fail_once = HashSet::new();
+    fail_once.insert("/vod/high/segment_1.ts".to_string());
+    let server = TestServer::start(routes(), fail_once).await;
+    let folder = prepare_folder("download");
+    let source = HlsSource::new(folder.join("playlists.json"));
+
+    let path = source
+        .download_playlist(
+            &format!("{}/vod/master.m3u8", server.base_url),
+            &folder,
+            "video",
+        )
+        .await;
+    let content = path
+        .as_ref()
+        .ok()
+        .map(|p| std::fs::read_to_string(p).unwrap());
+    std::fs::remove_dir_all(&folder).unwrap();
+
+    let path = path.unwrap();
+    assert_eq!(Some("video.ts"), path.file_name().and_then(|n| n.to_str()));
+    assert_eq!(
+        "[high segment 0][high segment 1][high segment 2]",
+        content.unwrap()
+    );
+}
+
+#[tokio::test]
+async fn download_respects_max_bandwidth() {
+    let server = TestServer::start(routes(), HashSet::new()).await;
+    let folder = prepare_folder("bandwidth");
+    let source = HlsSource::new(folder.join("playlists.json")).with_max_bandwidth(Some(1_000_000));
+
+    let path = source
+        .download_playlist(
+            &format!("{}/vod/master.m3u8", server.base_url),
+            &folder,
+            "video",
+        )
+        .await;
+    let content = path.as_ref().map(|p| std::fs::read_to_string(p).unwrap());
+    std::fs::remove_dir_all(&folder).unwrap();
+
+    assert_eq!(
+        "[low segment 0][low segment 1][low segment 2]",
+        content.unwrap()
+    );
+}
+
+#[tokio::test]
+async fn download_fails_after_retries() {
+    let mut routes = routes();
+    routes.remove("/vod/high/segment_2.ts");
+    let server = TestServer::start(routes, HashSet::new()).await;
+    let folder = prepare_folder("missing");
+    let source = HlsSource::new(folder.join("playlists.json")).with_retries(1);
+
+    let res = source
+        .download_playlist(
+            &format!("{}/vod/master.m3u8", server.base_url),
+            &folder,
+            "video",
+        )
+        .await;
+    std::fs::remove_dir_all(&folder).unwrap();
+
+    assert!(res.is_err());
+}
+
+#[tokio::test]
+async fn videos_come_from_the_playlists_file() {
+    let server = TestServer::start(routes(), HashSet::new()).await;
+    let folder = prepare_folder("source");
+    let url = format!("{}/vod/master.m3u8", server.base_url);
+    std::fs::write(
+        folder.join("playlists.json"),
+        format!(
+            r#"[
+                {{"streamer_login": "NoPixelVODs", "url": "{}", "title": "Our own stream",
+                  "created_at": "2021-01-01T00:00:00Z"}},
+                {{"streamer_login": "someone_else", "url": "{}/other.m3u8"}}
+            ]"#,
+            url, server.base_url
+        ),
+    )
+    .unwrap();
+    let source = HlsSource::new(folder.join("playlists.json"));
+    let streamer = Streamers {
+        login: "nopixelvods".to_string(),
+        ..Default::default()
+    };
+
+    let videos = source.get_videos_for_streamer(&streamer).await.unwrap();
+    assert_eq!(1, videos.len());
+    let video = &videos[0];
+    assert_eq!(Some("Our own stream".to_string()), video.video.title);
+    assert_eq!("hls", video.video.source_name());
+    assert!(video.video.video_id < 0);
+
+    let path = source
+        .download_video(video, &folder.join("downloads"))
+        .await;
+    let content = path.as_ref().map(|p| std::fs::read_to_string(p).unwrap());
+    std::fs::remove_dir_all(&folder).unwrap();
+
+    assert_eq!(
+        "[high segment 0][high segment 1][high segment 2]",
+        content.unwrap()
+    );
+}
+
+#[tokio::test]
+async fn missing_segments_are_not_retried() {
+    let mut routes = routes();
+    routes.remove("/vod/high/segment_2.ts");
+    let server = TestServer::start(routes, HashSet::new()).await;
+    let folder = prepare_folder("not_retried");
+    let source = HlsSource::new(folder.join("playlists.json")).with_retries(3);
+
+    let res = source
+        .download_playlist(
+            &format!("{}/vod/master.m3u8", server.base_url),
+            &folder,
+            "video",
+        )
+        .await;
+    std::fs::remove_dir_all(&folder).unwrap();
+
+    let error = res.unwrap_err();
+    assert_eq!(1, server.requests_for("/vod/high/segment_2.ts"));
+    assert_eq!(ErrorKind::Permanent, classify_error(&error));
+}
+
+#[tokio::test]
+async fn created_at_comes_from_the_playlist_and_stays_the_same() {
+    let mut routes = routes();
+    routes.insert(
+        "/vod/no_time.m3u8".to_string(),
+        b"#EXTM3U\n#EXTINF:4.000,\nsegment.ts\n#EXT-X-ENDLIST\n".to_vec(),
+    );
+    let server = TestServer::start(routes, HashSet::new()).await;
+    let folder = prepare_folder("created_at");
+    std::fs::write(
+        folder.join("playlists.json"),
+        format!(
+            r#"[
+                {{"streamer_login": "nopixelvods", "url": "{0}/vod/master.m3u8"}},
+                {{"streamer_login": "nopixelvods", "url": "{0}/vod/no_time.m3u8"}}
+            ]"#,
+            server.base_url
+        ),
+    )
+    .unwrap();
+    let source = HlsSource::new(folder.join("playlists.json"));
+    let streamer = Streamers {
+        login: "nopixelvods".to_string(),
+        ..Default::default()
+    };
+
+    let first = source.get_videos_for_streamer(&streamer).await;
+    tokio::time::sleep(std::time::Duration::from_millis(20)).await;
+    let second = source.get_videos_for_streamer(&streamer).await;
+    std::fs::remove_dir_all(&folder).unwrap();
+
+    let first = first.unwrap();
+    let second = second.unwrap();
+    assert_eq!(
+        Some(Utc.with_ymd_and_hms(2021, 1, 1, 0, 0, 0).unwrap()),
+        first[0].video.created_at
+    );
+    assert!(first[1].video.created_at.is_some());
+    assert_eq!(first[1].video.created_at, second[1].video.created_at);
+    // the playlists are only read the first time
+    assert_eq!(1, server.requests_for("/vod/no_time.m3u8"));
+}
@@ -0,0 +1,170 @@
+use std::path::PathBuf;
+
+use chrono::{TimeZone, Utc};
+
+use downloader::destination::local::ArchivedPartMetadata;
+use downloader::destination::{LocalArchiveDestination, PartInfo};
+use downloader::shutdown::Shutdown;
+use downloader::store::{InMemoryStore, Store};
+use downloader::upload_parts;
+
+fn prepare_folder(name: &str) -> PathBuf {
+    let folder = PathBuf::from(format!("tests/test_data/tmp_archive_{}", name));
+    if folder.exists() {
+        std::fs::remove_dir_all(&folder).unwrap();
+    }
+    std::fs::create_dir_all(folder.join("parts")).unwrap();
+    std::fs::create_dir_all(folder.join("archive")).unwrap();
+    folder
+}
+
+fn get_sample_part(part: usize, total_parts: usize) -> PartInfo {
+    PartInfo {
+        title: format!(
+            "[2021-03-04][Part {:0>2}/{:0>2}] Test Video",
+            part, total_parts
+        ),
+        description: "Test Description".to_string(),
+        tags: vec!["gta".to_string()],
+        public: false,
+        video_id: 123,
+        streamer_login: "NoPixelVODs".to_string(),
+        created_at: Some(Utc.with_ymd_and_hms(2021, 3, 4, 5, 6, 7).unwrap()),
+        part,
+        total_parts,
+        duration: Some(1200),
+        chapter: None,
+    }
+}
+
+#[test]
+fn part_path_uses_the_layout() {
+    let destination = LocalArchiveDestination::new("/archive");
+    assert_eq!(
+        PathBuf::from("/archive/nopixelvods/2021/03/123/02.mp4"),
+        destination.part_path(&get_sample_part(2, 3)).unwrap()
+    );
+
+    let destination = LocalArchiveDestination::new("/archive")
+        .with_layout("{yyyy}-{mm}-{dd}/{streamer_login}_{video_id}_{part}.mp4");
+    assert_eq!(
+        PathBuf::from("/archive/2021-03-04/nopixelvods_123_01.mp4"),
+        destination.part_path(&get_sample_part(1, 1)).unwrap()
+    );
+}
+
+#[test]
+fn part_path_adds_the_part_if_the_layout_has_none() {
+    let destination =
+        LocalArchiveDestination::new("/archive").with_layout("{streamer_login}/{video_id}.mp4");
+    assert_eq!(
+        PathBuf::from("/archive/nopixelvods/123_01.mp4"),
+        destination.part_path(&get_sample_part(1, 2)).unwrap()
+    );
+    assert_eq!(
+        PathBuf::from("/archive/nopixelvods/123_02.mp4"),
+        destination.part_path(&get_sample_part(2, 2)).unwrap()
+    );
+
+    let destination =
+        LocalArchiveDestination::new("/archive").with_layout("{streamer_login}.v2/{video_id}");
+    assert_eq!(
+        PathBuf::from("/archive/nopixelvods.v2/123_02"),
+        destination.part_path(&get_sample_part(2, 2)).unwrap()
+    );
+}
+
+#[test]
+fn part_path_stays_inside_the_archive() {
+    let destination = LocalArchiveDestination::new("/archive").with_layout("../{part}.mp4");
+    assert!(destination.part_path(&get_sample_part(1, 1)).is_err());
+
+    let mut part = get_sample_part(1, 1);
+    part.created_at = None;
+    assert!(LocalArchiveDestination::new("/archive")
+        .part_path(&part)
+        .is_err());
+}
+
+#[tokio::test]
+async fn upload_copies_parts_and_writes_metadata() {
+    let folder = prepare_folder("upload");
+    let archive = folder.join("archive");
+    let destination = LocalArchiveDestination::new(&archive);
+    let mut parts = vec![];
+    for i in 1..=2 {
+        let path = folder.join("parts").join(format!("part_{}.mp4", i));
+        std::fs::write(&path, format!("part {}", i)).unwrap();
+        parts.push((path, get_sample_part(i, 2)));
+    }
+
+    let store = InMemoryStore::new();
+    let res = upload_parts(
+        &store,
+        &destination,
+        &parts,
+        "[2021-03-04] Test Video",
+        false,
+        &Shutdown::new(),
+    )
+    .await;
+    let video_folder = archive.join("nopixelvods/2021/03/123");
+    let second = std::fs::read_to_string(video_folder.join("02.mp4"));
+    let metadata = std::fs::read_to_string(video_folder.join("02.json"));
+    let originals_still_there = parts.iter().all(|(path, _)| path.exists());
+    let leftovers = std::fs::read_dir(&video_folder)
+        .map(|d| d.count())
+        .unwrap_or_default();
+    std::fs::remove_dir_all(&folder).unwrap();
+
+    let collection = res.unwrap().unwrap();
+    assert!(collection.url.starts_with("file://"));
+    assert_eq!("part 2", second.unwrap());
+    let metadata: ArchivedPartMetadata = serde_json::from_str(&metadata.unwrap()).unwrap();
+    assert_eq!("[2021-03-04][Part 02/02] Test Video", metadata.title);
+    assert_eq!("Test Description", metadata.description);
+    assert_eq!(2, metadata.part);
+    assert_eq!(
+        Some("[2021-03-04] Test Video".to_string()),
+        metadata.collection
+    );
+    assert!(originals_still_there);
+    // two parts and their metadata, no partial files
+    assert_eq!(4, leftovers);
+    let video_parts = store.get_video_parts(123).await.unwrap();
+    assert_eq!(2, video_parts.len());
+    assert!(video_parts[0].url.as_ref().unwrap().ends_with("01.mp4"));
+}
+
+#[tokio::test]
+async fn parts_do_not_overwrite_each_other_without_part_in_the_layout() {
+    let folder = prepare_folder("layout_without_part");
+    let archive = folder.join("archive");
+    let destination =
+        LocalArchiveDestination::new(&archive).with_layout("{streamer_login}/{video_id}.mp4");
+    let mut parts = vec![];
+    for i in 1..=2 {
+        let path = folder.join("parts").join(format!("part_{}.mp4", i));
+        std::fs::write(&path, format!("part {}", i)).unwrap();
+        parts.push((path, get_sample_part(i, 2)));
+    }
+
+    let store = InMemoryStore::new();
+    let res = upload_parts(
+        &store,
+        &destination,
+        &parts,
+        "[2021-03-04] Test Video",
+        false,
+        &Shutdown::new(),
+    )
+    .await;
+    let streamer_folder = archive.join("nopixelvods");
+    let first = std::fs::read_to_string(streamer_folder.join("123_01.mp4"));
+    let second = std::fs::read_to_string(streamer_folder.join("123_02.mp4"));
+    std::fs::remove_dir_all(&folder).unwrap();
+
+    res.unwrap().unwrap();
+    assert_eq!("part 1", first.unwrap());
+    assert_eq!("part 2", second.unwrap());
+}
@@ -0,0 +1,210 @@
+use std::path::PathBuf;
+
+use chrono::{TimeZone, Utc};
+
+use downloader::chapters::{self, Chapter};
+use downloader::check_for_new_videos;
+use downloader::data::Streamers;
+use downloader::source::local::{Sidecar, LOCAL_SOURCE_NAME};
+use downloader::source::{LocalFolderSource, VideoSource, VideoSources};
+use downloader::store::{InMemoryStore, Store};
+
+fn prepare_inbox(name: &str) -> PathBuf {
+    let inbox = PathBuf::from(format!("tests/test_data/tmp_inbox_{}", name));
+    if inbox.exists() {
+        std::fs::remove_dir_all(&inbox).unwrap();
+    }
+    std::fs::create_dir_all(&inbox).unwrap();
+
+    std::fs::write(inbox.join("first.mp4"), b"not really a video").unwrap();
+    std::fs::write(
+        inbox.join("first.json"),
+        r#"{
+            "title": "First recording",
+            "streamer_login": "NoPixelVODs",
+            "created_at": "2021-01-01T00:00:00Z",
+            "description": "recorded with OBS",
+            "chapters": [
+                { "start": 0, "title": "Just Chatting" },
+                { "start": 1800.5, "title": "Grand Theft Auto V" }
+            ]
+        }"#,
+    )
+    .unwrap();
+
+    std::fs::write(inbox.join("second.mkv"), b"also not a video").unwrap();
+    std::fs::write(
+        inbox.join("second.toml"),
+        "title = \"Second recording\"\n\
+         streamer_login = \"someone_else\"\n\
+         created_at = \"2021-01-02T00:00:00Z\"\n",
+    )
+    .unwrap();
+
+    // no sidecar yet, so this one is ignored
+    std::fs::write(inbox.join("third.mp4"), b"still copying").unwrap();
+    inbox
+}
+
+fn streamer(login: &str) -> Streamers {
+    Streamers {
+        login: login.to_string(),
+        watched: Some(true),
+        ..Default::default()
+    }
+}
+
+#[tokio::test]
+async fn scan_reads_json_and_toml_sidecars() {
+    let inbox = prepare_inbox("scan");
+    let source = LocalFolderSource::new(&inbox);
+
+    let entries = source.scan().await;
+    std::fs::remove_dir_all(&inbox).unwrap();
+
+    let entries = entries.unwrap();
+    assert_eq!(2, entries.len());
+    assert_eq!(
+        Sidecar {
+            title: "First recording".to_string(),
+            streamer_login: "NoPixelVODs".to_string(),
+            created_at: Utc.with_ymd_and_hms(2021, 1, 1, 0, 0, 0).unwrap(),
+            description: Some("recorded with OBS".to_string()),
+            chapters: vec![
+                Chapter {
+                    start: 0.0,
+                    title: "Just Chatting".to_string(),
+                },
+                Chapter {
+                    start: 1800.5,
+                    title: "Grand Theft Auto V".to_string(),
+                },
+            ],
+        },
+        entries[0].sidecar
+    );
+    assert_eq!("Second recording", entries[1].sidecar.title);
+    assert_eq!(None, entries[1].sidecar.description);
+    assert!(entries[1].sidecar.chapters.is_empty());
+    for entry in entries {
+        assert!(entry.video_id < 0, "local ids must not collide with twitch");
+    }
+}
+
+#[tokio::test]
+async fn videos_are_filtered_by_streamer() {
+    let inbox = prepare_inbox("streamer");
+    let source = LocalFolderSource::new(&inbox);
+
+    let videos = source
+        .get_videos_for_streamer(&streamer("nopixelvods"))
+        .await;
+    let again = source
+        .get_videos_for_streamer(&streamer("nopixelvods"))
+        .await;
+    std::fs::remove_dir_all(&inbox).unwrap();
+
+    let videos = videos.unwrap();
+    assert_eq!(1, videos.len());
+    let video = &videos[0];
+    assert_eq!(Some("First recording".to_string()), video.video.title);
+    assert_eq!(Some("nopixelvods".to_string()), video.video.user_login);
+    assert_eq!(LOCAL_SOURCE_NAME, video.video.source_name());
+    assert_eq!(Some(false), video.metadata.backed_up);
+    assert_eq!(video.video.video_id, video.metadata.video_id);
+    assert_eq!(video.video.video_id, again.unwrap()[0].video.video_id);
+}
+
+#[tokio::test]
+async fn download_copies_the_file_and_keeps_the_inbox() {
+    let inbox = prepare_inbox("download");
+    let download_folder = inbox.join("downloads");
+    let source = LocalFolderSource::new(&inbox);
+
+    let video = source
+        .get_videos_for_streamer(&streamer("nopixelvods"))
+        .await
+        .unwrap()
+        .remove(0);
+    let path = source.download_video(&video, &download_folder).await;
+    let copied = path
+        .as_ref()
+        .map(|p| std::fs::read(p).unwrap())
+        .unwrap_or_default();
+    let original_still_there = inbox.join("first.mp4").exists();
+    let video_chapters = match &path {
+        Ok(path) => chapters::read_sidecar(path).await.unwrap(),
+        Err(_) => None,
+    };
+    std::fs::remove_dir_all(&inbox).unwrap();
+
+    let path = path.unwrap();
+    assert!(path.starts_with(&download_folder));
+    assert_eq!(Some("first.mp4"), path.file_name().and_then(|n| n.to_str()));
+    assert_eq!(b"not really a video".to_vec(), copied);
+    assert!(original_still_there);
+    let video_chapters = video_chapters.unwrap();
+    assert_eq!(2, video_chapters.len());
+    assert_eq!("Grand Theft Auto V", video_chapters[1].title);
+}
+
+#[tokio::test]
+async fn check_for_new_videos_adds_inbox_videos() {
+    let inbox = prepare_inbox("check");
+    let store = InMemoryStore::new();
+    store
+        .upsert_streamer(&streamer("nopixelvods"))
+        .await
+        .unwrap();
+    let mut sources = VideoSources::new();
+    sources.add(LocalFolderSource::new(&inbox));
+
+    let res = check_for_new_videos(&store, &sources).await;
+    std::fs::remove_dir_all(&inbox).unwrap();
+    res.unwrap();
+
+    let pending = store.get_not_downloaded_video_metadata().await.unwrap();
+    assert_eq!(1, pending.len());
+    let video = store.get_video(pending[0].video_id).await.unwrap().unwrap();
+    assert_eq!(Some("First recording".to_string()), video.title);
+    assert_eq!(Some(LOCAL_SOURCE_NAME.to_string()), video.source);
+}
+
+#[tokio::test]
+async fn the_same_file_name_of_another_streamer_gets_another_id() {
+    let inbox = prepare_inbox("same_name");
+    let source = LocalFolderSource::new(&inbox);
+    let first = source.scan().await;
+    std::fs::write(
+        inbox.join("first.json"),
+        r#"{
+            "title": "First recording",
+            "streamer_login": "someone_else",
+            "created_at": "2021-01-01T00:00:00Z"
+        }"#,
+    )
+    .unwrap();
+    let second = source.scan().await;
+    std::fs::remove_dir_all(&inbox).unwrap();
+
+    let first = first.unwrap();
+    let second = second.unwrap();
+    let first_id = |entries: &[downloader::source::local::InboxEntry]| {
+        entries
+            .iter()
+            .find(|e| e.path.ends_with("first.mp4"))
+            .unwrap()
+            .video_id
+    };
+    assert_ne!(first_id(&first), first_id(&second));
+}
+
+#[test]
+fn synthetic_ids_are_stable() {
+    let a = downloader::source::synthetic_video_id("local", "first.mp4");
+    let b = downloader::source::synthetic_video_id("local", "first.mp4");
+    let c = downloader::source::synthetic_video_id("hls", "first.mp4");
+    assert_eq!(a, b);
+    assert_ne!(a, c);
+    assert!(a < 0 && c < 0);
+}
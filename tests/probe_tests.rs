@@ -0,0 +1,88 @@
+use chrono::Duration;
+use downloader::chapters::Chapter;
+use downloader::probe::{check_duration, MediaInfo};
+
+const FFPROBE_OUTPUT: &str = r#"{
+    "streams": [
+        {
+            "index": 0,
+            "codec_name": "h264",
+            "codec_type": "video",
+            "width": 1920,
+            "height": 1080,
+            "bit_rate": "6000000"
+        },
+        {
+            "index": 1,
+            "codec_name": "aac",
+            "codec_type": "audio",
+            "bit_rate": "N/A"
+        }
+    ],
+    "format": {
+        "filename": "video.mp4",
+        "format_name": "mov,mp4,m4a,3gp,3g2,mj2",
+        "duration": "3600.250000",
+        "size": "2700000000",
+        "bit_rate": "6160000"
+    },
+    "chapters": [
+        {
+            "id": 0,
+            "start_time": "0.000000",
+            "end_time": "1800.000000",
+            "tags": { "title": "Just Chatting" }
+        },
+        {
+            "id": 1,
+            "start_time": "1800.000000",
+            "end_time": "3600.250000"
+        }
+    ]
+}"#;
+
+#[test]
+fn media_info_is_read_from_the_ffprobe_json() {
+    let info = MediaInfo::from_ffprobe_json(FFPROBE_OUTPUT).unwrap();
+    assert_eq!(Some(3600.25), info.duration);
+    assert_eq!(Some(Duration::seconds(3600)), info.duration());
+    assert_eq!(Some(2_700_000_000), info.size);
+    assert_eq!(Some(6_160_000), info.bit_rate);
+    assert_eq!(Some("h264"), info.video_codec());
+    assert_eq!(Some("aac"), info.audio_codec());
+    assert_eq!(Some((1920, 1080)), info.resolution());
+    assert_eq!(Some(6_000_000), info.streams[0].bit_rate);
+    assert_eq!(None, info.streams[1].bit_rate);
+    assert_eq!(
+        vec![
+            Chapter {
+                start: 0.0,
+                title: "Just Chatting".to_string()
+            },
+            Chapter {
+                start: 1800.0,
+                title: "Chapter 2".to_string()
+            },
+        ],
+        info.chapters
+    );
+}
+
+#[test]
+fn media_info_without_format_or_streams() {
+    let info = MediaInfo::from_ffprobe_json("{}").unwrap();
+    assert_eq!(MediaInfo::default(), info);
+    assert_eq!(None, info.resolution());
+    assert!(MediaInfo::from_ffprobe_json("not json").is_err());
+}
+
+#[test]
+fn check_duration_allows_the_tolerance() {
+    let info = MediaInfo::from_ffprobe_json(FFPROBE_OUTPUT).unwrap();
+    let tolerance = Duration::seconds(60);
+    assert!(check_duration(&info, Duration::seconds(3600), tolerance).is_ok());
+    assert!(check_duration(&info, Duration::seconds(3650), tolerance).is_ok());
+    // a download that was cut short
+    assert!(check_duration(&info, Duration::seconds(7200), tolerance).is_err());
+    assert!(check_duration(&MediaInfo::default(), Duration::seconds(3600), tolerance).is_err());
+}
@@ -0,0 +1,139 @@
+use anyhow::anyhow;
+use chrono::{Duration, TimeZone, Utc};
+
+use downloader::data::VideoMetadata;
+use downloader::retry::{classify_error, ErrorKind, RetryPolicy};
+
+fn get_failed_metadata() -> VideoMetadata {
+    VideoMetadata {
+        video_id: 1,
+        backed_up: Some(false),
+        error: Some("Error while backing up video".to_string()),
+        ..Default::default()
+    }
+}
+
+#[test]
+fn classify_error_by_message() {
+    let quota = anyhow!("403 Forbidden: quotaExceeded").context("error uploading part");
+    assert_eq!(ErrorKind::Transient, classify_error(&quota));
+    let deleted = anyhow!("video 123 was deleted").context("Failed to download video");
+    assert_eq!(ErrorKind::Permanent, classify_error(&deleted));
+    let sub_only = anyhow!("this video is only available to subscribers");
+    assert_eq!(ErrorKind::Permanent, classify_error(&sub_only));
+    let server = anyhow!("got status 503 from the server");
+    assert_eq!(ErrorKind::Transient, classify_error(&server));
+    let unknown = anyhow!("something unexpected happened");
+    assert_eq!(ErrorKind::Permanent, classify_error(&unknown));
+    let reset = anyhow!("connection reset by peer").context("error uploading part");
+    assert_eq!(ErrorKind::Transient, classify_error(&reset));
+}
+
+#[test]
+fn classify_error_only_matches_whole_words() {
+    // not a server error, the numbers are only part of the id
+    let id = anyhow!("video 1500 was deleted");
+    assert_eq!(ErrorKind::Permanent, classify_error(&id));
+    let id = anyhow!("video 502 was deleted");
+    assert_eq!(ErrorKind::Permanent, classify_error(&id));
+    let longer_word = anyhow!("the reconnection of the stream timed outside the window");
+    assert_eq!(ErrorKind::Permanent, classify_error(&longer_word));
+}
+
+#[test]
+fn classify_error_checks_permanent_markers_first() {
+    let both = anyhow!("video 123 not found").context("connection closed after the request");
+    assert_eq!(ErrorKind::Permanent, classify_error(&both));
+    let sub_only = anyhow!("subscription required").context("request timed out while retrying");
+    assert_eq!(ErrorKind::Permanent, classify_error(&sub_only));
+}
+
+#[test]
+fn classify_error_by_status_in_the_message() {
+    let server = anyhow!("status code: 500").context("could not download video 404");
+    assert_eq!(ErrorKind::Transient, classify_error(&server));
+    let missing = anyhow!("got status 404 for video 503");
+    assert_eq!(ErrorKind::Permanent, classify_error(&missing));
+}
+
+#[test]
+fn classify_error_io_errors_are_transient() {
+    let error = anyhow::Error::new(std::io::Error::new(
+        std::io::ErrorKind::NotFound,
+        "ffmpeg not found",
+    ))
+    .context("error while splitting video into parts");
+    assert_eq!(ErrorKind::Transient, classify_error(&error));
+}
+
+#[test]
+fn delay_doubles_up_to_the_max() {
+    let policy = RetryPolicy {
+        max_attempts: 10,
+        base_delay: Duration::minutes(10),
+        max_delay: Duration::hours(1),
+    };
+    assert_eq!(Duration::minutes(10), policy.delay(1));
+    assert_eq!(Duration::minutes(20), policy.delay(2));
+    assert_eq!(Duration::minutes(40), policy.delay(3));
+    assert_eq!(Duration::hours(1), policy.delay(4));
+    assert_eq!(Duration::hours(1), policy.delay(1000));
+}
+
+#[test]
+fn delay_does_not_overflow_with_a_large_base_delay() {
+    let policy = RetryPolicy {
+        max_attempts: 10,
+        base_delay: Duration::minutes(1440),
+        max_delay: Duration::hours(24),
+    };
+    assert_eq!(Duration::hours(24), policy.delay(1));
+    assert_eq!(Duration::hours(24), policy.delay(31));
+    assert_eq!(Duration::hours(24), policy.delay(u32::MAX));
+
+    let policy = RetryPolicy {
+        max_attempts: 10,
+        base_delay: Duration::weeks(1_000_000_000),
+        max_delay: Duration::weeks(10_000_000_000),
+    };
+    assert_eq!(Duration::weeks(8_000_000_000), policy.delay(4));
+    // doubling that again is more than chrono can hold
+    assert_eq!(Duration::weeks(10_000_000_000), policy.delay(5));
+    assert_eq!(Duration::weeks(10_000_000_000), policy.delay(1000));
+}
+
+#[test]
+fn transient_failures_are_retried_until_max_attempts() {
+    let policy = RetryPolicy {
+        max_attempts: 3,
+        ..Default::default()
+    };
+    let now = Utc.with_ymd_and_hms(2023, 1, 1, 0, 0, 0).unwrap();
+    let mut metadata = get_failed_metadata();
+
+    policy.record_failure(&mut metadata, ErrorKind::Transient, now);
+    assert_eq!(Some(1), metadata.retry_count);
+    assert_eq!(Some(true), metadata.error_transient);
+    assert_eq!(Some(now + policy.base_delay), metadata.next_attempt_at);
+    assert!(!metadata.is_pending(now));
+    assert!(metadata.is_pending(now + policy.base_delay));
+
+    policy.record_failure(&mut metadata, ErrorKind::Transient, now);
+    assert_eq!(Some(now + policy.base_delay * 2), metadata.next_attempt_at);
+
+    policy.record_failure(&mut metadata, ErrorKind::Transient, now);
+    assert_eq!(Some(3), metadata.retry_count);
+    assert_eq!(Some(false), metadata.error_transient);
+    assert_eq!(None, metadata.next_attempt_at);
+    assert!(!metadata.is_pending(now + Duration::days(365)));
+}
+
+#[test]
+fn permanent_failures_are_not_retried() {
+    let now = Utc.with_ymd_and_hms(2023, 1, 1, 0, 0, 0).unwrap();
+    let mut metadata = get_failed_metadata();
+    RetryPolicy::default().record_failure(&mut metadata, ErrorKind::Permanent, now);
+    assert_eq!(Some(1), metadata.retry_count);
+    assert_eq!(Some(false), metadata.error_transient);
+    assert!(!metadata.is_pending(now + Duration::days(365)));
+}
@@ -0,0 +1,90 @@
+use chrono::{Duration, NaiveTime, TimeZone, Utc};
+
+use downloader::schedule::{QuietHours, Schedule, Trigger};
+
+#[test]
+fn next_run_uses_the_interval_without_cron() {
+    let now = Utc.with_ymd_and_hms(2023, 1, 1, 12, 30, 0).unwrap();
+    let schedule = Schedule {
+        interval: Duration::minutes(45),
+        ..Default::default()
+    };
+    assert_eq!(now + Duration::minutes(45), schedule.next_run(now));
+}
+
+#[test]
+fn next_run_follows_the_cron_expression() {
+    let now = Utc.with_ymd_and_hms(2023, 1, 1, 12, 30, 0).unwrap();
+    let schedule = Schedule {
+        cron: Some(Schedule::parse_cron("0 */6 * * *").unwrap()),
+        ..Default::default()
+    };
+    assert_eq!(
+        Utc.with_ymd_and_hms(2023, 1, 1, 18, 0, 0).unwrap(),
+        schedule.next_run(now)
+    );
+
+    // the full format with seconds works as well
+    let schedule = Schedule {
+        cron: Some(Schedule::parse_cron("30 15 4 * * *").unwrap()),
+        ..Default::default()
+    };
+    assert_eq!(
+        Utc.with_ymd_and_hms(2023, 1, 2, 4, 15, 30).unwrap(),
+        schedule.next_run(now)
+    );
+
+    assert!(Schedule::parse_cron("every day").is_err());
+}
+
+#[test]
+fn jitter_stays_below_the_max() {
+    let schedule = Schedule::default();
+    assert_eq!(Duration::zero(), schedule.random_jitter());
+
+    let schedule = Schedule {
+        jitter: Duration::seconds(30),
+        ..Default::default()
+    };
+    for _ in 0..100 {
+        let jitter = schedule.random_jitter();
+        assert!(jitter >= Duration::zero());
+        assert!(jitter <= Duration::seconds(30));
+    }
+}
+
+#[test]
+fn quiet_hours_wrap_around_midnight() {
+    let quiet_hours: QuietHours = "22:00-06:30".parse().unwrap();
+    assert_eq!(
+        NaiveTime::from_hms_opt(22, 0, 0).unwrap(),
+        quiet_hours.start
+    );
+    let at = |hour, minute| Utc.with_ymd_and_hms(2023, 1, 1, hour, minute, 0).unwrap();
+    assert!(quiet_hours.contains(at(23, 0)));
+    assert!(quiet_hours.contains(at(3, 0)));
+    assert!(quiet_hours.contains(at(6, 29)));
+    assert!(!quiet_hours.contains(at(6, 30)));
+    assert!(!quiet_hours.contains(at(12, 0)));
+
+    let quiet_hours: QuietHours = "09:00 - 17:00".parse().unwrap();
+    assert!(quiet_hours.contains(at(9, 0)));
+    assert!(!quiet_hours.contains(at(17, 0)));
+    assert!(!quiet_hours.contains(at(3, 0)));
+
+    assert!("22:00".parse::<QuietHours>().is_err());
+    assert!("22-06".parse::<QuietHours>().is_err());
+}
+
+#[tokio::test]
+async fn trigger_before_waiting_is_not_lost() {
+    let trigger = Trigger::new();
+    trigger.trigger();
+    tokio::time::timeout(std::time::Duration::from_secs(1), trigger.triggered())
+        .await
+        .unwrap();
+
+    let not_triggered =
+        tokio::time::timeout(std::time::Duration::from_millis(20), trigger.triggered()).await;
+    assert!(not_triggered.is_err());
+}
@@ -0,0 +1,28 @@
+use downloader::error::DownloaderError;
+use downloader::settings::Settings;
+
+#[test]
+fn default_settings_are_valid() {
+    Settings::default().validate().unwrap();
+}
+
+#[test]
+fn splitting_needs_a_duration_or_a_size_limit() {
+    let without_limit = Settings {
+        split_by_duration: false,
+        split_max_part_size_mb: None,
+        ..Default::default()
+    };
+    let error = without_limit.validate().unwrap_err();
+    assert!(matches!(
+        error.downcast_ref::<DownloaderError>(),
+        Some(DownloaderError::Config(_))
+    ));
+
+    let by_size = Settings {
+        split_by_duration: false,
+        split_max_part_size_mb: Some(1024),
+        ..Default::default()
+    };
+    by_size.validate().unwrap();
+}
@@ -0,0 +1,40 @@
+use std::time::Duration;
+
+use downloader::shutdown::Shutdown;
+
+#[tokio::test]
+async fn requested_waits_for_the_request() {
+    let shutdown = Shutdown::new();
+    assert!(!shutdown.is_requested());
+    let waiting = shutdown.clone();
+    let handle = tokio::spawn(async move { waiting.requested().await });
+    tokio::time::sleep(Duration::from_millis(10)).await;
+    assert!(!handle.is_finished());
+
+    shutdown.request();
+    tokio::time::timeout(Duration::from_secs(1), handle)
+        .await
+        .unwrap()
+        .unwrap();
+    assert!(shutdown.is_requested());
+    // later calls return right away
+    tokio::time::timeout(Duration::from_secs(1), shutdown.requested())
+        .await
+        .unwrap();
+}
+
+#[tokio::test]
+async fn deadline_passes_only_after_a_request() {
+    let shutdown = Shutdown::new();
+    let deadline = shutdown.deadline_passed(Duration::from_millis(10));
+    let not_requested = tokio::time::timeout(Duration::from_millis(50), deadline).await;
+    assert!(not_requested.is_err());
+
+    shutdown.request();
+    tokio::time::timeout(
+        Duration::from_secs(1),
+        shutdown.deadline_passed(Duration::from_millis(10)),
+    )
+    .await
+    .unwrap();
+}
@@ -0,0 +1,64 @@
+use downloader::split_points::{
+    parse_scene_changes, parse_silences, pick_split_point, Silence, SplitPointMode,
+};
+
+#[test]
+fn split_point_modes_are_parsed() {
+    assert_eq!(SplitPointMode::Time, "time".parse().unwrap());
+    assert_eq!(SplitPointMode::Silence, " Silence ".parse().unwrap());
+    assert_eq!(SplitPointMode::Scene, "scene".parse().unwrap());
+    assert_eq!(SplitPointMode::Chapters, "chapters".parse().unwrap());
+    assert!("keyframe".parse::<SplitPointMode>().is_err());
+    assert_eq!(SplitPointMode::Time, SplitPointMode::default());
+}
+
+#[test]
+fn parse_silences_pairs_start_and_end() {
+    let stderr = "Input #0, mov,mp4,m4a,3gp,3g2,mj2, from 'video.mp4':\n\
+                  [silencedetect @ 0x5600] silence_start: 12.5\n\
+                  [silencedetect @ 0x5600] silence_end: 14.25 | silence_duration: 1.75\n\
+                  size=N/A time=00:01:00.00 bitrate=N/A speed= 500x\n\
+                  [silencedetect @ 0x5600] silence_start: 50\n\
+                  [silencedetect @ 0x5600] silence_end: 51 | silence_duration: 1\n\
+                  [silencedetect @ 0x5600] silence_start: 119.5\n";
+    assert_eq!(
+        vec![
+            Silence {
+                start: 12.5,
+                end: 14.25
+            },
+            Silence {
+                start: 50.0,
+                end: 51.0
+            },
+        ],
+        parse_silences(stderr)
+    );
+    assert!(parse_silences("").is_empty());
+}
+
+#[test]
+fn parse_scene_changes_reads_the_frame_times() {
+    let stderr = "[Parsed_showinfo_1 @ 0x5600] config in time_base: 1/90000, frame_rate: 60/1\n\
+                  [Parsed_showinfo_1 @ 0x5600] n:   0 pts: 1125000 pts_time:12.5    duration: 1500\n\
+                  [Parsed_showinfo_1 @ 0x5600] n:   1 pts: 5400000 pts_time:60      duration: 1500\n\
+                  frame=    2 fps=0.0 q=-0.0 Lsize=N/A time=00:01:00.00\n";
+    assert_eq!(vec![12.5, 60.0], parse_scene_changes(stderr));
+}
+
+#[test]
+fn pick_split_point_takes_the_closest_candidate_in_the_window() {
+    let candidates = [100.0, 3550.0, 3620.0, 3800.0];
+    assert_eq!(
+        3620.0,
+        pick_split_point(3600.0, 3480.0, 3720.0, &candidates)
+    );
+    // nothing in the window
+    assert_eq!(3600.0, pick_split_point(3600.0, 3480.0, 3720.0, &[100.0]));
+    // never after the latest cut, even without candidates
+    assert_eq!(3590.0, pick_split_point(3600.0, 3480.0, 3590.0, &[]));
+    assert_eq!(
+        3550.0,
+        pick_split_point(3600.0, 3480.0, 3590.0, &candidates)
+    );
+}
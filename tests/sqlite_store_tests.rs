@@ -0,0 +1,273 @@
+use std::path::PathBuf;
+
+use chrono::{TimeZone, Utc};
+
+use downloader::data::{
+    PartStatus, StreamerDestinations, Streamers, VideoMetadata, VideoParts, VideoStage,
+    VideoUploads, Videos,
+};
+use downloader::store::{SqliteStore, Store};
+
+fn get_sample_video(video_id: i64) -> Videos {
+    Videos {
+        video_id,
+        title: Some(format!("Test Video {}", video_id)),
+        description: Some("Test Description".to_string()),
+        user_login: Some("nopixelvods".to_string()),
+        created_at: Some(Utc.with_ymd_and_hms(2021, 1, 1, 0, 0, 0).unwrap()),
+        url: Some(format!("https://www.twitch.tv/videos/{}", video_id)),
+        duration: Some(3600),
+        ..Default::default()
+    }
+}
+
+fn get_sample_metadata(video_id: i64) -> VideoMetadata {
+    VideoMetadata {
+        video_id,
+        backed_up: Some(false),
+        ..Default::default()
+    }
+}
+
+fn prepare_db_path(name: &str) -> PathBuf {
+    let path = std::env::temp_dir().join(format!("downloader_sqlite_test_{}.sqlite", name));
+    if path.exists() {
+        std::fs::remove_file(&path).unwrap();
+    }
+    path
+}
+
+#[tokio::test]
+async fn migrations_create_schema_and_are_idempotent() {
+    let path = prepare_db_path("migrations");
+    let store = SqliteStore::open(&path).unwrap();
+    let version = store.schema_version().unwrap();
+    assert!(version > 0);
+    store.upsert_video(&get_sample_video(1)).await.unwrap();
+    drop(store);
+
+    let store = SqliteStore::open(&path).unwrap();
+    assert_eq!(version, store.schema_version().unwrap());
+    assert!(store.get_video(1).await.unwrap().is_some());
+    std::fs::remove_file(path).unwrap();
+}
+
+#[tokio::test]
+async fn video_round_trip() {
+    let store = SqliteStore::open_in_memory().unwrap();
+    assert!(store.get_video(1).await.unwrap().is_none());
+
+    let video = get_sample_video(1);
+    store.upsert_video(&video).await.unwrap();
+    let loaded = store.get_video(1).await.unwrap().unwrap();
+    assert_eq!(video.title, loaded.title);
+    assert_eq!(video.created_at, loaded.created_at);
+    assert_eq!(video.duration, loaded.duration);
+
+    let mut video = video;
+    video.title = Some("changed".to_string());
+    store.upsert_video(&video).await.unwrap();
+    let loaded = store.get_video(1).await.unwrap().unwrap();
+    assert_eq!(Some("changed".to_string()), loaded.title);
+}
+
+#[tokio::test]
+async fn not_downloaded_videos_skip_backed_up_and_errors() {
+    let store = SqliteStore::open_in_memory().unwrap();
+    for video_id in [3, 1, 2, 4] {
+        store
+            .upsert_video_metadata(&get_sample_metadata(video_id))
+            .await
+            .unwrap();
+    }
+    let mut backed_up = get_sample_metadata(2);
+    backed_up.backed_up = Some(true);
+    store.save_video_metadata(&backed_up).await.unwrap();
+    let mut failed = get_sample_metadata(4);
+    failed.error = Some("something went wrong".to_string());
+    store.save_video_metadata(&failed).await.unwrap();
+
+    let pending = store.get_not_downloaded_video_metadata().await.unwrap();
+    let ids: Vec<i64> = pending.iter().map(|m| m.video_id).collect();
+    assert_eq!(vec![1, 3], ids);
+}
+
+#[tokio::test]
+async fn not_downloaded_videos_include_transient_errors_that_are_due() {
+    let store = SqliteStore::open_in_memory().unwrap();
+    let retries = [
+        (
+            1,
+            Some(true),
+            Some(Utc::now() - chrono::Duration::minutes(1)),
+        ),
+        (2, Some(true), Some(Utc::now() + chrono::Duration::hours(1))),
+        (3, Some(false), None),
+        (4, None, None),
+    ];
+    for (video_id, error_transient, next_attempt_at) in retries {
+        let metadata = VideoMetadata {
+            error: Some("something went wrong".to_string()),
+            error_code: Some("download".to_string()),
+            retry_count: Some(1),
+            error_transient,
+            next_attempt_at,
+            ..get_sample_metadata(video_id)
+        };
+        store.upsert_video_metadata(&metadata).await.unwrap();
+    }
+    store
+        .upsert_video_metadata(&get_sample_metadata(5))
+        .await
+        .unwrap();
+
+    let pending = store.get_not_downloaded_video_metadata().await.unwrap();
+    let ids: Vec<i64> = pending.iter().map(|m| m.video_id).collect();
+    assert_eq!(vec![1, 5], ids);
+    assert_eq!(Some(1), pending[0].retry_count);
+    assert_eq!(Some("download".to_string()), pending[0].error_code);
+}
+
+#[tokio::test]
+async fn save_metadata_requires_existing_row() {
+    let store = SqliteStore::open_in_memory().unwrap();
+    let res = store.save_video_metadata(&get_sample_metadata(1)).await;
+    assert!(res.is_err());
+}
+
+#[tokio::test]
+async fn video_stage_is_saved() {
+    let store = SqliteStore::open_in_memory().unwrap();
+    let mut metadata = get_sample_metadata(1);
+    store.upsert_video_metadata(&metadata).await.unwrap();
+    assert_eq!(
+        VideoStage::Discovered,
+        store.get_video_metadata(1).await.unwrap().unwrap().stage()
+    );
+
+    metadata.set_stage(VideoStage::Split);
+    metadata.download_path = Some("/downloads/1/1.mp4".to_string());
+    store.save_video_metadata(&metadata).await.unwrap();
+    let loaded = store.get_video_metadata(1).await.unwrap().unwrap();
+    assert_eq!(VideoStage::Split, loaded.stage());
+    assert_eq!(metadata.download_path, loaded.download_path);
+    assert!(loaded.stage_updated_at.is_some());
+}
+
+#[tokio::test]
+async fn video_parts_are_ordered_by_destination_and_part() {
+    let store = SqliteStore::open_in_memory().unwrap();
+    for (destination, part) in [("youtube", 2), ("s3", 1), ("youtube", 1)] {
+        store
+            .upsert_video_part(&VideoParts {
+                remote_id: Some(format!("{}-{}", destination, part)),
+                uploaded_at: Some(Utc.with_ymd_and_hms(2021, 1, 1, 0, 0, 0).unwrap()),
+                file_hash: Some("abc".to_string()),
+                duration: Some(60),
+                ..VideoParts::new(1, destination, part)
+            })
+            .await
+            .unwrap();
+    }
+    assert!(store.get_video_parts(2).await.unwrap().is_empty());
+
+    let video_parts = store.get_video_parts(1).await.unwrap();
+    let remote_ids: Vec<String> = video_parts
+        .iter()
+        .map(|p| p.remote_id.clone().unwrap())
+        .collect();
+    assert_eq!(vec!["s3-1", "youtube-1", "youtube-2"], remote_ids);
+    assert_eq!(
+        Some(Utc.with_ymd_and_hms(2021, 1, 1, 0, 0, 0).unwrap()),
+        video_parts[0].uploaded_at
+    );
+    assert_eq!(Some("abc".to_string()), video_parts[0].file_hash);
+    assert_eq!(Some(60), video_parts[0].duration);
+    assert_eq!(PartStatus::Pending, video_parts[0].status());
+}
+
+#[tokio::test]
+async fn part_uploads_are_migrated_to_uploaded_video_parts() {
+    let path = prepare_db_path("part_uploads_migration");
+    {
+        let connection = rusqlite::Connection::open(&path).unwrap();
+        connection
+            .execute_batch(
+                "CREATE TABLE part_uploads (
+                    id TEXT PRIMARY KEY NOT NULL,
+                    video_id INTEGER,
+                    destination TEXT,
+                    part INTEGER,
+                    remote_id TEXT,
+                    url TEXT,
+                    uploaded_at TEXT
+                );
+                CREATE INDEX part_uploads_video_id ON part_uploads (video_id);
+                CREATE TABLE video_metadata (video_id INTEGER PRIMARY KEY NOT NULL);
+                INSERT INTO part_uploads (id, video_id, destination, part, remote_id)
+                    VALUES ('1_youtube_1', 1, 'youtube', 1, 'abc');
+                PRAGMA user_version = 4;",
+            )
+            .unwrap();
+    }
+
+    let store = SqliteStore::open(&path).unwrap();
+    let video_parts = store.get_video_parts(1).await.unwrap();
+    assert_eq!(1, video_parts.len());
+    assert_eq!(Some("abc".to_string()), video_parts[0].remote_id);
+    assert_eq!(PartStatus::Uploaded, video_parts[0].status());
+    drop(store);
+    std::fs::remove_file(path).unwrap();
+}
+
+#[tokio::test]
+async fn destinations_and_video_uploads_round_trip() {
+    let store = SqliteStore::open_in_memory().unwrap();
+    store
+        .upsert_streamer_destination(&StreamerDestinations::new("nopixelvods", "youtube", true))
+        .await
+        .unwrap();
+    store
+        .upsert_streamer_destination(&StreamerDestinations::new("nopixelvods", "s3", false))
+        .await
+        .unwrap();
+    let destinations = store
+        .get_streamer_destinations("nopixelvods")
+        .await
+        .unwrap();
+    assert_eq!(2, destinations.len());
+    assert_eq!(Some("s3".to_string()), destinations[0].destination);
+    assert_eq!(Some(false), destinations[0].required);
+    assert!(store
+        .get_streamer_destinations("someone_else")
+        .await
+        .unwrap()
+        .is_empty());
+
+    store
+        .upsert_streamer(&Streamers {
+            login: "nopixelvods".to_string(),
+            watched: Some(true),
+            ..Default::default()
+        })
+        .await
+        .unwrap();
+    assert!(store.delete_streamer("nopixelvods").await.unwrap());
+    assert!(!store.delete_streamer("nopixelvods").await.unwrap());
+    assert!(store.get_streamer("nopixelvods").await.unwrap().is_none());
+    assert!(store
+        .get_streamer_destinations("nopixelvods")
+        .await
+        .unwrap()
+        .is_empty());
+
+    let mut video_upload = VideoUploads::new(1, "youtube");
+    store.upsert_video_upload(&video_upload).await.unwrap();
+    video_upload.backed_up = Some(true);
+    video_upload.collection_url = Some("https://example.com/playlist".to_string());
+    store.upsert_video_upload(&video_upload).await.unwrap();
+    let video_uploads = store.get_video_uploads(1).await.unwrap();
+    assert_eq!(1, video_uploads.len());
+    assert_eq!(Some(true), video_uploads[0].backed_up);
+    assert_eq!(video_upload.collection_url, video_uploads[0].collection_url);
+}
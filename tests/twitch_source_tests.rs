@@ -0,0 +1,135 @@
+use std::sync::{Arc, Mutex};
+
+use tokio::io::{AsyncReadExt, AsyncWriteExt};
+use tokio::net::TcpListener;
+
+use downloader::chapters::Chapter;
+use downloader::source::twitch::{get_chapters, parse_chapters};
+
+const CHAPTERS_RESPONSE: &str = r#"{
+  "data": {
+    "video": {
+      "id": "123",
+      "moments": {
+        "edges": [
+          {
+            "node": {
+              "id": "b",
+              "positionMilliseconds": 5400500,
+              "durationMilliseconds": 1800000,
+              "type": "GAME_CHANGE",
+              "description": "Grand Theft Auto V",
+              "details": { "game": { "id": "32982", "displayName": "Grand Theft Auto V" } }
+            }
+          },
+          {
+            "node": {
+              "id": "a",
+              "positionMilliseconds": 0,
+              "durationMilliseconds": 5400500,
+              "type": "GAME_CHANGE",
+              "description": "Just Chatting",
+              "details": { "game": null }
+            }
+          }
+        ]
+      }
+    }
+  }
+}"#;
+
+#[test]
+fn parse_chapters_reads_the_game_changes() {
+    let chapters = parse_chapters(CHAPTERS_RESPONSE).unwrap();
+    assert_eq!(
+        vec![
+            Chapter {
+                start: 0.0,
+                title: "Just Chatting".to_string(),
+            },
+            Chapter {
+                start: 5400.5,
+                title: "Grand Theft Auto V".to_string(),
+            },
+        ],
+        chapters
+    );
+}
+
+#[test]
+fn parse_chapters_of_videos_without_a_game_change() {
+    let json = r#"{"data":{"video":{"id":"123","moments":{"edges":[]}}}}"#;
+    assert!(parse_chapters(json).unwrap().is_empty());
+    let json = r#"{"data":{"video":{"id":"123","moments":null}}}"#;
+    assert!(parse_chapters(json).unwrap().is_empty());
+}
+
+#[test]
+fn parse_chapters_fails_for_errors_and_unknown_videos() {
+    let json = r#"{"errors":[{"message":"PersistedQueryNotFound"}]}"#;
+    assert!(parse_chapters(json).is_err());
+    let json = r#"{"data":{"video":null}}"#;
+    assert!(parse_chapters(json).is_err());
+}
+
+#[tokio::test]
+async fn get_chapters_asks_for_the_video() {
+    let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let address = listener.local_addr().unwrap();
+    let received = Arc::new(Mutex::new(String::new()));
+    let recorded = received.clone();
+    tokio::spawn(async move {
+        let (mut socket, _) = listener.accept().await.unwrap();
+        let mut request = vec![];
+        let mut buffer = [0u8; 1024];
+        loop {
+            let read = socket.read(&mut buffer).await.unwrap();
+            if read == 0 {
+                break;
+            }
+            request.extend_from_slice(&buffer[..read]);
+            let text = String::from_utf8_lossy(&request).to_string();
+            if let Some((head, body)) = text.split_once("\r\n\r\n") {
+                let length = head
+                    .lines()
+                    .find_map(|l| {
+                        l.to_lowercase()
+                            .strip_prefix("content-length:")
+                            .map(|v| v.trim().to_string())
+                    })
+                    .and_then(|v| v.parse::<usize>().ok())
+                    .unwrap_or(0);
+                if body.len() >= length {
+                    *recorded.lock().unwrap() = text;
+                    break;
+                }
+            }
+        }
+        let header = format!(
+            "HTTP/1.1 200 OK\r\nContent-Length: {}\r\nConnection: close\r\n\r\n",
+            CHAPTERS_RESPONSE.len()
+        );
+        socket.write_all(header.as_bytes()).await.unwrap();
+        socket
+            .write_all(CHAPTERS_RESPONSE.as_bytes())
+            .await
+            .unwrap();
+        socket.shutdown().await.ok();
+    });
+
+    let chapters = get_chapters(
+        &reqwest::Client::new(),
+        &format!("http://{}/gql", address),
+        123,
+    )
+    .await
+    .unwrap();
+
+    assert_eq!(2, chapters.len());
+    let request = received.lock().unwrap().clone();
+    assert!(request.starts_with("POST /gql "));
+    assert!(request
+        .to_lowercase()
+        .contains("client-id: kimne78kx3ncx6brgo4mv6wki5h1ko"));
+    assert!(request.contains(r#""videoID":"123""#));
+}
@@ -0,0 +1,88 @@
+use std::time::Duration;
+
+use std::cell::RefCell;
+
+use downloader::error::DownloaderError;
+use downloader::workers::{fair_order, run_to_completion, StageLimits};
+
+#[test]
+fn fair_order_lets_the_groups_take_turns() {
+    let videos = vec![
+        ("a", 1),
+        ("a", 2),
+        ("a", 3),
+        ("b", 1),
+        ("a", 4),
+        ("c", 1),
+        ("b", 2),
+    ];
+    let ordered = fair_order(videos, |(streamer, _)| *streamer);
+    assert_eq!(
+        vec![
+            ("a", 1),
+            ("b", 1),
+            ("c", 1),
+            ("a", 2),
+            ("b", 2),
+            ("a", 3),
+            ("a", 4),
+        ],
+        ordered
+    );
+    assert!(fair_order(Vec::<i32>::new(), |i| *i).is_empty());
+}
+
+#[tokio::test]
+async fn stages_are_limited_separately() {
+    let limits = StageLimits::new(1, 2, 0);
+    let download = limits.download().await;
+    let second_download = tokio::time::timeout(Duration::from_millis(20), limits.download()).await;
+    assert!(second_download.is_err());
+
+    // other stages are not blocked by the download
+    let _split = limits.split().await;
+    let _second_split = limits.split().await;
+    let _upload = limits.upload().await;
+    let second_upload = tokio::time::timeout(Duration::from_millis(20), limits.upload()).await;
+    assert!(second_upload.is_err());
+
+    drop(download);
+    let _download = tokio::time::timeout(Duration::from_secs(1), limits.download())
+        .await
+        .unwrap();
+}
+
+#[tokio::test]
+async fn run_to_completion_finishes_every_run_after_a_failure() {
+    let finished = RefCell::new(vec![]);
+    let result = run_to_completion(vec![1, 2, 3, 4], 2, |i| {
+        let finished = &finished;
+        async move {
+            if i == 1 {
+                return Err(DownloaderError::Storage(anyhow::anyhow!("store is down")).into());
+            }
+            // still running when the first one fails
+            tokio::time::sleep(Duration::from_millis(20)).await;
+            finished.borrow_mut().push(i);
+            if i == 3 {
+                anyhow::bail!("upload failed");
+            }
+            Ok(())
+        }
+    })
+    .await;
+
+    let mut finished = finished.into_inner();
+    finished.sort();
+    assert_eq!(vec![2, 3, 4], finished);
+    let error = result.unwrap_err();
+    let message = format!("{:#}", error);
+    assert!(message.contains("2 of the runs failed"), "{}", message);
+    assert!(message.contains("upload failed"), "{}", message);
+    assert!(error
+        .chain()
+        .any(|cause| cause.downcast_ref::<DownloaderError>().is_some()));
+    assert!(run_to_completion(vec![1, 2], 0, |_| async { Ok(()) })
+        .await
+        .is_ok());
+}